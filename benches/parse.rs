@@ -0,0 +1,248 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    io::Write,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+use parse_joural_meta_nom::{
+    bib::BibIndex,
+    front_matter::FrontMatter,
+    grammar::DEFAULT_REGISTRY,
+    metadata::{parse_all, Only, WriteOptions},
+    r#abstract::r#abstract,
+    writer::{Cut, Escape, Truncate},
+};
+
+const ARTICLE: &str = r#"authors=given> Aurora Almeida de Miranda, family> Leão\par title=Euclides da Cunha atualizado no sertão da teledramaturgia\par first_page=15\par last_page=29\par abstract=O objeto deste artigo é a série \textit {Onde nascem os fortes} (TV Globo, 2018), escrita para exibição em canal aberto de televisão, em ano eleitoral e filmada no cariri paraibano. A partir do título e da ambiência, percebemos uma configuração que remete ao livro \textit {Os sertões} \citeyear {EcCUNHA1902sertoes}. Ademais, o território sertanejo revela-se como poderoso cronotopo \cite {EcBAKHTIN2003Estetica}, em forte simetria com a linha abissal da Sociologia das Ausências \cite {EcSANTOS2004Para}.\par keywords=Onde nascem os fortes. Euclides da Cunha. Sertão. Teledramaturgia. Narrativa.\par section=Dossiê História dos Sertões: espaços, sentidos e saberes\par number=5\par semester=1\par year=2022"#;
+
+const BIB: &str = r#"@book{EcCUNHA1902sertoes, author = {Cunha, E.}, title = {Os sertões}, year = {1902}}
+@book{EcBAKHTIN2003Estetica, author = {Bakhtin, M.}, title = {Estética da criação verbal}, year = {2003}}
+@book{EcSANTOS2004Para, author = {Santos, B. S.}, title = {Para uma sociologia das ausências}, year = {2004}}"#;
+
+// counts allocations, to check the paths meant to write without any
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    f();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+// the abstract of ARTICLE repeated to about 64 KiB, as in the text() and
+// paragraph() scans of a long dossier introduction
+fn large_abstract() -> String {
+    let start = ARTICLE.find("abstract=").unwrap() + "abstract=".len();
+    let end = start + ARTICLE[start..].find(r"\par").unwrap();
+    let paragraph = &ARTICLE[start..end];
+
+    let mut text = String::from("title=Apresentação\\par abstract=");
+    while text.len() < 64 * 1024 {
+        text.push_str(paragraph);
+        text.push(' ');
+    }
+    text.push_str("\\par");
+    text
+}
+
+// the abstract tokenizer the single-pass scanner replaced, as the baseline:
+// many0(alt((text, command))) with one part per fragment
+mod combinator {
+    use nom::{
+        branch::alt,
+        bytes::complete::{is_not, tag},
+        multi::many0,
+        IResult,
+    };
+    use parse_joural_meta_nom::{r#abstract::block, space::space};
+
+    // built as the old parts were, but never read
+    #[allow(dead_code)]
+    pub enum Part<'a> {
+        Text(&'a [u8]),
+        Command(&'a [u8], &'a [u8]),
+    }
+
+    fn command(input: &[u8]) -> IResult<&[u8], Part<'_>> {
+        let (input, _) = space(input)?;
+        let (input, _) = tag("\\")(input)?;
+        let (input, command) = alt((tag("textit"), tag("citeyear"), tag("cite")))(input)?;
+        let (input, _) = space(input)?;
+        let (input, arg) = block(input)?;
+
+        Ok((input, Part::Command(command, arg)))
+    }
+
+    fn text(input: &[u8]) -> IResult<&[u8], Part<'_>> {
+        let (input, text) = is_not(&b"\\"[..])(input)?;
+
+        Ok((input, Part::Text(text)))
+    }
+
+    pub fn r#abstract(input: &[u8]) -> IResult<&[u8], Vec<Part<'_>>> {
+        many0(alt((text, command)))(input)
+    }
+}
+
+// the byte-by-byte scan Escape used before memchr, as the baseline
+fn escape_bytes(mut write: impl Write, mut text: &[u8]) -> std::io::Result<()> {
+    while let Some(pos) = text
+        .iter()
+        .position(|c| matches!(c, b'"' | b'\\' | 0..=0x1f | 0x7f))
+    {
+        write.write_all(&text[..pos])?;
+        match text[pos] {
+            b'"' => write.write_all(b"\\\"")?,
+            b'\\' => write.write_all(b"\\\\")?,
+            b'\n' => write.write_all(b"\\n")?,
+            b'\r' => write.write_all(b"\\r")?,
+            b'\t' => write.write_all(b"\\t")?,
+            c => write!(write, "\\x{:02X}", c)?,
+        }
+        text = &text[pos + 1..];
+    }
+    write.write_all(text)
+}
+
+fn parse(c: &mut Criterion) {
+    let bib = nom_bibtex::Bibtex::parse(BIB).unwrap();
+    let bib = BibIndex::new(&bib);
+    let date = chrono::DateTime::parse_from_rfc3339("2022-06-01T00:00:00Z")
+        .unwrap()
+        .into();
+    let options = WriteOptions::default();
+
+    let large = large_abstract();
+    let (metadata, errors) = parse_all(large.as_bytes(), &DEFAULT_REGISTRY);
+    assert!(errors.is_empty() && metadata.r#abstract.is_some());
+    let mut group = c.benchmark_group("large abstract");
+    group.throughput(Throughput::Bytes(large.len() as u64));
+    group.bench_function("parse", |b| {
+        b.iter(|| parse_all(black_box(large.as_bytes()), &DEFAULT_REGISTRY))
+    });
+    group.bench_function("write", |b| {
+        let mut page = Vec::new();
+        b.iter(|| {
+            page.clear();
+            metadata.wtite_to(&mut page, &bib, date, &options).unwrap();
+        })
+    });
+    group.finish();
+
+    // the abstract alone, tokenized by the scanner and by the combinators
+    // it replaced; both stop at the closing \par
+    let text = &large[large.find("abstract=").unwrap() + "abstract=".len()..];
+    assert_eq!(r#abstract(text.as_bytes()).unwrap().0, b"\\par");
+    assert_eq!(combinator::r#abstract(text.as_bytes()).unwrap().0, b"\\par");
+    let mut group = c.benchmark_group("abstract tokenizer");
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    group.bench_function("scanner", |b| {
+        b.iter(|| r#abstract(black_box(text.as_bytes())).unwrap())
+    });
+    group.bench_function("many0", |b| {
+        b.iter(|| combinator::r#abstract(black_box(text.as_bytes())).unwrap())
+    });
+    group.finish();
+
+    // the front matter alone, streamed as it is written; without the
+    // description, whose abstract is rendered first, nothing is allocated
+    let (metadata, errors) = parse_all(ARTICLE.as_bytes(), &DEFAULT_REGISTRY);
+    assert!(errors.is_empty());
+    let front_matter = WriteOptions {
+        only: Some(Only::FrontMatter),
+        ..WriteOptions::default()
+    };
+    let no_description = WriteOptions {
+        disabled: vec!["description".to_owned()],
+        ..front_matter.clone()
+    };
+    let mut page = Vec::with_capacity(4096);
+    for format in [FrontMatter::Yaml, FrontMatter::Toml] {
+        let options = WriteOptions {
+            front_matter: format,
+            ..no_description.clone()
+        };
+        let count = allocations(|| {
+            page.clear();
+            metadata.wtite_to(&mut page, &bib, date, &options).unwrap();
+        });
+        assert_eq!(count, 0, "{:?} front matter", format);
+    }
+    c.bench_function("front matter", |b| {
+        let mut page = Vec::new();
+        b.iter(|| {
+            page.clear();
+            metadata
+                .wtite_to(&mut page, &bib, date, black_box(&front_matter))
+                .unwrap();
+        })
+    });
+
+    // the description once the abstract is rendered: cut and escaped
+    let count = allocations(|| {
+        page.clear();
+        let mut description = Truncate::new(140, Cut::Sentence);
+        description.write_all(text.as_bytes()).unwrap();
+        description.finish(&[], Escape(&mut page)).unwrap();
+    });
+    assert_eq!(count, 0, "description");
+
+    let text = large.replace("Globo", "\"Globo\"");
+    let mut group = c.benchmark_group("escape");
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    group.bench_function("memchr", |b| {
+        let mut out = Vec::new();
+        b.iter(|| {
+            out.clear();
+            Escape(&mut out)
+                .write_all(black_box(text.as_bytes()))
+                .unwrap();
+        })
+    });
+    group.bench_function("byte loop", |b| {
+        let mut out = Vec::new();
+        b.iter(|| {
+            out.clear();
+            escape_bytes(&mut out, black_box(text.as_bytes())).unwrap();
+        })
+    });
+    group.finish();
+
+    // one batch run over the issue: every article parsed and written
+    let issue: Vec<String> = (0..200)
+        .map(|i| ARTICLE.replace("first_page=15", &format!("first_page={}", i)))
+        .collect();
+    let mut group = c.benchmark_group("batch");
+    group.throughput(Throughput::Elements(issue.len() as u64));
+    group.bench_function("convert", |b| {
+        let mut page = Vec::new();
+        b.iter(|| {
+            for article in &issue {
+                let (metadata, _) = parse_all(black_box(article.as_bytes()), &DEFAULT_REGISTRY);
+                page.clear();
+                metadata.wtite_to(&mut page, &bib, date, &options).unwrap();
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
@@ -0,0 +1,4 @@
+abstract = Abstract
+keywords = Keywords
+no-date = n.d.
+references = References
@@ -0,0 +1,4 @@
+abstract = Resumen
+keywords = Palabras clave
+no-date = s.f.
+references = Referencias
@@ -0,0 +1,4 @@
+abstract = Resumo
+keywords = Palavras-chave
+no-date = s.d.
+references = Referências
@@ -1,231 +1,2233 @@
-use std::{collections::HashMap, io::Write, borrow::Cow};
+use std::{borrow::Cow, io::Write, ops::Range};
 
+use memchr::memmem;
 use nom::{
-    branch::alt,
     bytes::complete::{is_not, tag},
-    character::complete::char,
     error::ErrorKind,
-    multi::many0,
-    sequence::delimited,
     IResult,
 };
 use nom_bibtex::Bibliography;
+use serde::Deserialize;
 
-use crate::space::space;
+use crate::{
+    bib::{is_doi, BibIndex},
+    citation::{self, citation_item, group, render_reference, CitationStyle, Hover, Kind},
+    grammar::{longest_tag, COMMANDS},
+    latex,
+    limits::Limits,
+    locale::Language,
+    sentence,
+    space::space,
+    writer::Tidy,
+};
+
+pub trait CommandHandler: Send + Sync {
+    fn render(&self, write: &mut dyn Write, arg: &[u8], format: &Format) -> std::io::Result<()>;
+}
+
+pub struct CustomCommand {
+    pub name: String,
+    pub handler: Box<dyn CommandHandler>,
+}
+
+impl std::fmt::Debug for CustomCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CustomCommand({})", self.name)
+    }
+}
+
+impl PartialEq for CustomCommand {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+pub struct Replacement(pub String);
+
+impl CommandHandler for Replacement {
+    fn render(&self, write: &mut dyn Write, arg: &[u8], _: &Format) -> std::io::Result<()> {
+        let mut template = self.0.as_str();
+        while let Some(pos) = template.find("{}") {
+            write.write_all(&template.as_bytes()[..pos])?;
+            write.write_all(arg)?;
+            template = &template[pos + 2..];
+        }
+        write.write_all(template.as_bytes())
+    }
+}
+
+// commands whose argument is rich text itself, e.g. a citation inside
+// \textit{...}
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Style {
+    Textit,
+    Emph,
+    Textbf,
+    Quote,
+    Superscript,
+    Subscript,
+    SmallCaps,
+    Uppercase,
+}
+
+impl Style {
+    fn of(command: &str) -> Option<Style> {
+        match command {
+            "textit" => Some(Style::Textit),
+            "emph" => Some(Style::Emph),
+            "textbf" => Some(Style::Textbf),
+            "enquote" | "aspas" => Some(Style::Quote),
+            "textsuperscript" => Some(Style::Superscript),
+            "textsubscript" => Some(Style::Subscript),
+            "textsc" => Some(Style::SmallCaps),
+            "MakeUppercase" => Some(Style::Uppercase),
+            _ => None,
+        }
+    }
+
+    fn command(self) -> &'static str {
+        match self {
+            Style::Textit => "textit",
+            Style::Emph => "emph",
+            Style::Textbf => "textbf",
+            Style::Quote => "enquote",
+            Style::Superscript => "textsuperscript",
+            Style::Subscript => "textsubscript",
+            Style::SmallCaps => "textsc",
+            Style::Uppercase => "MakeUppercase",
+        }
+    }
+}
+
+// the name write_source gives a command read as `name`, e.g. cite for
+// citep
+pub(crate) fn canonical_command(name: &str) -> &str {
+    match name {
+        "citep" => "cite",
+        "citeonline" | "textcite" => "citet",
+        name => match Style::of(name) {
+            Some(style) => style.command(),
+            None => name,
+        },
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum AbstractPart<'a> {
+    Text(&'a [u8]),
+    // the argument as written and the parts it was read into
+    Styled(Style, &'a [u8], Vec<AbstractPart<'a>>),
+    Discard(&'a [u8]),
+    // an escaped character such as \&
+    Symbol(&'a [u8]),
+    // an accent macro such as \'e and the letter it stands for
+    Accent(&'a [u8], char),
+    Reference(&'a [u8]),
+    // the whole command and the image path
+    Image(&'a [u8], &'a [u8]),
+    // the whole command and the address
+    Url(&'a [u8], &'a [u8]),
+    // $...$ or $$...$$ as written and the TeX inside
+    Math(&'a [u8], &'a [u8]),
+    // the environment as written and the parts of its body
+    Quotation(&'a [u8], Vec<AbstractPart<'a>>),
+    // the keys and the optional argument, e.g. p. 42
+    Citeyear(&'a [u8], Option<&'a [u8]>),
+    Citeauthor(&'a [u8]),
+    Citetitle(&'a [u8]),
+    CiteTextual(&'a [u8], Option<&'a [u8]>),
+    Cite(&'a [u8], Option<&'a [u8]>),
+    Custom(&'a CustomCommand, &'a [u8]),
+}
+
+impl<'a> AbstractPart<'a> {
+    fn citation_key(&self) -> Option<&'a [u8]> {
+        match *self {
+            AbstractPart::Citeyear(key, _)
+            | AbstractPart::Citeauthor(key)
+            | AbstractPart::Citetitle(key)
+            | AbstractPart::CiteTextual(key, _)
+            | AbstractPart::Cite(key, _) => Some(key),
+            _ => None,
+        }
+    }
+
+    // the flat text of a part, as \textit{...} and friends used to be read
+    fn text(&self) -> Option<&'a [u8]> {
+        match *self {
+            AbstractPart::Text(text) | AbstractPart::Styled(_, text, _) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+fn citation_keys<'a>(parts: &[AbstractPart<'a>], keys: &mut Vec<&'a [u8]>) {
+    for part in parts {
+        match part {
+            AbstractPart::Styled(_, _, parts) | AbstractPart::Quotation(_, parts) => {
+                citation_keys(parts, keys)
+            }
+            part => keys.extend(part.citation_key().into_iter().flat_map(cite_keys)),
+        }
+    }
+}
+
+// the commands `select` picks, nested ones included, as written
+fn sources<'a>(
+    parts: &[AbstractPart<'a>],
+    found: &mut Vec<&'a [u8]>,
+    select: fn(&AbstractPart<'a>) -> Option<&'a [u8]>,
+) {
+    for part in parts {
+        match part {
+            AbstractPart::Styled(_, _, parts) | AbstractPart::Quotation(_, parts) => {
+                sources(parts, found, select)
+            }
+            part => found.extend(select(part)),
+        }
+    }
+}
+
+// \cite{KeyA, KeyB} groups several works
+fn cite_keys(arg: &[u8]) -> impl Iterator<Item = &[u8]> {
+    arg.split(|c| *c == b',').map(<[u8]>::trim_ascii)
+}
+
+pub struct Abstract<'a> {
+    parts: Vec<AbstractPart<'a>>,
+    // the LaTeX as written, unlike write_source
+    source: &'a [u8],
+}
+
+#[derive(Clone, Copy)]
+pub enum Format {
+    Markdown,
+    PlainText,
+}
+
+// some renderers do not close _x_ when it touches a word character
+#[derive(Debug, PartialEq, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Emphasis {
+    #[default]
+    Underscore,
+    Asterisk,
+}
+
+impl Emphasis {
+    fn delimiter(self) -> u8 {
+        match self {
+            Emphasis::Underscore => b'_',
+            Emphasis::Asterisk => b'*',
+        }
+    }
+
+    pub(crate) fn wrap(self, text: &str) -> String {
+        let delimiter = self.delimiter() as char;
+        format!("{}{}{}", delimiter, text, delimiter)
+    }
+}
+
+// how \textit, used for work titles (grifo), is rendered
+#[derive(Debug, PartialEq, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TitleStyle {
+    #[default]
+    Italic,
+    Bold,
+    Quoted,
+}
+
+// how $...$ is rendered; KaTeX pages get the TeX as written
+#[derive(Debug, PartialEq, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MathStyle {
+    #[default]
+    Text,
+    Katex,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderOptions<'o> {
+    pub style: CitationStyle,
+    pub language: Language,
+    pub emphasis: Emphasis,
+    pub title_style: TitleStyle,
+    // e.g. "/img/n5/"; images are dropped without one
+    pub image_prefix: Option<&'o str>,
+    // drops {} and protective groups such as {IBGE} from running text
+    pub strip_braces: bool,
+    // keeps ~, -- and --- as written instead of typographic characters
+    pub raw_typography: bool,
+    // markdown citations with their full reference, see citation::Hover
+    pub hover: Option<Hover>,
+    // ``these'' and `these' as typographic quotes
+    pub smart_quotes: bool,
+    pub math: MathStyle,
+    // between the works of \cite{a,b}, citation::SEPARATOR when unset
+    pub citation_separator: Option<&'o str>,
+    // markdown with inline HTML, e.g. <sup> or a small caps <span>, where
+    // markdown has no syntax
+    pub inline_html: bool,
+}
+
+impl RenderOptions<'_> {
+    fn citation(&self) -> citation::Style {
+        citation::Style {
+            citation: self.style,
+            emphasis: self.emphasis,
+            language: self.language,
+        }
+    }
+
+    // whether `style` is rendered as italics
+    fn italic(&self, style: Style) -> bool {
+        match style {
+            Style::Emph => true,
+            Style::Textit => self.title_style == TitleStyle::Italic,
+            _ => false,
+        }
+    }
+}
+
+// the methods taking `text` get it already rendered
+impl Format {
+    fn italic(
+        &self,
+        mut write: impl Write,
+        text: &[u8],
+        emphasis: Emphasis,
+    ) -> std::io::Result<()> {
+        match self {
+            // the text comes escaped, see Renderer::italic
+            Format::Markdown => {
+                let delimiter = emphasis.delimiter();
+                write.write_all(&[delimiter])?;
+                write.write_all(text)?;
+                write.write_all(&[delimiter])
+            }
+            Format::PlainText => write.write_all(text),
+        }
+    }
+
+    fn bold(&self, mut write: impl Write, text: &[u8]) -> std::io::Result<()> {
+        match self {
+            Format::Markdown => {
+                write.write_all(b"**")?;
+                write.write_all(text)?;
+                write.write_all(b"**")
+            }
+            Format::PlainText => write.write_all(text),
+        }
+    }
+
+    // ^x^ and ~x~ are an extension of some renderers, <sup> needs one that
+    // keeps inline HTML
+    fn script(
+        &self,
+        mut write: impl Write,
+        (mark, tag): (&[u8], &str),
+        text: &[u8],
+        inline_html: bool,
+    ) -> std::io::Result<()> {
+        match self {
+            Format::Markdown if inline_html => {
+                write!(write, "<{}>", tag)?;
+                write.write_all(text)?;
+                write!(write, "</{}>", tag)
+            }
+            Format::Markdown => {
+                write.write_all(mark)?;
+                write.write_all(text)?;
+                write.write_all(mark)
+            }
+            Format::PlainText => write.write_all(text),
+        }
+    }
+
+    fn small_caps(
+        &self,
+        mut write: impl Write,
+        text: &[u8],
+        inline_html: bool,
+    ) -> std::io::Result<()> {
+        match self {
+            Format::Markdown if inline_html => {
+                write.write_all(br#"<span style="font-variant: small-caps">"#)?;
+                write.write_all(text)?;
+                write.write_all(b"</span>")
+            }
+            Format::Markdown => write.write_all(text),
+            Format::PlainText => {
+                write.write_all(String::from_utf8_lossy(text).to_uppercase().as_bytes())
+            }
+        }
+    }
+
+    fn styled(
+        &self,
+        mut write: impl Write,
+        style: Style,
+        text: &[u8],
+        options: &RenderOptions,
+    ) -> std::io::Result<()> {
+        match style {
+            Style::Textit => match options.title_style {
+                TitleStyle::Italic => self.italic(write, text, options.emphasis),
+                TitleStyle::Bold => self.bold(write, text),
+                TitleStyle::Quoted => self.quote(write, text, options.language),
+            },
+            // emphasis is not a title, so title_style leaves it alone
+            Style::Emph => self.italic(write, text, options.emphasis),
+            Style::Textbf => self.bold(write, text),
+            Style::Quote => self.quote(write, text, options.language),
+            Style::Superscript => self.script(write, (b"^", "sup"), text, options.inline_html),
+            Style::Subscript => self.script(write, (b"~", "sub"), text, options.inline_html),
+            Style::SmallCaps => self.small_caps(write, text, options.inline_html),
+            Style::Uppercase => {
+                write.write_all(String::from_utf8_lossy(text).to_uppercase().as_bytes())
+            }
+        }
+    }
+
+    // a citation by DOI without a bibliography entry becomes a link
+    fn doi(&self, mut write: impl Write, doi: &[u8]) -> std::io::Result<()> {
+        match self {
+            Format::Markdown => {
+                write.write_all(b"[doi:")?;
+                write.write_all(doi)?;
+                write.write_all(b"](https://doi.org/")?;
+                for c in doi {
+                    match c {
+                        b'(' => write.write_all(b"%28")?,
+                        b')' => write.write_all(b"%29")?,
+                        c => write.write_all(&[*c])?,
+                    }
+                }
+                write.write_all(b")")
+            }
+            Format::PlainText => {
+                write.write_all(b"doi:")?;
+                write.write_all(doi)
+            }
+        }
+    }
+
+    fn quote(
+        &self,
+        mut write: impl Write,
+        text: &[u8],
+        language: Language,
+    ) -> std::io::Result<()> {
+        let (open, close) = language.quotes();
+        write.write_all(open.as_bytes())?;
+        write.write_all(text)?;
+        write.write_all(close.as_bytes())
+    }
+
+    // a paragraph of its own, without the break after it
+    fn quotation(&self, mut write: impl Write, text: &[u8]) -> std::io::Result<()> {
+        write.write_all(b"\n")?;
+        for line in text.trim_ascii().split(|c| *c == b'\n') {
+            let line = line.trim_ascii();
+            let prefix: &[u8] = match (self, line.is_empty()) {
+                (Format::Markdown, false) => b"\n> ",
+                (Format::Markdown, true) => b"\n>",
+                (Format::PlainText, false) => b"\n    ",
+                (Format::PlainText, true) => b"\n",
+            };
+            write.write_all(prefix)?;
+            write.write_all(line)?;
+        }
+        Ok(())
+    }
+}
+
+// command arguments may still hold escaped symbols such as \&
+fn write_text(mut write: impl Write, text: &[u8], options: &RenderOptions) -> std::io::Result<()> {
+    let text = latex::decode_with(text, !options.raw_typography);
+    match options.smart_quotes {
+        true => write.write_all(&latex::smart_quotes(&text)),
+        false => write.write_all(&text),
+    }
+}
+
+// unbalanced braces are kept, since they were not meant as groups
+fn unbrace(text: &[u8]) -> Cow<'_, [u8]> {
+    let mut open = Vec::new();
+    let mut matched = Vec::new();
+    for pos in memchr::memchr2_iter(b'{', b'}', text) {
+        match text[pos] {
+            b'{' => open.push(pos),
+            _ => {
+                if let Some(start) = open.pop() {
+                    matched.extend([start, pos]);
+                }
+            }
+        }
+    }
+    if matched.is_empty() {
+        return Cow::Borrowed(text);
+    }
+
+    matched.sort_unstable();
+    let mut output = Vec::with_capacity(text.len());
+    let mut start = 0;
+    for pos in matched {
+        output.extend_from_slice(&text[start..pos]);
+        start = pos + 1;
+    }
+    output.extend_from_slice(&text[start..]);
+
+    Cow::Owned(output)
+}
+
+fn entry<'b>(
+    bib: &BibIndex<'b>,
+    key: &[u8],
+) -> std::io::Result<&'b Bibliography> {
+    bib.get(key).ok_or_else(|| {
+        std::io::Error::other(format!(
+            "bibliography not found: {}",
+            String::from_utf8_lossy(key)
+        ))
+    })
+}
+
+// \citeauthor{k} \citeyear{k} is a single textual citation; the parts
+// taken by the year and its optional argument, if `parts` starts with it
+fn year_after<'a>(parts: &[AbstractPart<'a>], key: &[u8]) -> Option<(usize, Option<&'a [u8]>)> {
+    match *parts {
+        [AbstractPart::Citeyear(k, locator), ..] if k == key => Some((1, locator)),
+        [AbstractPart::Text(space), AbstractPart::Citeyear(k, locator), ..]
+            if k == key && space.iter().all(|c| matches!(c, b' ' | b'~')) =>
+        {
+            Some((2, locator))
+        }
+        _ => None,
+    }
+}
+
+fn running_text(parts: &[AbstractPart], text: &mut Vec<u8>) {
+    for part in parts {
+        match part {
+            AbstractPart::Text(t) => text.extend_from_slice(t),
+            AbstractPart::Symbol(source) => text.extend_from_slice(&source[1..]),
+            AbstractPart::Accent(_, letter) => {
+                text.extend_from_slice(letter.encode_utf8(&mut [0; 4]).as_bytes())
+            }
+            AbstractPart::Styled(_, _, parts) => running_text(parts, text),
+            AbstractPart::Quotation(_, parts) => {
+                text.push(b' ');
+                running_text(parts, text);
+                text.push(b' ');
+            }
+            AbstractPart::Discard(_) => {}
+            _ => text.push(b' '),
+        }
+    }
+}
+
+struct Renderer<'r> {
+    bib: &'r BibIndex<'r>,
+    format: Format,
+    options: RenderOptions<'r>,
+    // the delimiter of the markdown italics being rendered, if any
+    italic: Option<u8>,
+}
+
+impl Renderer<'_> {
+    // `arg` holds one key or several separated by commas; a single work
+    // is hovered as a whole, several each on their own
+    // the locator, e.g. p. 42, follows the last work
+    fn citation(
+        &self,
+        out: &mut dyn Write,
+        arg: &[u8],
+        kind: Kind,
+        locator: Option<&[u8]>,
+    ) -> std::io::Result<()> {
+        let style = self.options.citation();
+        let locator = locator.map(|locator| {
+            let locator = latex::decode_with(locator, !self.options.raw_typography);
+            String::from_utf8_lossy(&locator).into_owned()
+        });
+        let hover = match self.format {
+            Format::Markdown => self.options.hover,
+            Format::PlainText => None,
+        };
+        let works = cite_keys(arg)
+            .map(|key| Ok((key, entry(self.bib, key)?)))
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        let items: Vec<_> = works
+            .iter()
+            .enumerate()
+            .map(|(i, (key, entry))| {
+                let locator = locator.as_deref().filter(|_| i + 1 == works.len());
+                let item = citation_item(entry, &style, kind, locator);
+                match hover.filter(|_| works.len() > 1) {
+                    Some(hover) => hover.wrap(key, &render_reference(entry, None), &item),
+                    None => item,
+                }
+            })
+            .collect();
+        let separator = self.options.citation_separator.unwrap_or(citation::SEPARATOR);
+        let citation = group(kind, &items, separator);
+
+        match (hover, works.as_slice()) {
+            (Some(hover), [(key, entry)]) => {
+                let reference = render_reference(entry, None);
+                out.write_all(hover.wrap(key, &reference, &citation).as_bytes())
+            }
+            _ => out.write_all(citation.as_bytes()),
+        }
+    }
+
+    // whether any of `parts` is a citation
+    fn parts(&self, parts: &[AbstractPart], out: &mut dyn Write) -> std::io::Result<bool> {
+        let mut cited = false;
+        let mut i = 0;
+        while i < parts.len() {
+            let (taken, citation) = self.part(&parts[i..], out)?;
+            cited |= citation;
+            i += taken;
+        }
+        Ok(cited)
+    }
+
+    // renders the first of `parts`, returning how many parts it took and
+    // whether it was or held a citation
+    fn part(&self, parts: &[AbstractPart], out: &mut dyn Write) -> std::io::Result<(usize, bool)> {
+        let (format, options, bib) = (&self.format, &self.options, self.bib);
+        let part = &parts[0];
+        let cited = part.citation_key().is_some();
+
+        let doi = part.citation_key().filter(|key| is_doi(key) && bib.get(key).is_none());
+        if let Some(doi) = doi {
+            let parenthetical = matches!(part, AbstractPart::Cite(..));
+            if parenthetical {
+                out.write_all(b"(")?;
+            }
+            format.doi(&mut *out, doi)?;
+            if parenthetical {
+                out.write_all(b")")?;
+            }
+            return Ok((1, true));
+        }
+
+        match *part {
+            AbstractPart::Styled(style, _, ref inner) => {
+                let italic = options.italic(style) && matches!(format, Format::Markdown);
+                // italics inside italics would close the outer ones early
+                if italic && self.italic.is_some() {
+                    return Ok((1, self.parts(inner, out)?));
+                }
+
+                // styled as a whole, so a citation inside stays with it
+                let mut text = Vec::new();
+                let cited = match italic {
+                    true => Renderer {
+                        italic: Some(options.emphasis.delimiter()),
+                        ..*self
+                    }
+                    .parts(inner, &mut text)?,
+                    false => self.parts(inner, &mut text)?,
+                };
+                format.styled(out, style, &text, options)?;
+                return Ok((1, cited));
+            }
+            AbstractPart::Quotation(_, ref inner) => {
+                let mut text = Vec::new();
+                let cited = self.parts(inner, &mut text)?;
+                format.quotation(&mut *out, &text)?;
+
+                // the text after it, if any, is a new paragraph
+                let rest = match parts.get(1) {
+                    Some(AbstractPart::Text(text)) => Some(text.trim_ascii_start()),
+                    _ => None,
+                };
+                let taken = 1 + rest.is_some() as usize;
+                if parts.len() > taken || rest.is_some_and(|rest| !rest.is_empty()) {
+                    out.write_all(b"\n\n")?;
+                }
+                if let Some(rest) = rest {
+                    // which may end before another quotation
+                    let rest = match parts.get(2) {
+                        Some(AbstractPart::Quotation(..)) => rest.trim_ascii_end(),
+                        _ => rest,
+                    };
+                    self.part(&[AbstractPart::Text(rest)], out)?;
+                }
+                return Ok((taken, cited));
+            }
+            AbstractPart::Text(text) => {
+                // a quotation after it starts a paragraph of its own
+                let text = match parts.get(1) {
+                    Some(AbstractPart::Quotation(..)) => text.trim_ascii_end(),
+                    _ => text,
+                };
+                let text = match options.strip_braces {
+                    true => unbrace(text),
+                    false => Cow::Borrowed(text),
+                };
+                match self.italic {
+                    // a delimiter in the text would close the italics early
+                    Some(delimiter) => {
+                        let mut rendered = Vec::new();
+                        write_text(&mut rendered, &text, options)?;
+                        for (i, chunk) in rendered.split(|c| *c == delimiter).enumerate() {
+                            if i > 0 {
+                                out.write_all(&[b'\\', delimiter])?;
+                            }
+                            out.write_all(chunk)?;
+                        }
+                    }
+                    None => write_text(out, &text, options)?,
+                }
+            }
+            AbstractPart::Discard(_) => {}
+            // markdown takes the same backslash escapes
+            AbstractPart::Symbol(source) => match format {
+                Format::Markdown => out.write_all(source)?,
+                Format::PlainText => out.write_all(&source[1..])?,
+            },
+            AbstractPart::Accent(_, letter) => write!(out, "{}", letter)?,
+            // reported when parsing, see Abstract::references
+            AbstractPart::Reference(_) => {}
+            // dropped without a prefix, see Metadata::write_warnings
+            AbstractPart::Image(_, path) => {
+                if let (Format::Markdown, Some(prefix)) = (format, options.image_prefix) {
+                    out.write_all(b"![](")?;
+                    out.write_all(prefix.as_bytes())?;
+                    out.write_all(path)?;
+                    out.write_all(b")")?;
+                }
+            }
+            AbstractPart::Url(_, url) => match format {
+                Format::Markdown => {
+                    out.write_all(b"<")?;
+                    out.write_all(url.trim_ascii())?;
+                    out.write_all(b">")?;
+                }
+                Format::PlainText => out.write_all(url.trim_ascii())?,
+            },
+            AbstractPart::Math(source, tex) => match (format, options.math) {
+                (Format::Markdown, MathStyle::Katex) => out.write_all(source)?,
+                _ => out.write_all(latex::math_text(tex).as_bytes())?,
+            },
+            AbstractPart::Custom(command, arg) => {
+                command.handler.render(out, arg, format)?;
+            }
+            AbstractPart::Citeauthor(key) => match year_after(&parts[1..], key) {
+                Some((taken, locator)) => {
+                    self.citation(out, key, Kind::Textual, locator)?;
+                    return Ok((1 + taken, true));
+                }
+                None => self.citation(out, key, Kind::Author, None)?,
+            },
+            AbstractPart::Citeyear(key, locator) => {
+                self.citation(out, key, Kind::Year, locator)?;
+            }
+            AbstractPart::Citetitle(key) => self.citation(out, key, Kind::Title, None)?,
+            AbstractPart::CiteTextual(key, locator) => {
+                self.citation(out, key, Kind::Textual, locator)?;
+            }
+            AbstractPart::Cite(key, locator) => {
+                self.citation(out, key, Kind::Parenthetical, locator)?;
+            }
+        }
+
+        Ok((1, cited))
+    }
+}
+
+fn write_source(parts: &[AbstractPart], write: &mut dyn Write) -> std::io::Result<()> {
+    for part in parts {
+        let (command, arg) = match *part {
+            AbstractPart::Text(text)
+            | AbstractPart::Discard(text)
+            | AbstractPart::Symbol(text)
+            | AbstractPart::Accent(text, _)
+            | AbstractPart::Reference(text)
+            | AbstractPart::Image(text, _)
+            | AbstractPart::Url(text, _)
+            | AbstractPart::Math(text, _)
+            | AbstractPart::Quotation(text, _) => {
+                write.write_all(text)?;
+                continue;
+            }
+            // the argument as written, nested commands included
+            AbstractPart::Styled(style, ..) => {
+                write!(write, "\\{}{{", style.command())?;
+                write.write_all(part.text().unwrap_or_default())?;
+                write.write_all(b"}")?;
+                continue;
+            }
+            AbstractPart::Citeyear(arg, _) => ("citeyear", arg),
+            AbstractPart::Citeauthor(arg) => ("citeauthor", arg),
+            AbstractPart::Citetitle(arg) => ("citetitle", arg),
+            AbstractPart::CiteTextual(arg, _) => ("citet", arg),
+            AbstractPart::Cite(arg, _) => ("cite", arg),
+            AbstractPart::Custom(command, arg) => (command.name.as_str(), arg),
+        };
+        write.write_all(b"\\")?;
+        write.write_all(command.as_bytes())?;
+        if let AbstractPart::Citeyear(_, Some(locator))
+        | AbstractPart::CiteTextual(_, Some(locator))
+        | AbstractPart::Cite(_, Some(locator)) = part
+        {
+            write.write_all(b"[")?;
+            write.write_all(locator)?;
+            write.write_all(b"]")?;
+        }
+        write.write_all(b"{")?;
+        write.write_all(arg)?;
+        write.write_all(b"}")?;
+    }
+
+    Ok(())
+}
+
+impl<'a> Abstract<'a> {
+    pub fn source(&self) -> &'a [u8] {
+        self.source
+    }
+
+    // whether the page needs a math renderer
+    pub fn has_math(&self) -> bool {
+        fn math(parts: &[AbstractPart]) -> bool {
+            parts.iter().any(|part| match part {
+                AbstractPart::Math(..) => true,
+                AbstractPart::Styled(_, _, parts) | AbstractPart::Quotation(_, parts) => {
+                    math(parts)
+                }
+                _ => false,
+            })
+        }
+        math(&self.parts)
+    }
+
+    // \ref and \autoref as written, dropped from every rendering since
+    // figures and tables do not exist outside the pdf
+    pub fn references(&self) -> Vec<&'a [u8]> {
+        let mut found = Vec::new();
+        sources(&self.parts, &mut found, |part| match *part {
+            AbstractPart::Reference(source) => Some(source),
+            _ => None,
+        });
+        found
+    }
+
+    // \includegraphics as written, dropped without an image prefix
+    pub fn images(&self) -> Vec<&'a [u8]> {
+        let mut found = Vec::new();
+        sources(&self.parts, &mut found, |part| match *part {
+            AbstractPart::Image(source, _) => Some(source),
+            _ => None,
+        });
+        found
+    }
+
+    pub fn citation_keys(&self) -> impl Iterator<Item = &'a [u8]> + '_ {
+        let mut keys = Vec::new();
+        citation_keys(&self.parts, &mut keys);
+        keys.into_iter()
+    }
+
+    /// The abstract as flat text, as it was read before formatting commands
+    /// held parts of their own: the arguments of `\textit` and friends
+    /// inline, escaped and accented characters as what they stand for, and
+    /// a space for every citation, reference, image, formula or other
+    /// command. Borrowed from the input when the abstract is plain text.
+    pub fn text(&self) -> Cow<'a, [u8]> {
+        match self.parts[..] {
+            [] => Cow::Borrowed(&[]),
+            [AbstractPart::Text(text)] => Cow::Borrowed(text),
+            _ => {
+                let mut text = Vec::new();
+                running_text(&self.parts, &mut text);
+                Cow::Owned(text)
+            }
+        }
+    }
+
+    // words of running text; citations, references and images do not count
+    pub fn words(&self) -> usize {
+        String::from_utf8_lossy(&self.text())
+            .split(|c: char| c.is_whitespace() || c == '~')
+            .filter(|word| word.chars().any(char::is_alphanumeric))
+            .count()
+    }
+
+    pub fn sentences(
+        &self,
+        bib: &BibIndex,
+        options: RenderOptions,
+    ) -> std::io::Result<Vec<String>> {
+        let mut text = Vec::new();
+        self.write_with_citations(&mut text, bib, Format::PlainText, options)?;
+        let text = String::from_utf8_lossy(&text);
+
+        Ok(sentence::sentences(&text).map(str::to_owned).collect())
+    }
+
+    pub fn write_source(&self, mut write: impl Write) -> std::io::Result<()> {
+        write_source(&self.parts, &mut write)
+    }
+
+    /// An owned copy, with the spans of its parts in `input`, the text the
+    /// abstract was parsed from.
+    pub fn to_owned_in(&self, input: &[u8]) -> OwnedAbstract {
+        OwnedAbstract {
+            parts: owned_parts(&self.parts, input),
+            span: span(input, self.source),
+        }
+    }
+
+    pub fn write_to(
+        &self,
+        write: impl Write,
+        bib: &BibIndex,
+        format: Format,
+    ) -> std::io::Result<()> {
+        self.write_with_citations(write, bib, format, RenderOptions::default())?;
+        Ok(())
+    }
+
+    // also returns where each rendered citation lies in the output; a
+    // styled group holding a citation counts as one
+    pub fn write_with_citations(
+        &self,
+        write: impl Write,
+        bib: &BibIndex,
+        format: Format,
+        options: RenderOptions,
+    ) -> std::io::Result<Vec<Range<usize>>> {
+        let mut write = Tidy::new(write);
+        let mut citations = Vec::new();
+        let renderer = Renderer {
+            bib,
+            format,
+            options,
+            italic: None,
+        };
+
+        let mut i = 0;
+        while i < self.parts.len() {
+            let start = write.position();
+            let (taken, cited) = renderer.part(&self.parts[i..], &mut write)?;
+            if cited {
+                citations.push(start..write.position());
+            }
+            i += taken;
+        }
+
+        write.finish()?;
+        Ok(citations)
+    }
+}
+
+/// An abstract that does not borrow its input, for passes that rewrite
+/// the content; each part remembers where in the input it was read from,
+/// so that diagnostics can still point there afterwards.
+#[derive(Debug, PartialEq, Clone)]
+pub struct OwnedAbstract {
+    pub parts: Vec<OwnedPart>,
+    /// Of the whole abstract in the input.
+    pub span: Option<Range<usize>>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct OwnedPart {
+    /// The command `source` is the argument of, e.g. `emph` or `cite`;
+    /// `None` when `source` is running text or a whole command.
+    pub command: Option<String>,
+    pub source: Vec<u8>,
+    /// Of `source` in the input; `None` for parts a pass made up.
+    pub span: Option<Range<usize>>,
+    /// The parts of a formatting command or a quotation.
+    pub parts: Vec<OwnedPart>,
+}
+
+// of `part` in `input`, if it was read from there
+fn span(input: &[u8], part: &[u8]) -> Option<Range<usize>> {
+    let start = (part.as_ptr() as usize).checked_sub(input.as_ptr() as usize)?;
+    (start + part.len() <= input.len()).then(|| start..start + part.len())
+}
+
+fn owned_parts(parts: &[AbstractPart], input: &[u8]) -> Vec<OwnedPart> {
+    parts
+        .iter()
+        .map(|part| {
+            let (command, source, parts) = match part {
+                AbstractPart::Styled(style, arg, parts) => {
+                    (Some(style.command()), *arg, &parts[..])
+                }
+                AbstractPart::Quotation(source, parts) => (None, *source, &parts[..]),
+                AbstractPart::Text(source)
+                | AbstractPart::Discard(source)
+                | AbstractPart::Symbol(source)
+                | AbstractPart::Accent(source, _)
+                | AbstractPart::Reference(source)
+                | AbstractPart::Image(source, _)
+                | AbstractPart::Url(source, _)
+                | AbstractPart::Math(source, _) => (None, *source, &[][..]),
+                AbstractPart::Citeyear(arg, _) => (Some("citeyear"), *arg, &[][..]),
+                AbstractPart::Citeauthor(arg) => (Some("citeauthor"), *arg, &[][..]),
+                AbstractPart::Citetitle(arg) => (Some("citetitle"), *arg, &[][..]),
+                AbstractPart::CiteTextual(arg, _) => (Some("citet"), *arg, &[][..]),
+                AbstractPart::Cite(arg, _) => (Some("cite"), *arg, &[][..]),
+                AbstractPart::Custom(command, arg) => {
+                    (Some(command.name.as_str()), *arg, &[][..])
+                }
+            };
+            OwnedPart {
+                command: command.map(str::to_owned),
+                source: source.to_vec(),
+                span: span(input, source),
+                parts: owned_parts(parts, input),
+            }
+        })
+        .collect()
+}
+
+/// A command argument: the inside of a `{...}` group, which may hold
+/// nested groups and escaped braces, or a single word as in `\textit x`.
+pub fn block(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    if input.first() != Some(&b'{') {
+        return is_not(&b" \t\r\n"[..])(input);
+    }
+
+    let mut depth = 0;
+    let mut i = 0;
+    while i < input.len() {
+        match input[i] {
+            b'\\' => i += 1,
+            b'{' => depth += 1,
+            b'}' if depth == 1 => return Ok((&input[i + 1..], &input[1..i])),
+            b'}' => depth -= 1,
+            _ => {}
+        }
+        i += 1;
+    }
+
+    Err(nom::Err::Error(nom::error::Error::new(input, ErrorKind::Char)))
+}
+
+// a bracketed optional argument as in \cite[p. 42]{key}; an empty one is
+// the same as none
+fn optional(input: &[u8]) -> (&[u8], Option<&[u8]>) {
+    let end = match input.first() {
+        Some(b'[') => memchr::memchr(b']', input),
+        _ => None,
+    };
+    match end {
+        Some(end) => {
+            let argument = input[1..end].trim_ascii();
+            (&input[end + 1..], Some(argument).filter(|arg| !arg.is_empty()))
+        }
+        None => (input, None),
+    }
+}
+
+fn command<'a>(
+    input: &'a [u8],
+    custom: &'a [CustomCommand],
+    limits: &Limits,
+    depth: usize,
+    count: &mut usize,
+) -> IResult<&'a [u8], AbstractPart<'a>> {
+    let (input, _) = space(input)?;
+    let original_input = input;
+    if latex::is_symbol(input) {
+        return Ok((&input[2..], AbstractPart::Symbol(&input[..2])));
+    }
+    if let Some((letter, len)) = latex::accent(input) {
+        return Ok((&input[len..], AbstractPart::Accent(&input[..len], letter)));
+    }
+    let (input, _) = tag("\\")(input)?;
+
+    let names = COMMANDS
+        .iter()
+        .map(|command| command.name)
+        .chain(custom.iter().map(|command| command.name.as_str()));
+    let (input, command) = longest_tag(input, names)?;
+
+    if command == "-" {
+        return Ok((input, AbstractPart::Discard(&original_input[..2])));
+    }
+
+    let (input, optional) = match command {
+        "hspace" => (input.strip_prefix(b"*").unwrap_or(input), None),
+        // options such as [width=5cm] make no sense outside the pdf
+        "includegraphics" => (optional(input).0, None),
+        "cite" | "citep" | "citeyear" | "citet" | "citeonline" | "textcite" => {
+            optional(space(input)?.0)
+        }
+        _ => (input, None),
+    };
+
+    let (input, _) = space(input)?;
+
+    let (input, arg) = block(input)?;
+
+    let source = &original_input[..original_input.len() - input.len()];
+
+    // custom commands may override the built-in ones (e.g. a text for \ref)
+    if let Some(command) = custom.iter().find(|custom| custom.name == command) {
+        return Ok((input, AbstractPart::Custom(command, arg)));
+    }
+
+    if let Some(style) = Style::of(command) {
+        let parts = nested(arg, custom, limits, depth, count)?;
+        return Ok((input, AbstractPart::Styled(style, arg, parts)));
+    }
+
+    if command == "begin" {
+        return quotation(input, original_input, arg, custom, limits, depth, count);
+    }
+
+    let part = match command {
+        "hspace" => AbstractPart::Discard(source),
+        "ref" | "autoref" => AbstractPart::Reference(source),
+        "includegraphics" => AbstractPart::Image(source, arg),
+        "url" => AbstractPart::Url(source, arg),
+        "citeyear" => AbstractPart::Citeyear(arg, optional),
+        "citeauthor" => AbstractPart::Citeauthor(arg),
+        "citetitle" => AbstractPart::Citetitle(arg),
+        // \citeonline from abnTeX2 and \textcite from biblatex
+        "citet" | "citeonline" | "textcite" => AbstractPart::CiteTextual(arg, optional),
+        "cite" | "citep" => AbstractPart::Cite(arg, optional),
+        _ => {
+            return Err(nom::Err::Error(nom::error::Error::new(
+                original_input,
+                ErrorKind::Satisfy,
+            )))
+        }
+    };
+
+    Ok((input, part))
+}
+
+// the parts of a command argument or environment body
+fn nested<'a>(
+    input: &'a [u8],
+    custom: &'a [CustomCommand],
+    limits: &Limits,
+    depth: usize,
+    count: &mut usize,
+) -> Result<Vec<AbstractPart<'a>>, nom::Err<nom::error::Error<&'a [u8]>>> {
+    if depth >= limits.depth() {
+        return Ok(vec![AbstractPart::Text(input)]);
+    }
+    // what the parser cannot read stays literal, as it used to
+    let (rest, mut parts) = rich_text(input, custom, limits, depth + 1, count)?;
+    if !rest.is_empty() {
+        parts.push(AbstractPart::Text(rest));
+    }
+    Ok(parts)
+}
+
+// the body of \begin{citacao}, abnTeX2's long quote, or of LaTeX's quote
+// and quotation; the language citacao takes, as in [english], is dropped
+fn quotation<'a>(
+    input: &'a [u8],
+    original_input: &'a [u8],
+    name: &[u8],
+    custom: &'a [CustomCommand],
+    limits: &Limits,
+    depth: usize,
+    count: &mut usize,
+) -> IResult<&'a [u8], AbstractPart<'a>> {
+    let end = [b"\\end{", name, b"}"].concat();
+    let body = match name {
+        b"citacao" | b"quote" | b"quotation" => memmem::find(input, &end),
+        _ => None,
+    };
+    let Some(body) = body else {
+        // other environments end the abstract as unknown commands do
+        return Err(nom::Err::Error(nom::error::Error::new(
+            original_input,
+            ErrorKind::Satisfy,
+        )));
+    };
+
+    let (input, body) = (&input[body + end.len()..], &input[..body]);
+    let body = match name {
+        b"citacao" => optional(body).0,
+        _ => body,
+    };
+    let source = &original_input[..original_input.len() - input.len()];
+    let parts = nested(body, custom, limits, depth, count)?;
+    Ok((input, AbstractPart::Quotation(source, parts)))
+}
+
+// $x$ or $$x$$ as pandoc reads them: the opening $ is not followed by a
+// space and the closing one neither follows a space nor precedes a digit
+fn math(input: &[u8]) -> Option<(&[u8], AbstractPart<'_>)> {
+    let delimiter: &[u8] = match input.starts_with(b"$$") {
+        true => b"$$",
+        false => b"$",
+    };
+    let body = &input[delimiter.len()..];
+    if body.first().is_none_or(u8::is_ascii_whitespace) {
+        return None;
+    }
+
+    let mut i = 0;
+    while i < body.len() {
+        match body[i] {
+            b'\\' => i += 2,
+            b'$' if i > 0 && body[i..].starts_with(delimiter) => {
+                let after = body.get(i + delimiter.len());
+                if body[i - 1].is_ascii_whitespace() || after.is_some_and(u8::is_ascii_digit) {
+                    return None;
+                }
+                let len = delimiter.len() * 2 + i;
+                return Some((&input[len..], AbstractPart::Math(&input[..len], &body[..i])));
+            }
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+// `count` is the number of parts read so far, nested ones included
+fn rich_text<'a>(
+    mut input: &'a [u8],
+    custom: &'a [CustomCommand],
+    limits: &Limits,
+    depth: usize,
+    count: &mut usize,
+) -> IResult<&'a [u8], Vec<AbstractPart<'a>>> {
+    // every command or $ splits the text around it, so this is an upper
+    // bound for the parts of this level, within the limits
+    let splits = memchr::memchr2_iter(b'\\', b'$', input).count();
+    let capacity = (2 * splits + 1).min(limits.max_parts.unwrap_or(usize::MAX));
+    let mut parts = Vec::with_capacity(capacity);
+    loop {
+        limits.check(input, *count)?;
+        let part = match memchr::memchr2(b'\\', b'$', input) {
+            Some(0) if input[0] == b'$' => match math(input) {
+                Some((inp, part)) => {
+                    input = inp;
+                    part
+                }
+                // a lone $, as in R$ 100
+                None => {
+                    let (text, inp) = input.split_at(1);
+                    input = inp;
+                    AbstractPart::Text(text)
+                }
+            },
+            Some(0) => match command(input, custom, limits, depth, count) {
+                Ok((inp, part)) => {
+                    input = inp;
+                    part
+                }
+                Err(nom::Err::Failure(err)) => return Err(nom::Err::Failure(err)),
+                // unknown commands (e.g. \par) end the abstract
+                Err(_) => break,
+            },
+            Some(pos) => {
+                let text = &input[..pos];
+                input = &input[pos..];
+                AbstractPart::Text(text)
+            }
+            None => {
+                if !input.is_empty() {
+                    parts.push(AbstractPart::Text(input));
+                    *count += 1;
+                }
+                input = &input[input.len()..];
+                break;
+            }
+        };
+        parts.push(part);
+        *count += 1;
+    }
+
+    Ok((input, parts))
+}
+
+pub fn abstract_with<'a>(
+    input: &'a [u8],
+    custom: &'a [CustomCommand],
+    limits: &Limits,
+) -> IResult<&'a [u8], Abstract<'a>> {
+    let (rest, parts) = rich_text(input, custom, limits, 0, &mut 0)?;
+    let source = &input[..input.len() - rest.len()];
+    Ok((rest, Abstract { parts, source }))
+}
+
+// built-in commands only and no limits
+pub fn r#abstract(input: &[u8]) -> IResult<&[u8], Abstract<'_>> {
+    abstract_with(input, &[], &Limits::NONE)
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::borrow::{Borrow, Cow};
+
+    use nom_bibtex::Bibtex;
+
+    use super::*;
+
+    #[test]
+    fn simple_abstract() {
+        let input = r#"O objeto deste artigo é a série \textit {Onde nascem os fortes} (TV Globo, 2018), escrita para exibição em canal aberto de televisão, em ano eleitoral e filmada no cariri paraibano. A partir do título e da ambiência, percebemos uma configuração que remete ao livro \textit {Os sertões} \citeyear {EcCUNHA1902sertoes}. Objetiva-se perscrutar como o conceito de sertão é trabalhado na obra, identificar a dialogia com o livro euclidiano e investigar o modo como as desigualdades sociais detectadas pelo escritor no início do século XX permanecem neste século XXI com impressionante atualidade. Ademais, o território sertanejo revela-se como poderoso cronotopo \cite {EcBAKHTIN2003Estetica}, em forte simetria com a linha abissal da Sociologia das Ausências \cite {EcSANTOS2004Para}. Elege-se o capítulo de estreia como evidenciador de pontos fundamentais da diegese, a partir de metodologia baseada na técnica da minutagem, através da qual analisa-se as estratégias de construção narrativa \cite {EcMOTTA2013analise}, bem como os procedimentos de elaboração do roteiro \cite {EcMACIEL2017poder}. Conclui-se que o episódio inaugural figura como síntese importante para o desenvolvimento da trama, apresentando cenas nas quais diversas percepções destacadas por Euclides da Cunha aparecem e dão pistas de como o roteiro prosseguirá, embora trazendo ressignificações para o espaço sertanejo e os personagens que o habitam."#;
+
+        let (input, abs) = r#abstract(input.as_bytes()).unwrap();
+
+        assert!(input.is_empty());
+
+        assert_eq!(
+            abs.parts,
+            vec![
+                AbstractPart::Text(r#"O objeto deste artigo é a série "#.as_bytes()),
+                AbstractPart::Styled(
+                    Style::Textit,
+                    b"Onde nascem os fortes",
+                    vec![AbstractPart::Text(b"Onde nascem os fortes")],
+                ),
+                AbstractPart::Text(r#" (TV Globo, 2018), escrita para exibição em canal aberto de televisão, em ano eleitoral e filmada no cariri paraibano. A partir do título e da ambiência, percebemos uma configuração que remete ao livro "#.as_bytes()),
+                AbstractPart::Styled(
+                    Style::Textit,
+                    "Os sertões".as_bytes(),
+                    vec![AbstractPart::Text("Os sertões".as_bytes())],
+                ),
+                AbstractPart::Text(" ".as_bytes()),
+                AbstractPart::Citeyear(r#"EcCUNHA1902sertoes"#.as_bytes(), None),
+                AbstractPart::Text(r#". Objetiva-se perscrutar como o conceito de sertão é trabalhado na obra, identificar a dialogia com o livro euclidiano e investigar o modo como as desigualdades sociais detectadas pelo escritor no início do século XX permanecem neste século XXI com impressionante atualidade. Ademais, o território sertanejo revela-se como poderoso cronotopo "#.as_bytes()),
+                AbstractPart::Cite(r#"EcBAKHTIN2003Estetica"#.as_bytes(), None),
+                AbstractPart::Text(r#", em forte simetria com a linha abissal da Sociologia das Ausências "#.as_bytes()),
+                AbstractPart::Cite(r#"EcSANTOS2004Para"#.as_bytes(), None),
+                AbstractPart::Text(r#". Elege-se o capítulo de estreia como evidenciador de pontos fundamentais da diegese, a partir de metodologia baseada na técnica da minutagem, através da qual analisa-se as estratégias de construção narrativa "#.as_bytes()),
+                AbstractPart::Cite(r#"EcMOTTA2013analise"#.as_bytes(), None),
+                AbstractPart::Text(r#", bem como os procedimentos de elaboração do roteiro "#.as_bytes()),
+                AbstractPart::Cite(r#"EcMACIEL2017poder"#.as_bytes(), None),
+                AbstractPart::Text(r#". Conclui-se que o episódio inaugural figura como síntese importante para o desenvolvimento da trama, apresentando cenas nas quais diversas percepções destacadas por Euclides da Cunha aparecem e dão pistas de como o roteiro prosseguirá, embora trazendo ressignificações para o espaço sertanejo e os personagens que o habitam."#.as_bytes()),
+            ],
+        );
+    }
+
+    #[test]
+    fn stops_at_unknown_command() {
+        let input = br#"Texto \textit{grifado} e fim. \par keywords=A."#;
+
+        let (input, abs) = r#abstract(input).unwrap();
+
+        assert_eq!(input, br#"\par keywords=A."#);
+        assert_eq!(
+            abs.parts,
+            vec![
+                AbstractPart::Text(b"Texto "),
+                AbstractPart::Styled(
+                    Style::Textit,
+                    b"grifado",
+                    vec![AbstractPart::Text(b"grifado")]
+                ),
+                AbstractPart::Text(b" e fim. "),
+            ],
+        );
+    }
+
+    #[test]
+    fn flat_text() {
+        let input = r"O \emph{sert\~ao de \textbf{Cunha}} \cite{Cunha1902} e \&.";
+        let (_, abs) = r#abstract(input.as_bytes()).unwrap();
+        assert_eq!(abs.text(), &b"O sert\xc3\xa3o de Cunha   e &."[..]);
+
+        let (_, abs) = r#abstract(b"Sobre o sert\xc3\xa3o.").unwrap();
+        assert!(matches!(abs.text(), Cow::Borrowed(b"Sobre o sert\xc3\xa3o.")));
+    }
+
+    #[test]
+    fn owned_spans() {
+        let input = r"abstract=O \emph{sertão de \cite[p. 2]{Cunha1902}} e $x^2$.".as_bytes();
+        let (_, abs) = r#abstract(&input[9..]).unwrap();
+        let owned = abs.to_owned_in(input);
+
+        fn spans(parts: &[OwnedPart], input: &[u8]) -> usize {
+            let mut count = 0;
+            for part in parts {
+                assert_eq!(&input[part.span.clone().unwrap()], part.source);
+                count += 1 + spans(&part.parts, input);
+            }
+            count
+        }
+        assert_eq!(spans(&owned.parts, input), 7);
+        assert_eq!(owned.span, Some(9..input.len()));
+
+        let emph = &owned.parts[1];
+        assert_eq!(emph.command.as_deref(), Some("emph"));
+        assert_eq!(emph.parts[1].command.as_deref(), Some("cite"));
+        assert_eq!(emph.parts[1].source, b"Cunha1902");
+
+        // parsed from somewhere else
+        assert_eq!(abs.to_owned_in(b"O sert").span, None);
+    }
+
+    #[test]
+    fn quotes() {
+        let input = r#"A \enquote{ecologia de saberes} e as \aspas {linhas abissais}."#;
+
+        let (_, abs) = r#abstract(input.as_bytes()).unwrap();
+
+        assert_eq!(abs.parts[1].text(), Some(&b"ecologia de saberes"[..]));
+        assert_eq!(abs.parts[3].text(), Some(&b"linhas abissais"[..]));
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::PlainText).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "A “ecologia de saberes” e as “linhas abissais”."
+        );
+    }
+
+    #[test]
+    fn localized_quotes() {
+        let input = r#"O \enquote{sertão} e \textit{Os sertões}."#;
+        let (_, abs) = r#abstract(input.as_bytes()).unwrap();
+
+        let render = |language| {
+            let options = RenderOptions {
+                language,
+                title_style: TitleStyle::Quoted,
+                ..Default::default()
+            };
+            let mut output = Vec::new();
+            abs.write_with_citations(&mut output, &BibIndex::default(), Format::Markdown, options)
+                .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(render(Language::PtBr), "O “sertão” e “Os sertões”.");
+        assert_eq!(render(Language::En), "O “sertão” e “Os sertões”.");
+        assert_eq!(render(Language::Es), "O «sertão» e «Os sertões».");
+    }
+
+    #[test]
+    fn scripts() {
+        let input = r#"No século XX\textsuperscript{o}, o CO\textsubscript{2}."#;
+
+        let (_, abs) = r#abstract(input.as_bytes()).unwrap();
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::Markdown).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "No século XX^o^, o CO~2~.");
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::PlainText).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "No século XXo, o CO2.");
+
+        let options = RenderOptions {
+            inline_html: true,
+            ..RenderOptions::default()
+        };
+        let mut output = Vec::new();
+        abs.write_with_citations(&mut output, &BibIndex::default(), Format::Markdown, options)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "No século XX<sup>o</sup>, o CO<sub>2</sub>."
+        );
+    }
+
+    #[test]
+    fn casing() {
+        let input = r#"Segundo \textsc{Santos}, a \MakeUppercase{ação}."#;
+
+        let (_, abs) = r#abstract(input.as_bytes()).unwrap();
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::Markdown).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "Segundo Santos, a AÇÃO.");
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::PlainText).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "Segundo SANTOS, a AÇÃO.");
+
+        let options = RenderOptions {
+            inline_html: true,
+            ..RenderOptions::default()
+        };
+        let mut output = Vec::new();
+        abs.write_with_citations(&mut output, &BibIndex::default(), Format::Markdown, options)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"Segundo <span style="font-variant: small-caps">Santos</span>, a AÇÃO."#
+        );
+    }
+
+    #[test]
+    fn typography() {
+        let (_, abs) = r#abstract(b"Entre 1902--1930 --- ou Euclides~da~Cunha.").unwrap();
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::PlainText).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Entre 1902–1930 — ou Euclides\u{a0}da\u{a0}Cunha."
+        );
+
+        let options = RenderOptions {
+            raw_typography: true,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        abs.write_with_citations(&mut output, &BibIndex::default(), Format::Markdown, options)
+            .unwrap();
+        assert_eq!(output, b"Entre 1902--1930 --- ou Euclides~da~Cunha.");
+    }
+
+    #[test]
+    fn smart_quotes() {
+        let input = br#"O ``mar'' de \textit{`sert\~ao'} n\~ao \'e d'\'agua."#;
+        let (_, abs) = r#abstract(input).unwrap();
+
+        let options = RenderOptions {
+            smart_quotes: true,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        abs.write_with_citations(&mut output, &BibIndex::default(), Format::Markdown, options)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "O “mar” de _‘sertão’_ não é d’água."
+        );
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::Markdown).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "O ``mar'' de _`sertão'_ não é d'água.");
+    }
+
+    #[test]
+    fn print_leftovers() {
+        let input = r#"A ex\-pe\-ri\-ên\-cia de Euclides~da~Cunha\hspace*{1em} e \textit{Os~sertões}."#;
+
+        let (input, abs) = r#abstract(input.as_bytes()).unwrap();
+        assert!(input.is_empty());
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::Markdown).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "A experiência de Euclides\u{a0}da\u{a0}Cunha e _Os\u{a0}sertões_."
+        );
+
+        let mut output = Vec::new();
+        abs.write_source(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"A ex\-pe\-ri\-ên\-cia de Euclides~da~Cunha\hspace*{1em} e \textit{Os~sertões}."#
+        );
+    }
+
+    #[test]
+    fn emphasis() {
+        let (_, abs) = r#abstract(br#"A serie \textit{Onde_nascem *os* fortes}."#).unwrap();
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::Markdown).unwrap();
+        assert_eq!(output, br#"A serie _Onde\_nascem *os* fortes_."#);
+
+        let options = RenderOptions {
+            emphasis: Emphasis::Asterisk,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        abs.write_with_citations(&mut output, &BibIndex::default(), Format::Markdown, options)
+            .unwrap();
+        assert_eq!(output, br#"A serie *Onde_nascem \*os\* fortes*."#);
+    }
+
+    #[test]
+    fn nested_italics() {
+        let input = br#"Em \textit{a \emph{b} c_d} e \textit{\textbf{e_f}}."#;
+        let (_, abs) = r#abstract(input).unwrap();
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::Markdown).unwrap();
+        assert_eq!(output, br#"Em _a b c\_d_ e _**e\_f**_."#);
+
+        // italics inside another style still escape their text
+        let (_, abs) = r#abstract(br#"Em \textbf{a \emph{b_c} d_e}."#).unwrap();
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::Markdown).unwrap();
+        assert_eq!(output, br#"Em **a _b\_c_ d_e**."#);
+    }
+
+    #[test]
+    fn emph() {
+        let (_, abs) = r#abstract(br#"Um \emph{novo} olhar sobre \textit{Os fortes}."#).unwrap();
+
+        let options = RenderOptions {
+            title_style: TitleStyle::Bold,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        abs.write_with_citations(&mut output, &BibIndex::default(), Format::Markdown, options)
+            .unwrap();
+        assert_eq!(output, br#"Um _novo_ olhar sobre **Os fortes**."#);
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::PlainText).unwrap();
+        assert_eq!(output, br#"Um novo olhar sobre Os fortes."#);
+
+        let mut output = Vec::new();
+        abs.write_source(&mut output).unwrap();
+        assert_eq!(output, br#"Um \emph{novo} olhar sobre \textit{Os fortes}."#);
+    }
+
+    #[test]
+    fn textbf() {
+        let (_, abs) = r#abstract(br#"Um \textbf{forte} \textbf{\textit{Os fortes}}."#).unwrap();
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::Markdown).unwrap();
+        assert_eq!(output, br#"Um **forte** **_Os fortes_**."#);
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::PlainText).unwrap();
+        assert_eq!(output, br#"Um forte Os fortes."#);
+    }
+
+    #[test]
+    fn nested_styles() {
+        let bib = Bibtex::parse(
+            r#"
+            @book{EcCUNHA1902sertoes,
+                author = {Cunha, E.},
+                title  = {Os sertões},
+                year   = {1902}
+            }"#,
+        )
+        .unwrap();
+        let bib = BibIndex::new(&bib);
+
+        let input = r#"Sobre \textit{Os sertões \citeyear{EcCUNHA1902sertoes}} e \textsc{a \textit{terra}}."#;
+        let (rest, abs) = r#abstract(input.as_bytes()).unwrap();
+        assert!(rest.is_empty());
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &bib, Format::Markdown).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Sobre _Os sertões (1902)_ e a _terra_."
+        );
+
+        let mut output = Vec::new();
+        abs.write_source(&mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), input);
+    }
+
+    #[test]
+    fn strip_braces() {
+        let (_, abs) =
+            r#abstract(br#"Dados do {IBGE} e {{do}} \textit{Os sertoes}{} {x."#).unwrap();
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::Markdown).unwrap();
+        assert_eq!(output, br#"Dados do {IBGE} e {{do}} _Os sertoes_{} {x."#);
+
+        let options = RenderOptions {
+            strip_braces: true,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        abs.write_with_citations(&mut output, &BibIndex::default(), Format::Markdown, options)
+            .unwrap();
+        assert_eq!(output, br#"Dados do IBGE e do _Os sertoes_ {x."#);
+    }
+
+    #[test]
+    fn symbols() {
+        let (rest, abs) = r#abstract(br#"Radio \& TV: 100\% \textit{A \& B}.\par"#).unwrap();
+        assert_eq!(rest, br#"\par"#);
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::PlainText).unwrap();
+        assert_eq!(output, br#"Radio & TV: 100% A & B."#);
+
+        let mut output = Vec::new();
+        abs.write_source(&mut output).unwrap();
+        assert_eq!(output, br#"Radio \& TV: 100\% \textit{A \& B}."#);
+
+        let (_, abs) = r#abstract(br#"US\$ 5, \{a\_b\}, 50\% \& mais"#).unwrap();
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::PlainText).unwrap();
+        assert_eq!(output, br#"US$ 5, {a_b}, 50% & mais"#);
+
+        // markdown keeps the backslash, which escapes the character there too
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::Markdown).unwrap();
+        assert_eq!(output, br#"US\$ 5, \{a\_b\}, 50\% \& mais"#);
+    }
+
+    #[test]
+    fn accents() {
+        let input = br#"Educa\c{c}\~ao no \textit{sert\~ao} de Jos\'e.\par"#;
+        let (rest, abs) = r#abstract(input).unwrap();
+        assert_eq!(rest, br#"\par"#);
 
-#[derive(Debug, PartialEq, Clone, Copy)]
-enum AbstractPart<'a> {
-    Text(&'a [u8]),
-    Textit(&'a [u8]),
-    Citeyear(&'a [u8]),
-    Cite(&'a [u8]),
-}
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::Markdown).unwrap();
+        assert_eq!(output, "Educação no _sertão_ de José.".as_bytes());
 
-pub struct Abstract<'a> {
-    parts: Vec<AbstractPart<'a>>,
-}
+        let mut output = Vec::new();
+        abs.write_source(&mut output).unwrap();
+        assert_eq!(output, &input[..input.len() - 4]);
+    }
 
-pub enum Format {
-    Markdown,
-    PlainText,
-}
+    #[test]
+    fn balanced_block() {
+        assert_eq!(
+            block(br#"{{O} sert\{a\}o} resto"#),
+            Ok((&b" resto"[..], &br#"{O} sert\{a\}o"#[..]))
+        );
+        assert_eq!(block(b"{} resto"), Ok((&b" resto"[..], &b""[..])));
+        assert_eq!(block(b"palavra resto"), Ok((&b" resto"[..], &b"palavra"[..])));
+        assert!(block(b"{aberto").is_err());
 
-impl Format {
-    fn italic(&self, mut write: impl Write, text: &[u8]) -> std::io::Result<()> {
-        match self {
-            Format::Markdown => {
-                write.write_all(b"_")?;
-                write.write_all(text)?;
-                write.write_all(b"_")
-            }
-            Format::PlainText => write.write_all(text),
-        }
+        let (rest, abs) = r#abstract("Sobre \\textit{Os {S}ertões}.".as_bytes()).unwrap();
+        assert!(rest.is_empty());
+        let options = RenderOptions {
+            strip_braces: true,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        abs.write_with_citations(&mut output, &BibIndex::default(), Format::Markdown, options)
+            .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "Sobre _Os Sertões_.");
     }
-}
 
-impl<'a> Abstract<'a> {
-    pub fn write_to(
-        &self,
-        mut write: impl Write,
-        bib: &HashMap<&[u8], &Bibliography>,
-        format: Format,
-    ) -> std::io::Result<()> {
-        for part in self.parts.iter().copied() {
-            match part {
-                AbstractPart::Text(text) => write.write_all(text)?,
-                AbstractPart::Textit(text) => {
-                    format.italic(&mut write, text)?;
-                }
-                AbstractPart::Citeyear(key) => {
-                    let bib = match bib.get(key) {
-                        Some(bib) => bib,
-                        None => {
-                            return Err(std::io::Error::new(
-                                std::io::ErrorKind::Other,
-                                format!("bibliography not found: {}", String::from_utf8_lossy(key)),
-                            ))
-                        }
-                    };
+    #[test]
+    fn title_styles() {
+        let (_, abs) = r#abstract(br#"O livro \textit{Os sertoes}."#).unwrap();
 
-                    let year = bib
-                        .tags()
-                        .iter()
-                        .find_map(|(k, v)| if k == "year" { Some(v.as_str()) } else { None })
-                        .unwrap_or("_s.d._");
+        let render = |title_style| {
+            let options = RenderOptions {
+                title_style,
+                ..Default::default()
+            };
+            let mut output = Vec::new();
+            abs.write_with_citations(&mut output, &BibIndex::default(), Format::Markdown, options)
+                .unwrap();
+            String::from_utf8(output).unwrap()
+        };
 
-                    write.write_all(b"(")?;
-                    write.write_all(year.as_bytes())?;
-                    write.write_all(b")")?;
-                }
-                AbstractPart::Cite(key) => {
-                    let bib = match bib.get(key) {
-                        Some(bib) => bib,
-                        None => {
-                            return Err(std::io::Error::new(
-                                std::io::ErrorKind::Other,
-                                format!("bibliography not found: {}", String::from_utf8_lossy(key)),
-                            ))
-                        }
-                    };
+        assert_eq!(render(TitleStyle::Italic), "O livro _Os sertoes_.");
+        assert_eq!(render(TitleStyle::Bold), "O livro **Os sertoes**.");
+        assert_eq!(render(TitleStyle::Quoted), "O livro “Os sertoes”.");
+    }
 
-                    let year = bib
-                        .tags()
-                        .iter()
-                        .find_map(|(k, v)| if k == "year" { Some(v.as_str()) } else { None })
-                        .unwrap_or("_s.d._");
-
-                    let author = bib
-                        .tags()
-                        .iter()
-                        .find_map(|(k, v)| {
-                            if k == "author" {
-                                let s: Vec<_> = v.split(" AND ")
-                                    .map(|a| a.split(',').next().unwrap())
-                                    .collect();
-                                
-                                if s.len() > 3 {
-                                    let mut s = s[0].to_owned();
-                                    s += ", _et al._";
-                                    Some(Cow::Owned(s))
-                                } else if s.len() > 1 {
-                                    Some(Cow::Owned(s.join("; ")))
-                                } else {
-                                    Some(Cow::Borrowed(s[1]))
-                                }
-                            } else {
-                                None
-                            }
-                        })
-                        .unwrap_or(
-                            bib.tags()
-                                .iter()
-                                .find_map(|(k, v)| {
-                                    if k == "title" {
-                                        Some(Cow::Borrowed(v.as_str().split(' ').next().unwrap()))
-                                    } else {
-                                        None
-                                    }
-                                })
-                                .unwrap_or(Cow::Borrowed("")),
-                        );
-
-                    write.write_all(b"(")?;
-                    write.write_all(author.trim().to_uppercase().as_bytes())?;
-                    write.write_all(b", ")?;
-                    write.write_all(year.trim().as_bytes())?;
-                    write.write_all(b")")?;
-                }
+    #[test]
+    fn images() {
+        let input = br#"O mapa \includegraphics[width=3cm]{mapa.png} mostra o sertao."#;
+
+        let (input, abs) = r#abstract(input).unwrap();
+        assert!(input.is_empty());
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::Markdown).unwrap();
+        assert_eq!(output, b"O mapa mostra o sertao.");
+
+        let options = RenderOptions {
+            image_prefix: Some("/img/n5/"),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        abs.write_with_citations(&mut output, &BibIndex::default(), Format::Markdown, options)
+            .unwrap();
+        assert_eq!(output, b"O mapa ![](/img/n5/mapa.png) mostra o sertao.");
+
+        let mut output = Vec::new();
+        abs.write_source(&mut output).unwrap();
+        assert_eq!(output, br#"O mapa \includegraphics[width=3cm]{mapa.png} mostra o sertao."#);
+    }
+
+    #[test]
+    fn urls() {
+        let input = br#"Dados em \url{https://galo.ufrn.br/a_b?c=1#d} e no IBGE."#;
+
+        let (input, abs) = r#abstract(input).unwrap();
+        assert!(input.is_empty());
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::Markdown).unwrap();
+        assert_eq!(output, b"Dados em <https://galo.ufrn.br/a_b?c=1#d> e no IBGE.");
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::PlainText).unwrap();
+        assert_eq!(output, b"Dados em https://galo.ufrn.br/a_b?c=1#d e no IBGE.");
+
+        let mut output = Vec::new();
+        abs.write_source(&mut output).unwrap();
+        assert_eq!(output, br#"Dados em \url{https://galo.ufrn.br/a_b?c=1#d} e no IBGE."#);
+    }
+
+    #[test]
+    fn math() {
+        let input = br#"Com $\alpha \leq \frac{1}{2}$ e $$x_{i}$$ por R$ 100 ou $5 e $10."#;
+
+        let (input, abs) = r#abstract(input).unwrap();
+        assert!(input.is_empty());
+        assert!(abs.has_math());
+
+        let options = RenderOptions {
+            math: MathStyle::Katex,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        abs.write_with_citations(&mut output, &BibIndex::default(), Format::Markdown, options)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"Com $\alpha \leq \frac{1}{2}$ e $$x_{i}$$ por R$ 100 ou $5 e $10."#
+        );
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::Markdown).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Com α ≤ 1/2 e x_i por R$ 100 ou $5 e $10."
+        );
+
+        let mut output = Vec::new();
+        abs.write_source(&mut output).unwrap();
+        assert_eq!(
+            output,
+            br#"Com $\alpha \leq \frac{1}{2}$ e $$x_{i}$$ por R$ 100 ou $5 e $10."#
+        );
+
+        let (_, abs) = r#abstract(b"Custa R$ 100 e US$ 20.").unwrap();
+        assert!(!abs.has_math());
+    }
+
+    #[test]
+    fn references() {
+        let input = br#"Como mostra a figura \ref{fig:mapa}, o sertao \autoref {tab:dados}."#;
+
+        let (input, abs) = r#abstract(input).unwrap();
+        assert!(input.is_empty());
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::Markdown).unwrap();
+        assert_eq!(output, b"Como mostra a figura, o sertao.");
+
+        let mut output = Vec::new();
+        abs.write_source(&mut output).unwrap();
+        assert_eq!(output, br#"Como mostra a figura \ref{fig:mapa}, o sertao \autoref {tab:dados}."#);
+
+        let custom = [CustomCommand {
+            name: "ref".to_owned(),
+            handler: Box::new(Replacement("(ver PDF)".to_owned())),
+        }];
+        let (_, abs) = abstract_with(br#"Na figura \ref{fig:mapa}."#, &custom, &Limits::NONE).unwrap();
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::Markdown).unwrap();
+        assert_eq!(output, b"Na figura (ver PDF).");
+    }
+
+    #[test]
+    fn custom_command() {
+        let custom = [CustomCommand {
+            name: "gls".to_owned(),
+            handler: Box::new(Replacement("{} (glossário)".to_owned())),
+        }];
+
+        let input = r#"Um \gls{SUS} \textit{mar}. \par"#;
+
+        let (input, abs) = abstract_with(input.as_bytes(), &custom, &Limits::NONE).unwrap();
+
+        assert_eq!(input, br#"\par"#);
+        assert_eq!(abs.parts[1], AbstractPart::Custom(&custom[0], b"SUS"));
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::Markdown).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "Um SUS (glossário) _mar_. ");
+
+        let mut output = Vec::new();
+        abs.write_source(&mut output).unwrap();
+        assert_eq!(output, br#"Um \gls{SUS} \textit{mar}. "#);
+        assert_eq!(abs.source(), br#"Um \gls{SUS} \textit{mar}. "#);
+    }
+
+    #[test]
+    fn textual_citations() {
+        let bib = Bibtex::parse(
+            r#"
+            @incollection{EcSANTOS2004Para,
+                author = {SANTOS, B. S.},
+                title  = {Para uma sociologia das ausências},
+                year   = {2004}
             }
-        }
+            @book{EcMENESES2009Epistemologias,
+                author = {Meneses, M. P. AND Santos, B. S.},
+                title  = {Epistemologias do Sul},
+                year   = {2009}
+            }"#,
+        )
+        .unwrap();
+        let bib = BibIndex::new(&bib);
 
-        Ok(())
+        let input = br#"Segundo \citeauthor{EcSANTOS2004Para} \citeyear{EcSANTOS2004Para}, e \citet{EcMENESES2009Epistemologias} \citep{EcSANTOS2004Para}."#;
+
+        let (_, abs) = r#abstract(input).unwrap();
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &bib, Format::Markdown).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Segundo Santos (2004), e Meneses e Santos (2009) (SANTOS, 2004)."
+        );
+
+        let input = br#"Para \citeonline{EcSANTOS2004Para}, como \textcite[p. 9]{EcMENESES2009Epistemologias}."#;
+        let (_, abs) = r#abstract(input).unwrap();
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &bib, Format::Markdown).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Para Santos (2004), como Meneses e Santos (2009, p. 9)."
+        );
+
+        let mut output = Vec::new();
+        abs.write_source(&mut output).unwrap();
+        assert_eq!(
+            output,
+            br#"Para \citet{EcSANTOS2004Para}, como \citet[p. 9]{EcMENESES2009Epistemologias}."#
+        );
+
+        let input = br#"Em \citetitle{EcMENESES2009Epistemologias}, \citeauthor{EcMENESES2009Epistemologias} retomam \citetitle {EcSANTOS2004Para}."#;
+        let (_, abs) = r#abstract(input).unwrap();
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &bib, Format::Markdown).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Em _Epistemologias do Sul_, Meneses e Santos retomam _Para uma sociologia das ausências_."
+        );
+        assert_eq!(
+            abs.citation_keys().collect::<Vec<_>>(),
+            [
+                &b"EcMENESES2009Epistemologias"[..],
+                b"EcMENESES2009Epistemologias",
+                b"EcSANTOS2004Para"
+            ]
+        );
     }
-}
 
-fn block(input: &[u8]) -> IResult<&[u8], &[u8]> {
-    let braced = delimited(char('{'), is_not(&b"}"[..]), char('}'));
-    let not_braced = is_not(&b" \t\r\n"[..]);
+    #[test]
+    fn quotations() {
+        let bib = Bibtex::parse(
+            r#"
+            @book{EcCUNHA1902sertoes,
+                author = {CUNHA, E.},
+                title  = {Os sertões},
+                year   = {1902}
+            }"#,
+        )
+        .unwrap();
+        let bib = BibIndex::new(&bib);
 
-    alt((braced, not_braced))(input)
-}
+        let input = r#"Diz o autor:
+\begin{citacao}[brazil]
+O sertanejo é, antes de tudo, um \textit{forte}.
 
-fn command(input: &[u8]) -> IResult<&[u8], AbstractPart> {
-    let (input, _) = space(input)?;
-    let original_input = input;
-    let (input, _) = tag("\\")(input)?;
+Não tem o raquitismo \cite{EcCUNHA1902sertoes}.
+\end{citacao}
+ E segue. \begin{quote}Outra\end{quote}\par"#;
 
-    let (input, command) = alt((tag("textit"), tag("citeyear"), tag("cite")))(input)?;
+        let (rest, abs) = r#abstract(input.as_bytes()).unwrap();
+        assert_eq!(rest, br#"\par"#);
+        assert_eq!(abs.citation_keys().collect::<Vec<_>>(), [b"EcCUNHA1902sertoes"]);
+        assert_eq!(abs.words(), 18);
 
-    let (input, _) = space(input)?;
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &bib, Format::Markdown).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Diz o autor:\n\n> O sertanejo é, antes de tudo, um _forte_.\n>\n> Não tem o raquitismo (CUNHA, 1902).\n\nE segue.\n\n> Outra"
+        );
 
-    let (input, arg) = block(input)?;
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &bib, Format::PlainText).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Diz o autor:\n\n    O sertanejo é, antes de tudo, um forte.\n\n    Não tem o raquitismo (CUNHA, 1902).\n\nE segue.\n\n    Outra"
+        );
 
-    let part = match command {
-        b"textit" => AbstractPart::Textit(arg),
-        b"citeyear" => AbstractPart::Citeyear(arg),
-        b"cite" => AbstractPart::Cite(arg),
-        _ => {
-            return Err(nom::Err::Error(nom::error::Error::new(
-                original_input,
-                ErrorKind::Satisfy,
-            )))
-        }
-    };
+        let mut output = Vec::new();
+        abs.write_source(&mut output).unwrap();
+        assert_eq!(output, &input.as_bytes()[..input.len() - 4]);
 
-    Ok((input, part))
-}
+        // other environments end the abstract as before
+        let (rest, _) = r#abstract(br#"Os itens \begin{itemize}\item a\end{itemize}"#).unwrap();
+        assert_eq!(rest, br#"\begin{itemize}\item a\end{itemize}"#);
+    }
 
-fn text(input: &[u8]) -> IResult<&[u8], AbstractPart> {
-    let (input, text) = is_not(&b"\\"[..])(input)?;
+    #[test]
+    fn doi_citations() {
+        let (_, abs) =
+            r#abstract(br#"Como visto \cite{10.1590/1982-2553} e em \citet{10.1002/(SICI)1097}."#)
+                .unwrap();
 
-    Ok((input, AbstractPart::Text(text)))
-}
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::Markdown).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Como visto ([doi:10.1590/1982-2553](https://doi.org/10.1590/1982-2553)) e em [doi:10.1002/(SICI)1097](https://doi.org/10.1002/%28SICI%291097)."
+        );
 
-pub fn r#abstract(input: &[u8]) -> IResult<&[u8], Abstract> {
-    let part = alt((text, command));
-    let (input, parts) = many0(part)(input)?;
-    Ok((input, Abstract { parts }))
-}
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &BibIndex::default(), Format::PlainText).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Como visto (doi:10.1590/1982-2553) e em doi:10.1002/(SICI)1097."
+        );
 
-#[cfg(test)]
-mod test {
+        let (_, abs) = r#abstract(br#"\cite{EcSANTOS2004Para}"#).unwrap();
+        assert!(abs.write_to(Vec::new(), &BibIndex::default(), Format::PlainText).is_err());
+    }
 
-    use std::borrow::{Borrow, Cow};
+    #[test]
+    fn author_year_style() {
+        let bib = Bibtex::parse(
+            r#"
+            @incollection{EcSANTOS2004Para,
+                author = {SANTOS, B. S.},
+                title  = {Para uma sociologia das ausências},
+                year   = {2004}
+            }"#,
+        )
+        .unwrap();
+        let bib = BibIndex::new(&bib);
 
-    use nom_bibtex::Bibtex;
+        let (_, abs) = r#abstract(br#"Como visto \cite{EcSANTOS2004Para}."#).unwrap();
 
-    use super::*;
+        let mut output = Vec::new();
+        let options = RenderOptions {
+            style: CitationStyle::AuthorYear,
+            ..Default::default()
+        };
+        abs.write_with_citations(&mut output, &bib, Format::Markdown, options)
+            .unwrap();
+        assert_eq!(output, b"Como visto (Santos, 2004).");
+    }
 
     #[test]
-    fn simple_abstract() {
-        let input = r#"O objeto deste artigo é a série \textit {Onde nascem os fortes} (TV Globo, 2018), escrita para exibição em canal aberto de televisão, em ano eleitoral e filmada no cariri paraibano. A partir do título e da ambiência, percebemos uma configuração que remete ao livro \textit {Os sertões} \citeyear {EcCUNHA1902sertoes}. Objetiva-se perscrutar como o conceito de sertão é trabalhado na obra, identificar a dialogia com o livro euclidiano e investigar o modo como as desigualdades sociais detectadas pelo escritor no início do século XX permanecem neste século XXI com impressionante atualidade. Ademais, o território sertanejo revela-se como poderoso cronotopo \cite {EcBAKHTIN2003Estetica}, em forte simetria com a linha abissal da Sociologia das Ausências \cite {EcSANTOS2004Para}. Elege-se o capítulo de estreia como evidenciador de pontos fundamentais da diegese, a partir de metodologia baseada na técnica da minutagem, através da qual analisa-se as estratégias de construção narrativa \cite {EcMOTTA2013analise}, bem como os procedimentos de elaboração do roteiro \cite {EcMACIEL2017poder}. Conclui-se que o episódio inaugural figura como síntese importante para o desenvolvimento da trama, apresentando cenas nas quais diversas percepções destacadas por Euclides da Cunha aparecem e dão pistas de como o roteiro prosseguirá, embora trazendo ressignificações para o espaço sertanejo e os personagens que o habitam."#;
+    fn citation_hover() {
+        let bib = Bibtex::parse(
+            r#"
+            @incollection{EcSANTOS2004Para,
+                author = {SANTOS, B. S.},
+                title  = {Para uma sociologia das ausências},
+                year   = {2004}
+            }"#,
+        )
+        .unwrap();
+        let bib = BibIndex::new(&bib);
 
-        let (input, abs) = r#abstract(input.as_bytes()).unwrap();
+        let input = br#"Como visto \cite{EcSANTOS2004Para} e \citeauthor{EcSANTOS2004Para}."#;
+        let (_, abs) = r#abstract(input).unwrap();
+
+        let render = |format, hover| {
+            let options = RenderOptions {
+                hover,
+                ..Default::default()
+            };
+            let mut output = Vec::new();
+            abs.write_with_citations(&mut output, &bib, format, options).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(
+            render(Format::Markdown, Some(Hover::Abbr)),
+            "Como visto <abbr title=\"SANTOS, B. S. Para uma sociologia das ausências. 2004.\">\
+             (SANTOS, 2004)</abbr> e <abbr title=\"SANTOS, B. S. Para uma sociologia das \
+             ausências. 2004.\">Santos</abbr>."
+        );
+        assert_eq!(
+            render(Format::Markdown, Some(Hover::Shortcode)),
+            "Como visto {{< cite key=\"EcSANTOS2004Para\" reference=\"SANTOS, B. S. Para uma \
+             sociologia das ausências. 2004.\" >}}(SANTOS, 2004){{< /cite >}} e {{< cite \
+             key=\"EcSANTOS2004Para\" reference=\"SANTOS, B. S. Para uma sociologia das \
+             ausências. 2004.\" >}}Santos{{< /cite >}}."
+        );
+        // descriptions and other plain text stay as they were
+        assert_eq!(
+            render(Format::PlainText, Some(Hover::Abbr)),
+            "Como visto (SANTOS, 2004) e Santos."
+        );
+    }
+
+    #[test]
+    fn grouped_citations() {
+        let bib = Bibtex::parse(
+            r#"
+            @book{EcCUNHA1902sertoes,
+                author = {Cunha, Euclides da},
+                title  = {Os sertões},
+                year   = {1902}
+            }
+            @incollection{EcSANTOS2004Para,
+                author = {SANTOS, B. S.},
+                title  = {Para uma sociologia das ausências},
+                year   = {2004}
+            }"#,
+        )
+        .unwrap();
+        let bib = BibIndex::new(&bib);
+
+        let input = br#"Como visto \cite{EcCUNHA1902sertoes, EcSANTOS2004Para} e \citet{EcCUNHA1902sertoes,EcSANTOS2004Para}."#;
+        let (_, abs) = r#abstract(input).unwrap();
+        assert_eq!(
+            abs.citation_keys().collect::<Vec<_>>(),
+            [
+                &b"EcCUNHA1902sertoes"[..],
+                b"EcSANTOS2004Para",
+                b"EcCUNHA1902sertoes",
+                b"EcSANTOS2004Para"
+            ]
+        );
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &bib, Format::Markdown).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Como visto (CUNHA, 1902; SANTOS, 2004) e Cunha (1902); Santos (2004)."
+        );
+
+        let (_, abs) = r#abstract(br#"Ver \cite{EcCUNHA1902sertoes,EcSANTOS2004Para}."#).unwrap();
+        let options = RenderOptions {
+            citation_separator: Some(", "),
+            hover: Some(Hover::Abbr),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        abs.write_with_citations(&mut output, &bib, Format::Markdown, options).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Ver (<abbr title=\"CUNHA, Euclides da. Os sertões. 1902.\">CUNHA, 1902</abbr>, \
+             <abbr title=\"SANTOS, B. S. Para uma sociologia das ausências. 2004.\">SANTOS, \
+             2004</abbr>)."
+        );
+
+        let (_, abs) = r#abstract(br#"Ver \cite{EcCUNHA1902sertoes,EcFALTA}."#).unwrap();
+        let err = abs.write_to(&mut Vec::new(), &bib, Format::Markdown).unwrap_err();
+        assert_eq!(err.to_string(), "bibliography not found: EcFALTA");
+    }
+
+    #[test]
+    fn locators() {
+        let bib = Bibtex::parse(
+            r#"
+            @book{EcBAKHTIN2003Estetica,
+                author = {Bakhtin, Mikhail},
+                title  = {Estética da criação verbal},
+                year   = {2003}
+            }
+            @incollection{EcSANTOS2004Para,
+                author = {SANTOS, B. S.},
+                title  = {Para uma sociologia das ausências},
+                year   = {2004}
+            }"#,
+        )
+        .unwrap();
+        let bib = BibIndex::new(&bib);
 
+        let input = br#"Como \cite[p.~42]{EcBAKHTIN2003Estetica}, \citeyear [p. 7--9]{EcSANTOS2004Para}, \citeauthor{EcSANTOS2004Para} \citeyear[p. 3]{EcSANTOS2004Para} e \cite[p. 1]{EcBAKHTIN2003Estetica,EcSANTOS2004Para} \cite[]{EcBAKHTIN2003Estetica}."#;
+        let (input, abs) = r#abstract(input).unwrap();
         assert!(input.is_empty());
 
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &bib, Format::Markdown).unwrap();
         assert_eq!(
-            abs.parts,
-            vec![
-                AbstractPart::Text(r#"O objeto deste artigo é a série "#.as_bytes()),
-                AbstractPart::Textit(r#"Onde nascem os fortes"#.as_bytes()),
-                AbstractPart::Text(r#" (TV Globo, 2018), escrita para exibição em canal aberto de televisão, em ano eleitoral e filmada no cariri paraibano. A partir do título e da ambiência, percebemos uma configuração que remete ao livro "#.as_bytes()),
-                AbstractPart::Textit(r#"Os sertões"#.as_bytes()),
-                AbstractPart::Text(" ".as_bytes()),
-                AbstractPart::Citeyear(r#"EcCUNHA1902sertoes"#.as_bytes()),
-                AbstractPart::Text(r#". Objetiva-se perscrutar como o conceito de sertão é trabalhado na obra, identificar a dialogia com o livro euclidiano e investigar o modo como as desigualdades sociais detectadas pelo escritor no início do século XX permanecem neste século XXI com impressionante atualidade. Ademais, o território sertanejo revela-se como poderoso cronotopo "#.as_bytes()),
-                AbstractPart::Cite(r#"EcBAKHTIN2003Estetica"#.as_bytes()),
-                AbstractPart::Text(r#", em forte simetria com a linha abissal da Sociologia das Ausências "#.as_bytes()),
-                AbstractPart::Cite(r#"EcSANTOS2004Para"#.as_bytes()),
-                AbstractPart::Text(r#". Elege-se o capítulo de estreia como evidenciador de pontos fundamentais da diegese, a partir de metodologia baseada na técnica da minutagem, através da qual analisa-se as estratégias de construção narrativa "#.as_bytes()),
-                AbstractPart::Cite(r#"EcMOTTA2013analise"#.as_bytes()),
-                AbstractPart::Text(r#", bem como os procedimentos de elaboração do roteiro "#.as_bytes()),
-                AbstractPart::Cite(r#"EcMACIEL2017poder"#.as_bytes()),
-                AbstractPart::Text(r#". Conclui-se que o episódio inaugural figura como síntese importante para o desenvolvimento da trama, apresentando cenas nas quais diversas percepções destacadas por Euclides da Cunha aparecem e dão pistas de como o roteiro prosseguirá, embora trazendo ressignificações para o espaço sertanejo e os personagens que o habitam."#.as_bytes()),
-            ],
+            String::from_utf8(output).unwrap(),
+            "Como (BAKHTIN, 2003, p.\u{a0}42), (2004, p. 7–9), Santos (2004, p. 3) e (BAKHTIN, \
+             2003; SANTOS, 2004, p. 1) (BAKHTIN, 2003)."
+        );
+
+        let mut output = Vec::new();
+        abs.write_source(&mut output).unwrap();
+        assert_eq!(
+            output,
+            br#"Como \cite[p.~42]{EcBAKHTIN2003Estetica}, \citeyear[p. 7--9]{EcSANTOS2004Para}, \citeauthor{EcSANTOS2004Para} \citeyear[p. 3]{EcSANTOS2004Para} e \cite[p. 1]{EcBAKHTIN2003Estetica,EcSANTOS2004Para} \cite{EcBAKHTIN2003Estetica}."#
         );
     }
 
+    #[test]
+    fn localized_no_date() {
+        let bib = Bibtex::parse("@misc{EcANON, title = {Cordel}}").unwrap();
+        let bib = BibIndex::new(&bib);
+
+        let (_, abs) = r#abstract(br#"Um folheto \citeyear{EcANON}."#).unwrap();
+
+        let mut output = Vec::new();
+        abs.write_to(&mut output, &bib, Format::Markdown).unwrap();
+        assert_eq!(output, b"Um folheto (_s.d._).");
+
+        let options = RenderOptions {
+            language: Language::En,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        abs.write_with_citations(&mut output, &bib, Format::Markdown, options)
+            .unwrap();
+        assert_eq!(output, b"Um folheto (_n.d._).");
+    }
+
+    #[test]
+    fn part_limit() {
+        // \textit{dois} is two parts, the group and its text
+        let limits = Limits {
+            max_parts: Some(4),
+            ..Default::default()
+        };
+        let input = br#"Um \textit{dois} tres \textit{quatro} cinco"#;
+
+        assert!(abstract_with(br#"Um \textit{dois} tres"#, &[], &limits).is_ok());
+        assert!(matches!(
+            abstract_with(input, &[], &limits),
+            Err(nom::Err::Failure(_))
+        ));
+    }
+
+    #[test]
+    fn depth_limit() {
+        let input = br#"Um \textit{dois \textbf{tres \emph{quatro}}} cinco"#;
+        let plain = |limits: &Limits| {
+            let (_, abs) = abstract_with(input, &[], limits).unwrap();
+            let mut output = Vec::new();
+            abs.write_to(&mut output, &BibIndex::default(), Format::PlainText)
+                .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(plain(&Limits::NONE), "Um dois tres quatro cinco");
+        let limits = Limits {
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        // the argument of \textbf is the second level
+        assert_eq!(plain(&limits), r#"Um dois tres \emph{quatro} cinco"#);
+    }
+
+    #[test]
+    fn empty_abstract() {
+        let (input, abs) = r#abstract(b"").unwrap();
+
+        assert!(input.is_empty());
+        assert!(abs.parts.is_empty());
+    }
+
     #[test]
     fn markdown() {
         let input = r#"O objeto deste artigo é a série \textit {Onde nascem os fortes} (TV Globo, 2018), escrita para exibição em canal aberto de televisão, em ano eleitoral e filmada no cariri paraibano. A partir do título e da ambiência, percebemos uma configuração que remete ao livro \textit {Os sertões} \citeyear {EcCUNHA1902sertoes}. Objetiva-se perscrutar como o conceito de sertão é trabalhado na obra, identificar a dialogia com o livro euclidiano e investigar o modo como as desigualdades sociais detectadas pelo escritor no início do século XX permanecem neste século XXI com impressionante atualidade. Ademais, o território sertanejo revela-se como poderoso cronotopo \cite {EcBAKHTIN2003Estetica}, em forte simetria com a linha abissal da Sociologia das Ausências \cite {EcSANTOS2004Para}. Elege-se o capítulo de estreia como evidenciador de pontos fundamentais da diegese, a partir de metodologia baseada na técnica da minutagem, através da qual analisa-se as estratégias de construção narrativa \cite {EcMOTTA2013analise}, bem como os procedimentos de elaboração do roteiro \cite {EcMACIEL2017poder}. Conclui-se que o episódio inaugural figura como síntese importante para o desenvolvimento da trama, apresentando cenas nas quais diversas percepções destacadas por Euclides da Cunha aparecem e dão pistas de como o roteiro prosseguirá, embora trazendo ressignificações para o espaço sertanejo e os personagens que o habitam."#;
@@ -419,11 +2421,7 @@ mod test {
         "#;
 
         let bib = Bibtex::parse(bibliography).unwrap();
-        let bib: HashMap<_, _> = bib
-            .bibliographies()
-            .iter()
-            .map(|b| (b.citation_key().as_bytes(), b))
-            .collect();
+        let bib = BibIndex::new(&bib);
 
         let (_, abs) = r#abstract(input.as_bytes()).unwrap();
 
@@ -1,20 +1,56 @@
 use nom::{
-    branch::{alt},
-    bytes::complete::{is_not, tag},
     character::complete::char,
-    IResult, sequence::{separated_pair, tuple}, error::ErrorKind,
+    IResult, sequence::separated_pair, error::ErrorKind,
 };
+use std::mem::discriminant;
 
-use crate::space::space;
+use crate::{
+    grammar::{longest_tag, AUTHOR_PARTS},
+    latex,
+    slug::slug,
+    space::space,
+};
 
 #[derive(Debug, PartialEq)]
 pub struct Author<'a> {
     pub given: &'a[u8],
     pub family: &'a[u8],
+    // position among the authors set with order>, the source order otherwise
+    pub order: Option<u32>,
+}
+
+impl Author<'_> {
+    // stable ASCII name for taxonomy pages, e.g. joao-da-silva-conceicao;
+    // accent macros count as the letters they stand for
+    pub fn slug(&self) -> String {
+        let (given, family) = (latex::decode(self.given), latex::decode(self.family));
+        let given = String::from_utf8_lossy(&given);
+        let family = String::from_utf8_lossy(&family);
+        slug(&format!("{} {}", given.trim(), family.trim()))
+    }
 }
 
-fn name(input: &[u8]) -> IResult<&[u8], &[u8]> {
-    let (input, name) = is_not(&b",.\\"[..])(input)?;
+/// A given or family name, ending at `,`, `.` (both consumed) or a command
+/// such as `\par`; accent macros such as `\'e` and escaped symbols such as
+/// `\&` belong to the name.
+pub fn name(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    let mut len = 0;
+    loop {
+        let rest = &input[len..];
+        len += match rest.first() {
+            None | Some(b',' | b'.') => break,
+            Some(b'\\') => match latex::accent(rest) {
+                Some((_, macro_len)) => macro_len,
+                None if latex::is_symbol(rest) => 2,
+                None => break,
+            },
+            Some(_) => 1,
+        };
+    }
+    if len == 0 {
+        return Err(nom::Err::Error(nom::error::Error::new(input, ErrorKind::IsNot)));
+    }
+    let (name, input) = input.split_at(len);
 
     // skip end delim if necessary
     let input = if input.is_empty() { 
@@ -28,13 +64,16 @@ fn name(input: &[u8]) -> IResult<&[u8], &[u8]> {
     Ok((input, name))
 }
 
-enum AuthorPart<'a> {
+#[derive(Debug, PartialEq)]
+pub enum AuthorPart<'a> {
     Given(&'a[u8]),
     Family(&'a[u8]),
+    Order(u32),
 }
 
-fn author_part(input: &[u8]) -> IResult<&[u8], AuthorPart> {
-    let key = alt((tag("given"), tag("family")));
+/// One `given> Name` or `family> Name` pair of an author.
+pub fn author_part(input: &[u8]) -> IResult<&[u8], AuthorPart<'_>> {
+    let key = |input| longest_tag(input, AUTHOR_PARTS.iter().map(|part| part.name));
     fn separator(input: &[u8]) -> IResult<&[u8], ()> {
         let (input, _) = space(input)?;
         let (input, _) = char('>')(input)?;
@@ -45,31 +84,78 @@ fn author_part(input: &[u8]) -> IResult<&[u8], AuthorPart> {
     let (input, (key, value)) = separated_pair(key, separator, name)(input)?;
 
     let part = match key {
-        b"given" => AuthorPart::Given(value),
-        b"family" => AuthorPart::Family(value),
+        "given" => AuthorPart::Given(value),
+        "family" => AuthorPart::Family(value),
+        "order" => match std::str::from_utf8(value).ok().and_then(|n| n.trim().parse().ok()) {
+            Some(order) => AuthorPart::Order(order),
+            None => return Err(nom::Err::Error(nom::error::Error::new(value, ErrorKind::Digit))),
+        },
         _ => unreachable!(),
     };
 
     Ok((input, part))
 }
 
-pub fn author(input: &[u8]) -> IResult<&[u8], Author> {
-    let original_input = input;
-    let (input, parts) = tuple((author_part, author_part))(input)?;
+/// An author as a given and a family part and an optional order part, in any order.
+pub fn author(input: &[u8]) -> IResult<&[u8], Author<'_>> {
+    let (mut input, first) = author_part(input)?;
+    let mut parts = vec![first];
+    // a part seen already starts the next author
+    while let Ok((rest, part)) = author_part(input) {
+        if parts.iter().any(|seen| discriminant(seen) == discriminant(&part)) {
+            break;
+        }
+        parts.push(part);
+        input = rest;
+    }
 
-    let (given, family) = match parts {
-        (AuthorPart::Family(family), AuthorPart::Given(given)) => (given, family),
-        (AuthorPart::Given(given), AuthorPart::Family(family)) => (given, family),
-        _ => return Err(nom::Err::Error(nom::error::Error::new(original_input, ErrorKind::Satisfy))),
+    let (mut given, mut family, mut order) = (None, None, None);
+    for part in parts {
+        match part {
+            AuthorPart::Given(name) => given = Some(name),
+            AuthorPart::Family(name) => family = Some(name),
+            AuthorPart::Order(n) => order = Some(n),
+        }
+    }
+    // the error points at where the missing part was expected
+    let (Some(given), Some(family)) = (given, family) else {
+        return Err(nom::Err::Error(nom::error::Error::new(input, ErrorKind::Satisfy)));
     };
 
-    Ok((input, Author { given, family }))
+    Ok((input, Author { given, family, order }))
 }
 
 #[cfg(test)]
 mod test {
 
-    use super::{author, Author};
+    use super::{author, author_part, Author, AuthorPart};
+
+    #[test]
+    fn slug() {
+        let slug = |given: &str, family: &str| {
+            Author {
+                given: given.as_bytes(),
+                family: family.as_bytes(),
+                order: None,
+            }
+            .slug()
+        };
+
+        assert_eq!(slug("Aurora Almeida de", " Miranda Leão "), "aurora-almeida-de-miranda-leao");
+        assert_eq!(slug("Conceição", "Gonçalves-Brandão"), "conceicao-goncalves-brandao");
+        assert_eq!(slug("D. M.", "Albuquerque Jr."), "d-m-albuquerque-jr");
+        assert_eq!(slug("Jos\\'e", "Concei\\c{c}\\~ao"), "jose-conceicao");
+    }
+
+    #[test]
+    fn accent_macros() {
+        let (input, author) = author(br"given> Jos\'e, family> Concei\c{c}\~ao\par").unwrap();
+
+        assert_eq!(author.given, br"Jos\'e");
+        assert_eq!(author.family, br"Concei\c{c}\~ao");
+        assert_eq!(input, br"\par");
+        assert_eq!(author.slug(), "jose-conceicao");
+    }
 
     #[test]
     fn no_space() {
@@ -82,6 +168,7 @@ mod test {
             Author {
                 family: b"Tal",
                 given: b"Fulano de",
+                order: None,
             }
         );
 
@@ -102,6 +189,7 @@ mod test {
             Author {
                 family: b"Tal",
                 given: b"Fulano de",
+                order: None,
             }
         );
 
@@ -121,9 +209,29 @@ mod test {
             Author {
                 family: b"Tal",
                 given: b"Fulano de",
+                order: None,
             }
         );
         
         assert_eq!(input, b"\\par");
     }
+
+    #[test]
+    fn single_part() {
+        let (input, part) = author_part(b" family > Tal, given> Fulano").unwrap();
+
+        assert_eq!(part, AuthorPart::Family(b"Tal"));
+        assert_eq!(input, b" given> Fulano");
+    }
+
+    #[test]
+    fn order() {
+        let (input, author) = author(b"order> 2, given> Fulano, family> Tal. given> Outro").unwrap();
+
+        assert_eq!(author.order, Some(2));
+        assert_eq!(author.family, b"Tal");
+        assert_eq!(input, b" given> Outro");
+
+        assert!(author_part(b"order> segundo").is_err());
+    }
 }
@@ -0,0 +1,473 @@
+use std::{
+    borrow::Cow,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use nom_bibtex::Bibtex;
+use serde_json::json;
+
+use crate::{
+    bib::{resolve_dois, BibIndex, DoiLookup},
+    error::{ParseError, ParseWarning},
+    grammar::Registry,
+    input::InputSource,
+    metadata::{parse_all, WriteOptions},
+    output::{write_atomic, write_record},
+    page::{previous_date, previous_slugs},
+    verify::files,
+    writer::{Newline, Newlines},
+};
+
+pub struct BatchOptions<'a> {
+    pub registry: &'a Registry,
+    pub options: &'a WriteOptions,
+    // without one a replaced page keeps its date, new ones get the current time
+    pub date: Option<chrono::DateTime<chrono::Utc>>,
+    pub newline: Newline,
+    // used by articles without a .bib file of their own
+    pub bib: Option<&'a str>,
+    // file name of the shared bibliography, for the bibliography: field
+    pub bib_file: Option<&'a str>,
+    // keep the search index entry of every converted article
+    pub search_index: bool,
+    // of the metadata files, e.g. tex; READMEs and the like are left alone
+    pub extension: &'a str,
+    // looks up DOI citations missing from the bibliography, e.g. online
+    pub dois: Option<&'a dyn DoiLookup>,
+}
+
+pub enum Destination<W: Write> {
+    // pages mirror the metadata tree below this directory
+    Directory(PathBuf),
+    // pages are NDJSON records, see output::write_record
+    Multiplex(W),
+}
+
+// why a file was not converted
+#[derive(Debug, PartialEq)]
+pub enum Failure {
+    // reading the sources or writing the page
+    Io(String),
+    Metadata(Vec<ParseError>),
+    Bibliography(String),
+    // e.g. a citation missing from the bibliography
+    Render(String),
+}
+
+impl Failure {
+    // stable names for tools reading the summary
+    pub fn code(&self) -> &'static str {
+        match self {
+            Failure::Io(_) => "io",
+            Failure::Metadata(_) => "metadata",
+            Failure::Bibliography(_) => "bibliography",
+            Failure::Render(_) => "render",
+        }
+    }
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Failure::Metadata(errors) => {
+                let errors: Vec<_> = errors.iter().map(ToString::to_string).collect();
+                write!(f, "{}", errors.join("; "))
+            }
+            Failure::Io(reason) | Failure::Bibliography(reason) | Failure::Render(reason) => {
+                write!(f, "{}", reason)
+            }
+        }
+    }
+}
+
+// what became of one metadata file
+pub struct Outcome {
+    pub source: PathBuf,
+    // the page written
+    pub result: Result<PathBuf, Failure>,
+    pub warnings: Vec<ParseWarning>,
+    // with BatchOptions::search_index
+    pub search_entry: Option<serde_json::Value>,
+    pub elapsed: Duration,
+}
+
+// `previous` is the page being replaced, if any
+fn convert(
+    source: &Path,
+    previous: Option<&[u8]>,
+    options: &BatchOptions,
+    warnings: &mut Vec<ParseWarning>,
+    search_entry: &mut Option<serde_json::Value>,
+) -> Result<Vec<u8>, Failure> {
+    let input = source
+        .read_input()
+        .map_err(|err| Failure::Io(err.to_string()))?;
+    let (mut metadata, errors) = parse_all(&input, options.registry);
+    warnings.append(&mut metadata.warnings);
+    if !errors.is_empty() {
+        return Err(Failure::Metadata(errors));
+    }
+
+    // an article's own bibliography wins over the shared one
+    let own = source.with_extension("bib");
+    let (mut bib, bib_file) = match std::fs::read_to_string(&own) {
+        Ok(bib) => (bib, own.file_name().map(|name| name.to_string_lossy())),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => (
+            options.bib.unwrap_or_default().to_owned(),
+            options.bib_file.map(Cow::Borrowed),
+        ),
+        Err(err) => return Err(Failure::Io(format!("{}: {}", own.display(), err))),
+    };
+    if let (Some(lookup), Some(r#abstract)) = (options.dois, &metadata.r#abstract) {
+        let cited = r#abstract.citation_keys();
+        warnings.extend(resolve_dois(&mut bib, &input, cited, lookup));
+    }
+    let bib = Bibtex::parse(&bib)
+        .map_err(|err| Failure::Bibliography(format!("invalid bibliography: {:?}", err)))?;
+    let bib = BibIndex::new(&bib);
+    let written = metadata.write_warnings(&input, &bib, options.options);
+    warnings.extend(written.map_err(|err| Failure::Render(err.to_string()))?);
+
+    let mut write_options = Cow::Borrowed(options.options);
+    if let (Some(previous), Some(slug)) = (previous, metadata.slug()) {
+        write_options.to_mut().aliases = previous_slugs(previous, &slug);
+    }
+    if write_options.bibliography_prefix.is_some() {
+        write_options.to_mut().bibliography_file = bib_file.map(Cow::into_owned);
+    }
+
+    let date = options
+        .date
+        .or_else(|| previous.and_then(previous_date))
+        .unwrap_or_else(chrono::Utc::now);
+
+    let mut page = Vec::new();
+    metadata
+        .wtite_to(
+            Newlines::new(&mut page, options.newline),
+            &bib,
+            date,
+            &write_options,
+        )
+        .map_err(|err| Failure::Render(err.to_string()))?;
+    if options.search_index {
+        let entry = metadata
+            .search_entry(&bib)
+            .map_err(|err| Failure::Render(err.to_string()))?;
+        *search_entry = Some(entry);
+    }
+    Ok(page)
+}
+
+// converts every metadata file below `meta`, carrying on after failures;
+// returns what became of each source
+pub fn batch<W: Write>(
+    meta: &Path,
+    mut destination: Destination<W>,
+    options: &BatchOptions,
+) -> std::io::Result<Vec<Outcome>> {
+    let mut report = Vec::new();
+    let io = |err: std::io::Error| Failure::Io(err.to_string());
+
+    for source in files(meta)? {
+        // .bib files are read with the article they belong to
+        if source.extension().is_none_or(|ext| ext != options.extension) {
+            continue;
+        }
+
+        let start = Instant::now();
+        let mut warnings = Vec::new();
+        let mut search_entry = None;
+        let relative = source.strip_prefix(meta).map_err(std::io::Error::other)?;
+        let result = match &mut destination {
+            Destination::Directory(out) => {
+                let path = out.join(relative).with_extension("md");
+                let previous = std::fs::read(&path).ok();
+                let page = convert(
+                    &source,
+                    previous.as_deref(),
+                    options,
+                    &mut warnings,
+                    &mut search_entry,
+                );
+                page.and_then(|page| {
+                    if let Some(parent) = path.parent() {
+                        std::fs::create_dir_all(parent).map_err(io)?;
+                    }
+                    write_atomic(&path, &page).map_err(io)?;
+                    Ok(path)
+                })
+            }
+            Destination::Multiplex(write) => {
+                let page = convert(&source, None, options, &mut warnings, &mut search_entry);
+                page.and_then(|page| {
+                    let path = relative.with_extension("md");
+                    write_record(write, &path.to_string_lossy(), &page).map_err(io)?;
+                    Ok(path)
+                })
+            }
+        };
+        report.push(Outcome {
+            source,
+            result,
+            warnings,
+            search_entry,
+            elapsed: start.elapsed(),
+        });
+    }
+
+    Ok(report)
+}
+
+// where a message applies, for annotating the metadata file
+pub(crate) fn located(message: String, position: Option<(usize, usize)>) -> serde_json::Value {
+    match position {
+        Some((line, column)) => json!({ "message": message, "line": line, "column": column }),
+        None => json!({ "message": message }),
+    }
+}
+
+/// The report of a batch run as JSON, for CI to annotate the metadata
+/// files: every file with its status, output or error code, the errors and
+/// warnings with their positions, and how long it took.
+pub fn write_summary(
+    mut write: impl Write,
+    report: &[Outcome],
+    elapsed: Duration,
+) -> std::io::Result<()> {
+    let files: Vec<_> = report
+        .iter()
+        .map(|outcome| {
+            let warnings: Vec<_> = outcome
+                .warnings
+                .iter()
+                .map(|warning| located(warning.to_string(), Some(warning.position())))
+                .collect();
+            let mut file = json!({
+                "source": outcome.source.to_string_lossy(),
+                "warnings": warnings,
+                "elapsed_ms": outcome.elapsed.as_millis() as u64,
+            });
+            match &outcome.result {
+                Ok(output) => {
+                    file["status"] = "ok".into();
+                    file["output"] = output.to_string_lossy().into();
+                }
+                Err(failure) => {
+                    let errors = match failure {
+                        Failure::Metadata(errors) => errors
+                            .iter()
+                            .map(|err| located(err.to_string(), err.position()))
+                            .collect(),
+                        failure => vec![located(failure.to_string(), None)],
+                    };
+                    file["status"] = "failed".into();
+                    file["code"] = failure.code().into();
+                    file["errors"] = errors.into();
+                }
+            }
+            file
+        })
+        .collect();
+
+    let failed = report.iter().filter(|outcome| outcome.result.is_err()).count();
+    let summary = json!({
+        "converted": report.len() - failed,
+        "failed": failed,
+        "elapsed_ms": elapsed.as_millis() as u64,
+        "files": files,
+    });
+    serde_json::to_writer_pretty(&mut write, &summary)?;
+    writeln!(write)
+}
+
+/// The search index of a batch run, one JSON array with the entry of
+/// every converted article, for the site's client-side search.
+pub fn write_search_index(mut write: impl Write, report: &[Outcome]) -> std::io::Result<()> {
+    let entries: Vec<_> = report
+        .iter()
+        .filter(|outcome| outcome.result.is_ok())
+        .filter_map(|outcome| outcome.search_entry.as_ref())
+        .collect();
+    serde_json::to_writer(&mut write, &entries)?;
+    writeln!(write)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn mirrored_tree() {
+        let dir = std::env::temp_dir().join(format!("batch-{}", std::process::id()));
+        let (meta, out) = (dir.join("meta"), dir.join("out"));
+        std::fs::create_dir_all(meta.join("n5")).unwrap();
+
+        std::fs::write(meta.join("n5/a.tex"), r"abstract=Como \cite{Key}.\par").unwrap();
+        std::fs::write(
+            meta.join("n5/a.bib"),
+            "@book{Key, author = {Cunha, E.}, title = {Os sertões}, year = {1902}}",
+        )
+        .unwrap();
+        std::fs::write(meta.join("n5/b.tex"), r"abstract=Como \cite{Outra}.\par").unwrap();
+        std::fs::write(meta.join("c.tex"), r"title=Sertão\par").unwrap();
+        std::fs::write(meta.join("README.md"), "# Metadados\n").unwrap();
+
+        let date = chrono::DateTime::parse_from_rfc3339("2022-06-01T00:00:00Z").unwrap();
+        let options = BatchOptions {
+            registry: &Registry::default(),
+            options: &WriteOptions::default(),
+            date: Some(date.into()),
+            newline: Newline::Lf,
+            bib: None,
+            bib_file: None,
+            search_index: false,
+            extension: "tex",
+            dois: None,
+        };
+        let report = batch(
+            &meta,
+            Destination::<Vec<u8>>::Directory(out.clone()),
+            &options,
+        )
+        .unwrap();
+
+        let c = std::fs::read_to_string(out.join("c.md")).unwrap();
+        let a = std::fs::read_to_string(out.join("n5/a.md")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(report.len(), 3);
+        assert!(report[0].result.is_ok());
+        assert!(report[1].result.is_ok());
+        assert_eq!(
+            report[2].result,
+            Err(Failure::Render("bibliography not found: Outra".to_owned()))
+        );
+        assert!(c.starts_with("---\ntitle: \"Sertão\"\n"));
+        assert!(a.contains("**Resumo:** Como (CUNHA, 1902)."));
+    }
+
+    #[test]
+    fn regeneration() {
+        let dir = std::env::temp_dir().join(format!("batch-regeneration-{}", std::process::id()));
+        let (meta, out) = (dir.join("meta"), dir.join("out"));
+        std::fs::create_dir_all(&meta).unwrap();
+        std::fs::create_dir_all(&out).unwrap();
+        std::fs::write(meta.join("a.tex"), r"title=Sertão novo\par").unwrap();
+        std::fs::write(
+            out.join("a.md"),
+            "---\ntitle: \"Sertão\"\ndate: 2022-06-01T00:00:00+00:00\n---\n\n",
+        )
+        .unwrap();
+
+        let options = BatchOptions {
+            registry: &Registry::default(),
+            options: &WriteOptions::default(),
+            date: None,
+            newline: Newline::Lf,
+            bib: None,
+            bib_file: None,
+            search_index: false,
+            extension: "tex",
+            dois: None,
+        };
+        let report = batch(
+            &meta,
+            Destination::<Vec<u8>>::Directory(out.clone()),
+            &options,
+        )
+        .unwrap();
+        let page = std::fs::read_to_string(out.join("a.md")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(report[0].result.is_ok());
+        assert_eq!(
+            page,
+            "---\ntitle: \"Sertão novo\"\naliases:\n- \"sertao\"\ndate: 2022-06-01T00:00:00+00:00\n---\n\n"
+        );
+    }
+
+    #[test]
+    fn multiplex() {
+        let dir = std::env::temp_dir().join(format!("batch-multiplex-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.tex"), r"title=Sertão\par").unwrap();
+
+        let options = BatchOptions {
+            registry: &Registry::default(),
+            options: &WriteOptions::default(),
+            date: None,
+            newline: Newline::Lf,
+            bib: None,
+            bib_file: None,
+            search_index: true,
+            extension: "tex",
+            dois: None,
+        };
+        let mut output = Vec::new();
+        let report = batch(&dir, Destination::Multiplex(&mut output), &options).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(report[0].result.is_ok());
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with(r#"{"path":"a.md","contents":"---\ntitle: \"Sertão\"\n"#));
+
+        let mut index = Vec::new();
+        write_search_index(&mut index, &report).unwrap();
+        let index: serde_json::Value = serde_json::from_slice(&index).unwrap();
+        assert_eq!(index[0]["title"], "Sertão");
+        assert_eq!(index[0]["slug"], "sertao");
+    }
+
+    #[test]
+    fn summary() {
+        let report = vec![
+            Outcome {
+                source: "meta/a.tex".into(),
+                result: Ok("out/a.md".into()),
+                warnings: vec![ParseWarning::UnknownKey {
+                    key: "volume".to_owned(),
+                    line: 2,
+                    column: 1,
+                }],
+                search_entry: None,
+                elapsed: Duration::from_millis(3),
+            },
+            Outcome {
+                source: "meta/b.tex".into(),
+                result: Err(Failure::Render("bibliography not found: Outra".to_owned())),
+                warnings: Vec::new(),
+                search_entry: None,
+                elapsed: Duration::from_millis(1),
+            },
+        ];
+        let mut output = Vec::new();
+        write_summary(&mut output, &report, Duration::from_millis(5)).unwrap();
+        let summary: serde_json::Value = serde_json::from_slice(&output).unwrap();
+
+        assert_eq!(summary["converted"], 1);
+        assert_eq!(summary["failed"], 1);
+        assert_eq!(
+            summary["files"][0],
+            json!({
+                "source": "meta/a.tex",
+                "status": "ok",
+                "output": "out/a.md",
+                "warnings": [{
+                    "message": "unknown key volume at line 2, column 1 skipped",
+                    "line": 2,
+                    "column": 1,
+                }],
+                "elapsed_ms": 3,
+            })
+        );
+        assert_eq!(summary["files"][1]["code"], "render");
+        assert_eq!(
+            summary["files"][1]["errors"],
+            json!([{ "message": "bibliography not found: Outra" }])
+        );
+    }
+}
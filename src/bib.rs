@@ -0,0 +1,230 @@
+use nom_bibtex::{Bibliography, Bibtex};
+
+use crate::error::{location, offset, ParseWarning};
+
+// sorted by citation key, so lookups and reports do not depend on hashing
+#[derive(Default)]
+pub struct BibIndex<'b> {
+    entries: Vec<(&'b [u8], &'b Bibliography)>,
+    duplicates: Vec<&'b str>,
+}
+
+impl<'b> BibIndex<'b> {
+    pub fn new(bib: &'b Bibtex) -> Self {
+        let mut entries: Vec<_> = bib
+            .bibliographies()
+            .iter()
+            .map(|b| (b.citation_key().as_bytes(), b))
+            .collect();
+        // stable, so the first of several entries with the same key wins
+        entries.sort_by_key(|(key, _)| *key);
+
+        let mut duplicates = Vec::new();
+        entries.dedup_by(|(key, b), (previous, _)| {
+            let duplicate = key == previous;
+            if duplicate && duplicates.last() != Some(&b.citation_key()) {
+                duplicates.push(b.citation_key());
+            }
+            duplicate
+        });
+
+        Self {
+            entries,
+            duplicates,
+        }
+    }
+
+    // a DOI also finds the entry resolve_dois added for it
+    pub fn get(&self, key: &[u8]) -> Option<&'b Bibliography> {
+        let find = |key: &[u8]| {
+            self.entries
+                .binary_search_by_key(&key, |(k, _)| k)
+                .ok()
+                .map(|i| self.entries[i].1)
+        };
+        find(key).or_else(|| match is_doi(key) {
+            true => find(doi_key(key).as_bytes()),
+            false => None,
+        })
+    }
+
+    pub fn duplicates(&self) -> &[&'b str] {
+        &self.duplicates
+    }
+}
+
+// some authors cite by DOI, e.g. \cite{10.1590/1982-2553202145763}
+pub fn is_doi(key: &[u8]) -> bool {
+    let registrant = match key.strip_prefix(b"10.") {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let digits = registrant.iter().take_while(|c| c.is_ascii_digit()).count();
+    matches!(&registrant[digits..], [b'/', suffix @ ..] if !suffix.is_empty()) && digits >= 4
+}
+
+// the citation key of a work looked up by DOI; BibTeX keys cannot hold
+// the slashes and parentheses of a DOI
+pub fn doi_key(doi: &[u8]) -> String {
+    let key = doi.iter().map(|c| match c.is_ascii_alphanumeric() {
+        true => *c as char,
+        false => '_',
+    });
+    "doi_".chars().chain(key).collect()
+}
+
+// finds works cited by DOI that no bibliography has, e.g. from doi.org with
+// the online feature; the entry may use any citation key
+pub trait DoiLookup: Sync {
+    fn bibtex(&self, doi: &[u8]) -> std::io::Result<String>;
+}
+
+// adds to `bib` an entry for every DOI of `cited`, keys of the metadata
+// `input`, missing from it; DOIs that cannot be looked up are still
+// rendered as links, with a warning
+pub fn resolve_dois<'k>(
+    bib: &mut String,
+    input: &[u8],
+    cited: impl IntoIterator<Item = &'k [u8]>,
+    lookup: &dyn DoiLookup,
+) -> Vec<ParseWarning> {
+    let mut missing: Vec<_> = match Bibtex::parse(bib) {
+        Ok(parsed) => {
+            let index = BibIndex::new(&parsed);
+            cited
+                .into_iter()
+                .filter(|key| is_doi(key) && index.get(key).is_none())
+                .collect()
+        }
+        // reported when the bibliography is read for the page
+        Err(_) => return Vec::new(),
+    };
+    missing.sort_unstable();
+    missing.dedup();
+
+    let mut failed = Vec::new();
+    for doi in missing {
+        let entry = lookup.bibtex(doi).and_then(|entry| {
+            let invalid =
+                |reason: String| std::io::Error::new(std::io::ErrorKind::InvalidData, reason);
+            let entry = rekeyed(&entry, &doi_key(doi))
+                .ok_or_else(|| invalid("not a BibTeX entry".to_owned()))?;
+            // one bad entry must not take the whole bibliography down
+            Bibtex::parse(&entry).map_err(|err| invalid(format!("{:?}", err)))?;
+            Ok(entry)
+        });
+        match entry {
+            Ok(entry) => {
+                bib.push('\n');
+                bib.push_str(&entry);
+            }
+            Err(err) => {
+                let (line, column) = location(input, offset(input, doi));
+                failed.push(ParseWarning::UnresolvedDoi {
+                    doi: String::from_utf8_lossy(doi).into_owned(),
+                    reason: err.to_string(),
+                    line,
+                    column,
+                });
+            }
+        }
+    }
+    failed
+}
+
+// the first entry of `bibtex` with its citation key replaced by `key`
+fn rekeyed(bibtex: &str, key: &str) -> Option<String> {
+    let at = bibtex.find('@')?;
+    let open = at + bibtex[at..].find(['{', '('])?;
+    let comma = open + bibtex[open..].find(',')?;
+    Some(format!("{}{}{}", &bibtex[at..=open], key, &bibtex[comma..]))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn lookup() {
+        let bib = Bibtex::parse(
+            r#"
+            @book{EcCUNHA1902sertoes, title = {Os sertoes}, year = {1902}}
+            @book{EcBAKHTIN2003Estetica, title = {Estetica}, year = {2003}}
+            @book{EcCUNHA1902sertoes, title = {Repetido}, year = {1903}}
+            @book{EcALBUQUERQUE2013Nordestino, title = {Nordestino}}
+            @book{EcALBUQUERQUE2013Nordestino, title = {Repetido}}
+            "#,
+        )
+        .unwrap();
+        let index = BibIndex::new(&bib);
+
+        let title = |key: &[u8]| {
+            index.get(key).map(|b| {
+                b.tags()
+                    .iter()
+                    .find(|(k, _)| k == "title")
+                    .unwrap()
+                    .1
+                    .clone()
+            })
+        };
+
+        assert_eq!(title(b"EcCUNHA1902sertoes").as_deref(), Some("Os sertoes"));
+        assert_eq!(title(b"EcBAKHTIN2003Estetica").as_deref(), Some("Estetica"));
+        assert_eq!(title(b"EcMOTTA2013analise"), None);
+        assert_eq!(
+            index.duplicates(),
+            ["EcALBUQUERQUE2013Nordestino", "EcCUNHA1902sertoes"]
+        );
+    }
+
+    #[test]
+    fn doi() {
+        assert!(is_doi(b"10.1590/1982-2553202145763"));
+        assert!(is_doi(b"10.1002/(SICI)1097-4571"));
+        assert!(!is_doi(b"10.15/x"));
+        assert!(!is_doi(b"10.1590/"));
+        assert!(!is_doi(b"EcCUNHA1902sertoes"));
+    }
+    struct Found;
+
+    impl DoiLookup for Found {
+        fn bibtex(&self, doi: &[u8]) -> std::io::Result<String> {
+            match doi {
+                b"10.1590/1982-2553" => {
+                    Ok(" @article{Santos_2004, title={Sociologia}, year={2004}}".to_owned())
+                }
+                _ => Err(std::io::ErrorKind::NotFound.into()),
+            }
+        }
+    }
+
+    #[test]
+    fn resolved_dois() {
+        let input = "abstract=Como visto \\cite{EcCUNHA1902sertoes}, \\cite{10.1590/1982-2553}\n\\cite{10.1590/1982-2553} e \\cite{10.1590/sem-registro}.";
+        let cited = input
+            .split(['{', '}'])
+            .skip(1)
+            .step_by(2)
+            .map(str::as_bytes);
+        let mut bib = "@book{EcCUNHA1902sertoes, title = {Os sertoes}}".to_owned();
+
+        let failed = resolve_dois(&mut bib, input.as_bytes(), cited, &Found);
+
+        assert_eq!(
+            failed,
+            [ParseWarning::UnresolvedDoi {
+                doi: "10.1590/sem-registro".to_owned(),
+                reason: "entity not found".to_owned(),
+                line: 2,
+                column: 34,
+            }]
+        );
+        assert!(bib.ends_with("\n@article{doi_10_1590_1982_2553, title={Sociologia}, year={2004}}"));
+        let parsed = Bibtex::parse(&bib).unwrap();
+        let index = BibIndex::new(&parsed);
+        assert!(index.get(b"10.1590/1982-2553").is_some());
+        assert!(index.get(b"10.1590/sem-registro").is_none());
+    }
+}
@@ -0,0 +1,218 @@
+use std::io::Write;
+
+use serde::Deserialize;
+
+use crate::{
+    bib::{is_doi, BibIndex},
+    metadata::{keyword_list, Metadata},
+};
+
+// mirrors the journal's author guidelines, e.g. in a profile:
+// [profile.galo.check]
+// max_title = 200
+// keywords = [3, 6]
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CheckOptions {
+    // every citation key of an issue starts with it, e.g. Ec
+    pub key_prefix: Option<String>,
+    // in characters
+    pub max_title: Option<usize>,
+    // inclusive ranges
+    pub keywords: Option<(usize, usize)>,
+    pub abstract_words: Option<(usize, usize)>,
+    pub years: Option<(u32, u32)>,
+    // empty accepts any semester
+    pub semesters: Vec<u32>,
+}
+
+fn number(value: &[u8]) -> Option<u32> {
+    std::str::from_utf8(value).ok()?.trim().parse().ok()
+}
+
+// writes one line per problem and returns whether any was found
+pub fn check(
+    metadata: &Metadata,
+    options: &CheckOptions,
+    mut write: impl Write,
+) -> std::io::Result<bool> {
+    let mut problems = false;
+
+    if let (Some(prefix), Some(r#abstract)) = (&options.key_prefix, &metadata.r#abstract) {
+        for key in r#abstract.citation_keys() {
+            if !key.starts_with(prefix.as_bytes()) && !is_doi(key) {
+                problems = true;
+                writeln!(
+                    write,
+                    "abstract: citation key {} does not start with the issue prefix {}",
+                    String::from_utf8_lossy(key),
+                    prefix
+                )?;
+            }
+        }
+    }
+
+    if let (Some(max), Some(title)) = (options.max_title, metadata.title) {
+        let len = String::from_utf8_lossy(title).trim().chars().count();
+        if len > max {
+            problems = true;
+            writeln!(write, "title: {} characters, at most {} allowed", len, max)?;
+        }
+    }
+
+    if let Some((min, max)) = options.keywords {
+        let len = metadata.keywords.map(keyword_list).unwrap_or_default().len();
+        if !(min..=max).contains(&len) {
+            problems = true;
+            writeln!(write, "keywords: {} found, expected {} to {}", len, min, max)?;
+        }
+    }
+
+    if let Some((min, max)) = options.abstract_words {
+        let len = metadata.r#abstract.as_ref().map_or(0, |r#abstract| r#abstract.words());
+        if !(min..=max).contains(&len) {
+            problems = true;
+            writeln!(write, "abstract: {} words, expected {} to {}", len, min, max)?;
+        }
+    }
+
+    if let (Some((min, max)), Some(year)) = (options.years, metadata.year) {
+        match number(year) {
+            Some(year) if (min..=max).contains(&year) => {}
+            _ => {
+                problems = true;
+                writeln!(
+                    write,
+                    "year: {} is not between {} and {}",
+                    String::from_utf8_lossy(year).trim(),
+                    min,
+                    max
+                )?;
+            }
+        }
+    }
+
+    if let (false, Some(semester)) = (options.semesters.is_empty(), metadata.semester) {
+        if !number(semester).is_some_and(|semester| options.semesters.contains(&semester)) {
+            problems = true;
+            writeln!(
+                write,
+                "semester: {} is not one of {:?}",
+                String::from_utf8_lossy(semester).trim(),
+                options.semesters
+            )?;
+        }
+    }
+
+    Ok(problems)
+}
+
+// like check, for citations missing from the bibliography; a DOI needs no
+// entry, it becomes a link
+pub fn citations(
+    metadata: &Metadata,
+    bib: &BibIndex,
+    mut write: impl Write,
+) -> std::io::Result<bool> {
+    let mut problems = false;
+
+    let keys = metadata.r#abstract.iter().flat_map(|r#abstract| r#abstract.citation_keys());
+    let mut reported = Vec::new();
+    for key in keys {
+        if bib.get(key).is_some() || is_doi(key) || reported.contains(&key) {
+            continue;
+        }
+        problems = true;
+        reported.push(key);
+        writeln!(
+            write,
+            "abstract: {} is not in the bibliography",
+            String::from_utf8_lossy(key)
+        )?;
+    }
+
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::metadata::metadata;
+
+    fn run(input: &str, options: &CheckOptions) -> (bool, String) {
+        let (_, metadata) = metadata(input.as_bytes()).unwrap();
+
+        let mut output = Vec::new();
+        let problems = check(&metadata, options, &mut output).unwrap();
+
+        (problems, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn key_prefix() {
+        let input = r#"abstract=Como \cite{EcSANTOS2004Para} e \citeyear{XyCUNHA1902sertoes}.\par"#;
+        let options = CheckOptions {
+            key_prefix: Some("Ec".to_owned()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            run(input, &options),
+            (
+                true,
+                "abstract: citation key XyCUNHA1902sertoes does not start with the issue prefix Ec\n"
+                    .to_owned()
+            )
+        );
+        assert_eq!(run(input, &CheckOptions::default()), (false, String::new()));
+    }
+
+    #[test]
+    fn guidelines() {
+        let options = CheckOptions {
+            max_title: Some(10),
+            keywords: Some((3, 6)),
+            abstract_words: Some((4, 250)),
+            years: Some((1990, 2030)),
+            semesters: vec![1, 2],
+            ..Default::default()
+        };
+
+        let input = r#"title=O sertão e o mar\par abstract=Como \cite{EcSANTOS2004Para} diz.\par keywords=Sertão. Mar.\par year=2202\par semester=3\par"#;
+        assert_eq!(
+            run(input, &options),
+            (
+                true,
+                "title: 16 characters, at most 10 allowed\n\
+                 keywords: 2 found, expected 3 to 6\n\
+                 abstract: 2 words, expected 4 to 250\n\
+                 year: 2202 is not between 1990 and 2030\n\
+                 semester: 3 is not one of [1, 2]\n"
+                    .to_owned()
+            )
+        );
+
+        let input = r#"title=Sertão\par abstract=O sertão~vai virar mar.\par keywords=Sertão. Mar. Seca.\par year=2022\par semester=2\par"#;
+        assert_eq!(run(input, &options), (false, String::new()));
+    }
+
+    #[test]
+    fn missing_citations() {
+        let bib = nom_bibtex::Bibtex::parse(
+            "@book{EcCUNHA1902sertoes, author = {Cunha, E.}, title = {Os sertões}, year = {1902}}",
+        )
+        .unwrap();
+        let bib = BibIndex::new(&bib);
+
+        let input = r#"abstract=\cite{EcCUNHA1902sertoes}, \cite{EcSANTOS2004Para}, \citet{EcSANTOS2004Para} e \cite{10.1590/1982-2553}.\par"#;
+        let (_, metadata) = metadata(input.as_bytes()).unwrap();
+
+        let mut output = Vec::new();
+        assert!(citations(&metadata, &bib, &mut output).unwrap());
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "abstract: EcSANTOS2004Para is not in the bibliography\n"
+        );
+    }
+}
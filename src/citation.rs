@@ -0,0 +1,582 @@
+use std::{borrow::Cow, io::Write};
+
+use nom_bibtex::Bibliography;
+use serde::Deserialize;
+
+use crate::{locale::Language, r#abstract::Emphasis, slug::slug};
+
+#[derive(Debug, PartialEq, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CitationStyle {
+    // (SANTOS, 2004)
+    #[default]
+    Abnt,
+    // (Santos, 2004)
+    AuthorYear,
+}
+
+// what a citation shows, after the command that asks for it
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Kind {
+    // \cite: (SANTOS, 2004)
+    Parenthetical,
+    // \citet: Santos (2004)
+    Textual,
+    // \citeauthor: Santos
+    Author,
+    // \citeyear: (2004)
+    Year,
+    // \citetitle: _Para uma sociologia das ausências_
+    Title,
+}
+
+// how Markdown citations carry the full reference for readers to hover
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Hover {
+    // <abbr title="SANTOS, B. S. ...">(SANTOS, 2004)</abbr>
+    Abbr,
+    // {{< cite key="..." reference="..." >}}(SANTOS, 2004){{< /cite >}}
+    Shortcode,
+}
+
+impl Hover {
+    pub fn wrap(self, key: &[u8], reference: &str, citation: &str) -> String {
+        match self {
+            Hover::Abbr => format!(
+                "<abbr title=\"{}\">{}</abbr>",
+                html_attribute(reference),
+                citation
+            ),
+            Hover::Shortcode => format!(
+                "{{{{< cite key=\"{}\" reference=\"{}\" >}}}}{}{{{{< /cite >}}}}",
+                String::from_utf8_lossy(key),
+                reference.replace('\\', "\\\\").replace('"', "\\\""),
+                citation
+            ),
+        }
+    }
+}
+
+fn html_attribute(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Style {
+    pub citation: CitationStyle,
+    // wraps et al. and the no-date mark
+    pub emphasis: Emphasis,
+    // of the no-date mark
+    pub language: Language,
+}
+
+// between the works of \cite{a,b}: (SANTOS, 2004; MENESES, 2005)
+pub const SEPARATOR: &str = "; ";
+
+pub fn render_citation(entry: &Bibliography, style: &Style, kind: Kind) -> String {
+    group(kind, &[citation_item(entry, style, kind, None)], SEPARATOR)
+}
+
+// one work of a citation, without the parentheses it shares with the
+// others, e.g. SANTOS, 2004; the locator, as in SANTOS, 2004, p. 42, goes
+// after the year
+pub fn citation_item(
+    entry: &Bibliography,
+    style: &Style,
+    kind: Kind,
+    locator: Option<&str>,
+) -> String {
+    let year = match locator {
+        Some(locator) => format!("{}, {}", year(entry, style), locator),
+        None => year(entry, style).into_owned(),
+    };
+    match kind {
+        Kind::Parenthetical => format!("{}, {}", parenthetical_authors(entry, style), year),
+        Kind::Textual => format!("{} ({})", textual_authors(entry, style.emphasis), year),
+        Kind::Author => textual_authors(entry, style.emphasis),
+        Kind::Year => year,
+        Kind::Title => bib_tag(entry, "title")
+            .map(|title| style.emphasis.wrap(title.trim()))
+            .unwrap_or_default(),
+    }
+}
+
+// the works of a single command as one citation
+pub fn group(kind: Kind, items: &[String], separator: &str) -> String {
+    match kind {
+        Kind::Parenthetical | Kind::Year => format!("({})", items.join(separator)),
+        Kind::Textual | Kind::Author | Kind::Title => items.join(separator),
+    }
+}
+
+// order of the reference list
+#[derive(Debug, PartialEq, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Sort {
+    // by the authors, then year and title, after ABNT
+    #[default]
+    Author,
+    // as first cited in the abstract
+    Citation,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct References {
+    pub sort: Sort,
+    // further works of the same authors start with ______. instead
+    pub dash: bool,
+}
+
+// the entry in the reference list, after ABNT; titles are emphasized
+// with `emphasis`, plain text without one, e.g.
+// SANTOS, B. S. Para uma sociologia das ausências. In: Conhecimento
+// prudente para uma vida decente. São Paulo: Cortez, 2004. p. 777-821.
+pub fn render_reference(entry: &Bibliography, emphasis: Option<Emphasis>) -> String {
+    reference(entry, reference_authors(entry).as_deref(), emphasis)
+}
+
+// SANTOS, B. S.; MENESES, M. P.
+fn reference_authors(entry: &Bibliography) -> Option<String> {
+    let authors: Vec<_> = bib_tag(entry, "author")?
+        .split(" AND ")
+        .map(|author| match author.split_once(',') {
+            Some((family, given)) => {
+                format!("{}, {}", family.trim().to_uppercase(), given.trim())
+            }
+            None => author.trim().to_uppercase(),
+        })
+        .collect();
+    Some(match authors.as_slice() {
+        [first, _, _, _, ..] => format!("{} et al", first),
+        authors => authors.join("; "),
+    })
+}
+
+fn reference(entry: &Bibliography, authors: Option<&str>, emphasis: Option<Emphasis>) -> String {
+    let tag = |name| {
+        bib_tag(entry, name)
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    };
+    let highlight = |text: &str| match emphasis {
+        Some(emphasis) => emphasis.wrap(text),
+        None => text.to_owned(),
+    };
+    let joined = |items: &[Option<String>]| {
+        let items: Vec<_> = items.iter().flatten().map(String::as_str).collect();
+        Some(items.join(", ")).filter(|items| !items.is_empty())
+    };
+    let publication = match (tag("address"), tag("publisher")) {
+        (Some(address), Some(publisher)) => Some(format!("{}: {}", address, publisher)),
+        (address, publisher) => address.or(publisher).map(str::to_owned),
+    };
+    let year = tag("year").map(str::to_owned);
+    let pages = tag("pages").map(|pages| format!("p. {}", pages.replace("--", "-")));
+
+    let mut parts = vec![authors.map(str::to_owned)];
+    match entry.entry_type().to_lowercase().as_str() {
+        "article" => {
+            parts.push(tag("title").map(str::to_owned));
+            parts.push(joined(&[
+                tag("journal").map(highlight),
+                tag("address").map(str::to_owned),
+                tag("volume").map(|volume| format!("v. {}", volume)),
+                tag("number").map(|number| format!("n. {}", number)),
+                pages,
+                year,
+            ]));
+        }
+        "incollection" | "inbook" | "inproceedings" => {
+            parts.push(tag("title").map(str::to_owned));
+            parts.push(tag("booktitle").map(|book| format!("In: {}", highlight(book))));
+            parts.push(joined(&[publication, year]));
+            parts.push(pages);
+        }
+        kind @ ("phdthesis" | "mastersthesis") => {
+            let degree = match kind {
+                "phdthesis" => "Tese (Doutorado)",
+                _ => "Dissertação (Mestrado)",
+            };
+            parts.push(tag("title").map(highlight));
+            parts.push(year.clone());
+            let degree = match tag("school") {
+                Some(school) => format!("{} – {}", degree, school),
+                None => degree.to_owned(),
+            };
+            parts.push(joined(&[
+                Some(degree),
+                tag("address").map(str::to_owned),
+                year,
+            ]));
+        }
+        // books and anything else
+        _ => {
+            parts.push(tag("title").map(highlight));
+            parts.push(tag("edition").map(|edition| format!("{} ed", edition)));
+            parts.push(joined(&[publication, year]));
+        }
+    }
+    parts.push(tag("url").map(|url| format!("Disponível em: {}", url)));
+    parts.push(tag("doi").map(|doi| format!("DOI: {}", doi)));
+
+    let parts: Vec<_> = parts
+        .iter()
+        .flatten()
+        .map(|part| part.trim_end_matches('.'))
+        .collect();
+    format!("{}.", parts.join(". "))
+}
+
+// the cited entries, one paragraph each, in markdown
+pub fn write_references(
+    mut write: impl Write,
+    mut entries: Vec<&Bibliography>,
+    options: &References,
+    emphasis: Emphasis,
+) -> std::io::Result<()> {
+    if options.sort == Sort::Author {
+        // accents and case only break ties, as in an index
+        entries.sort_by_cached_key(|entry| {
+            let heading = reference_authors(entry)
+                .or_else(|| bib_tag(entry, "title").map(str::to_owned))
+                .unwrap_or_default();
+            let year = bib_tag(entry, "year").unwrap_or_default().trim().to_owned();
+            let title = bib_tag(entry, "title").map(slug).unwrap_or_default();
+            (slug(&heading), year, title, heading)
+        });
+    }
+
+    let mut previous = None;
+    for (i, entry) in entries.into_iter().enumerate() {
+        let authors = reference_authors(entry);
+        let repeated = options.dash && authors.is_some() && authors == previous;
+        let shown = match repeated {
+            // escaped so markdown does not read emphasis or a rule
+            true => Some(r"\_\_\_\_\_\_"),
+            false => authors.as_deref(),
+        };
+        if i > 0 {
+            write.write_all(b"\n\n")?;
+        }
+        write.write_all(reference(entry, shown, Some(emphasis)).as_bytes())?;
+        previous = authors;
+    }
+
+    Ok(())
+}
+
+fn bib_tag<'b>(bib: &'b Bibliography, name: &str) -> Option<&'b str> {
+    bib.tags()
+        .iter()
+        .find_map(|(k, v)| if k == name { Some(v.as_str()) } else { None })
+}
+
+fn year<'b>(bib: &'b Bibliography, style: &Style) -> Cow<'b, str> {
+    match bib_tag(bib, "year") {
+        Some(year) => Cow::Borrowed(year.trim()),
+        None => Cow::Owned(style.emphasis.wrap(&style.language.message("no-date"))),
+    }
+}
+
+fn family_names(bib: &Bibliography) -> Vec<&str> {
+    match bib_tag(bib, "author") {
+        Some(authors) => authors
+            .split(" AND ")
+            .map(|a| a.split(',').next().unwrap().trim())
+            .collect(),
+        None => vec![bib_tag(bib, "title")
+            .map(|title| title.split(' ').next().unwrap())
+            .unwrap_or("")],
+    }
+}
+
+// only the names are cased, et al. stays as is
+fn parenthetical_authors(bib: &Bibliography, style: &Style) -> String {
+    let names: Vec<_> = family_names(bib)
+        .into_iter()
+        .map(|name| match style.citation {
+            CitationStyle::Abnt => name.to_uppercase(),
+            CitationStyle::AuthorYear => capitalize(name),
+        })
+        .collect();
+    if names.len() > 3 {
+        format!("{}, {}", names[0], style.emphasis.wrap("et al."))
+    } else {
+        names.join("; ")
+    }
+}
+
+fn textual_authors(bib: &Bibliography, emphasis: Emphasis) -> String {
+    let names: Vec<_> = family_names(bib).into_iter().map(capitalize).collect();
+    match names.as_slice() {
+        [] => String::new(),
+        [name] => name.clone(),
+        [first, _, _, _, ..] => format!("{} {}", first, emphasis.wrap("et al.")),
+        [rest @ .., last] => format!("{} e {}", rest.join(", "), last),
+    }
+}
+
+// SANTOS, in the bibliography style, is written Santos in running text
+fn capitalize(name: &str) -> String {
+    let words: Vec<String> = name
+        .split(' ')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first
+                    .to_uppercase()
+                    .chain(chars.flat_map(char::to_lowercase))
+                    .collect(),
+                None => String::new(),
+            }
+        })
+        .collect();
+    words.join(" ")
+}
+
+#[cfg(test)]
+mod test {
+
+    use nom_bibtex::Bibtex;
+
+    use super::*;
+
+    const BIB: &str = r#"
+        @incollection{EcSANTOS2004Para,
+            author = {SANTOS, B. S.},
+            title  = {Para uma sociologia das ausências},
+            year   = {2004}
+        }
+        @book{EcMENESES2009Epistemologias,
+            author = {Meneses, M. P. AND Santos, B. S.},
+            title  = {Epistemologias do Sul},
+            year   = {2009}
+        }
+        @book{EcMUITOS2010,
+            author = {Um, A. AND Dois, B. AND Tres, C. AND Quatro, D.},
+            title  = {Obra coletiva}
+        }
+        @article{EcMOTTA2013analise,
+            author  = {Motta, Luiz Gonzaga},
+            title   = {Análise crítica da narrativa},
+            journal = {Revista Galáxia},
+            volume  = {13},
+            number  = {2},
+            pages   = {10--20},
+            year    = {2013}
+        }"#;
+
+    #[test]
+    fn kinds() {
+        let bib = Bibtex::parse(BIB).unwrap();
+        let santos = &bib.bibliographies()[0];
+        let style = Style::default();
+
+        assert_eq!(
+            render_citation(santos, &style, Kind::Parenthetical),
+            "(SANTOS, 2004)"
+        );
+        assert_eq!(
+            render_citation(santos, &style, Kind::Textual),
+            "Santos (2004)"
+        );
+        assert_eq!(render_citation(santos, &style, Kind::Author), "Santos");
+        assert_eq!(render_citation(santos, &style, Kind::Year), "(2004)");
+        assert_eq!(
+            render_citation(santos, &style, Kind::Title),
+            "_Para uma sociologia das ausências_"
+        );
+
+        let item = |kind| citation_item(santos, &style, kind, Some("p. 42"));
+        assert_eq!(item(Kind::Parenthetical), "SANTOS, 2004, p. 42");
+        assert_eq!(item(Kind::Textual), "Santos (2004, p. 42)");
+        assert_eq!(item(Kind::Year), "2004, p. 42");
+    }
+
+    #[test]
+    fn several_authors() {
+        let bib = Bibtex::parse(BIB).unwrap();
+        let style = Style {
+            citation: CitationStyle::AuthorYear,
+            ..Default::default()
+        };
+
+        let two = &bib.bibliographies()[1];
+        assert_eq!(
+            render_citation(two, &style, Kind::Parenthetical),
+            "(Meneses; Santos, 2009)"
+        );
+        assert_eq!(
+            render_citation(two, &style, Kind::Textual),
+            "Meneses e Santos (2009)"
+        );
+
+        let four = &bib.bibliographies()[2];
+        assert_eq!(
+            render_citation(four, &style, Kind::Parenthetical),
+            "(Um, _et al._, _s.d._)"
+        );
+        assert_eq!(render_citation(four, &style, Kind::Author), "Um _et al._");
+
+        let style = Style::default();
+        assert_eq!(
+            render_citation(four, &style, Kind::Parenthetical),
+            "(UM, _et al._, _s.d._)"
+        );
+    }
+
+    #[test]
+    fn grouped() {
+        let bib = Bibtex::parse(BIB).unwrap();
+        let entries = bib.bibliographies();
+        let style = Style::default();
+        let items = |kind| {
+            let works = [&entries[0], &entries[3]];
+            let items: Vec<_> = works
+                .iter()
+                .map(|e| citation_item(e, &style, kind, None))
+                .collect();
+            group(kind, &items, SEPARATOR)
+        };
+
+        assert_eq!(items(Kind::Parenthetical), "(SANTOS, 2004; MOTTA, 2013)");
+        assert_eq!(items(Kind::Textual), "Santos (2004); Motta (2013)");
+        assert_eq!(items(Kind::Year), "(2004; 2013)");
+        let items = [citation_item(&entries[0], &style, Kind::Author, None)];
+        assert_eq!(group(Kind::Author, &items, " e "), "Santos");
+    }
+
+    #[test]
+    fn references() {
+        let bib = Bibtex::parse(BIB).unwrap();
+        let entries = bib.bibliographies();
+
+        assert_eq!(
+            render_reference(&entries[0], None),
+            "SANTOS, B. S. Para uma sociologia das ausências. 2004."
+        );
+        assert_eq!(
+            render_reference(&entries[1], None),
+            "MENESES, M. P.; SANTOS, B. S. Epistemologias do Sul. 2009."
+        );
+        assert_eq!(
+            render_reference(&entries[2], None),
+            "UM, A. et al. Obra coletiva."
+        );
+        assert_eq!(
+            render_reference(&entries[3], None),
+            "MOTTA, Luiz Gonzaga. Análise crítica da narrativa. \
+             Revista Galáxia, v. 13, n. 2, p. 10-20, 2013."
+        );
+    }
+
+    #[test]
+    fn hover() {
+        let reference = r#"SANTOS, B. S. "Para" & <outros>. 2004."#;
+
+        assert_eq!(
+            Hover::Abbr.wrap(b"EcSANTOS2004Para", reference, "(SANTOS, 2004)"),
+            "<abbr title=\"SANTOS, B. S. &quot;Para&quot; &amp; &lt;outros&gt;. 2004.\">(SANTOS, 2004)</abbr>"
+        );
+        assert_eq!(
+            Hover::Shortcode.wrap(b"EcSANTOS2004Para", "SANTOS, B. S. 2004.", "(SANTOS, 2004)"),
+            r#"{{< cite key="EcSANTOS2004Para" reference="SANTOS, B. S. 2004." >}}(SANTOS, 2004){{< /cite >}}"#
+        );
+    }
+
+    #[test]
+    fn entry_types() {
+        let bib = Bibtex::parse(
+            r#"
+            @book{EcCUNHA1902sertoes,
+                author    = {Cunha, Euclides da},
+                title     = {Os sertões},
+                edition   = {2},
+                address   = {Rio de Janeiro},
+                publisher = {Laemmert},
+                year      = {1903}
+            }
+            @incollection{EcSANTOS2004Para,
+                author    = {Santos, B. S.},
+                title     = {Para uma sociologia das ausências},
+                booktitle = {Conhecimento prudente para uma vida decente},
+                address   = {São Paulo},
+                publisher = {Cortez},
+                pages     = {777--821},
+                year      = {2004}
+            }
+            @mastersthesis{EcLIMA2015,
+                author  = {Lima, A.},
+                title   = {O sertão na TV},
+                school  = {Universidade Federal do Rio Grande do Norte},
+                address = {Natal},
+                year    = {2015},
+                url     = {https://repositorio.ufrn.br/x}
+            }"#,
+        )
+        .unwrap();
+        let entries = bib.bibliographies();
+        let emphasis = Some(Emphasis::Underscore);
+
+        assert_eq!(
+            render_reference(&entries[0], emphasis),
+            "CUNHA, Euclides da. _Os sertões_. 2 ed. Rio de Janeiro: Laemmert, 1903."
+        );
+        assert_eq!(
+            render_reference(&entries[1], emphasis),
+            "SANTOS, B. S. Para uma sociologia das ausências. In: _Conhecimento prudente para \
+             uma vida decente_. São Paulo: Cortez, 2004. p. 777-821."
+        );
+        assert_eq!(
+            render_reference(&entries[2], None),
+            "LIMA, A. O sertão na TV. 2015. Dissertação (Mestrado) – Universidade Federal do \
+             Rio Grande do Norte, Natal, 2015. Disponível em: https://repositorio.ufrn.br/x."
+        );
+    }
+
+    #[test]
+    fn reference_list() {
+        let bib = Bibtex::parse(
+            r#"
+            @book{EcSANTOS2010b, author = {Santos, B. S.}, title = {Depois}, year = {2010}}
+            @book{EcMOTTA2013, author = {Motta, L. G.}, title = {Narrativa}, year = {2013}}
+            @book{EcSANTOS2004, author = {Santos, B. S.}, title = {Antes}, year = {2004}}
+            @book{EcSA2001, author = {Sá, C.}, title = {Sertão}, year = {2001}}"#,
+        )
+        .unwrap();
+        let entries: Vec<_> = bib.bibliographies().iter().collect();
+        let list = |options: References| {
+            let mut output = Vec::new();
+            write_references(&mut output, entries.clone(), &options, Emphasis::Underscore).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(
+            list(References::default()),
+            "MOTTA, L. G. _Narrativa_. 2013.\n\n\
+             SÁ, C. _Sertão_. 2001.\n\n\
+             SANTOS, B. S. _Antes_. 2004.\n\n\
+             SANTOS, B. S. _Depois_. 2010."
+        );
+        assert_eq!(
+            list(References {
+                dash: true,
+                ..Default::default()
+            }),
+            "MOTTA, L. G. _Narrativa_. 2013.\n\n\
+             SÁ, C. _Sertão_. 2001.\n\n\
+             SANTOS, B. S. _Antes_. 2004.\n\n\
+             \\_\\_\\_\\_\\_\\_. _Depois_. 2010."
+        );
+        assert!(list(References {
+            sort: Sort::Citation,
+            ..Default::default()
+        })
+        .starts_with("SANTOS, B. S. _Depois_. 2010."));
+    }
+}
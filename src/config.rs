@@ -0,0 +1,437 @@
+use std::{collections::BTreeMap, path::Path};
+
+use serde::Deserialize;
+
+use crate::{
+    check::CheckOptions,
+    error::ConvertError,
+    front_matter::FrontMatter,
+    grammar::{Key, Multiplicity, Registry, Shape},
+    locale::Language,
+    metadata::{DescriptionStrategy, NoAbstract, WriteOptions},
+    paragraph::Terminator,
+    citation::{CitationStyle, Hover, References},
+    r#abstract::{Emphasis, MathStyle, Replacement, TitleStyle},
+    writer::Cut,
+};
+
+// read from the working directory when no config is given
+pub const DEFAULT: &str = "galo.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // e.g. "~0.1", checked against the running binary
+    pub required_version: Option<String>,
+    pub profile: BTreeMap<String, Profile>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    // written as is into the front matter, e.g. journal = "Galo"
+    pub constants: BTreeMap<String, String>,
+    pub aliases: BTreeMap<String, String>,
+    pub extra_keys: Vec<String>,
+    // checked values of extra keys, e.g. volume = "integer", see
+    // grammar::Shape; keys given a shape need not be in extra_keys
+    pub shapes: BTreeMap<String, Shape>,
+    pub commands: BTreeMap<String, String>,
+    pub citation_style: Option<CitationStyle>,
+    // between the works of \cite{a,b}, "; " by default
+    pub citation_separator: Option<String>,
+    // "abbr" or "shortcode", see citation::Hover
+    pub citation_hover: Option<Hover>,
+    // e.g. [profile.galo.references] with sort = "citation" and dash = true
+    pub references: Option<References>,
+    pub title_style: Option<TitleStyle>,
+    pub language: Option<Language>,
+    pub key_prefix: Option<String>,
+    // what ends a field: "blank-line", "auto" or a literal such as "\\par"
+    pub terminator: Option<Terminator>,
+    // skip unknown keys with a warning
+    pub lenient: bool,
+    // keep unknown keys as extra front matter entries
+    pub keep_unknown: bool,
+    pub check: CheckOptions,
+    pub description: Option<DescriptionStrategy>,
+    pub description_length: Option<usize>,
+    // "character", "word" or "sentence"
+    pub description_cut: Option<Cut>,
+    pub keyword_case: bool,
+    // proper nouns kept capitalized by keyword_case, e.g. ["Euclides", "Cunha"]
+    pub keyword_exceptions: Vec<String>,
+    pub sort_tags: bool,
+    pub image_prefix: Option<String>,
+    // adds a bibliography: field, e.g. "/bib/" gives "/bib/artigo.bib"
+    pub bibliography_prefix: Option<String>,
+    pub emphasis: Option<Emphasis>,
+    pub strip_braces: bool,
+    pub raw_typography: bool,
+    pub smart_quotes: bool,
+    pub inline_html: bool,
+    // "text" or "katex", how $...$ in the abstract is rendered
+    pub math: Option<MathStyle>,
+    pub front_matter: Option<FrontMatter>,
+    pub opengraph: bool,
+    pub jsonld: bool,
+    pub abstract_tex: bool,
+    // description and body of articles without an abstract
+    pub no_abstract: Option<NoAbstract>,
+    pub disabled: Vec<String>,
+    // {front_matter} and {body} are replaced by the generated parts
+    pub template: Option<String>,
+}
+
+impl Config {
+    pub fn parse(input: &str) -> std::io::Result<Self> {
+        toml::from_str(input)
+            .map_err(|err| std::io::Error::other(format!("invalid config: {}", err)))
+    }
+
+    pub fn read(path: &Path) -> std::io::Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn check_version(&self, version: &str) -> std::io::Result<()> {
+        let required = match &self.required_version {
+            Some(required) => required,
+            None => return Ok(()),
+        };
+
+        let requirement = semver::VersionReq::parse(required).map_err(|err| {
+            std::io::Error::other(format!("invalid required_version {}: {}", required, err))
+        })?;
+        let version = semver::Version::parse(version).map_err(std::io::Error::other)?;
+
+        if !requirement.matches(&version) {
+            return Err(std::io::Error::other(format!(
+                "this config requires version {} but this is {}; \
+                 rebuild the tool from the repository before generating pages",
+                required, version
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn profile(&self, name: &str) -> std::io::Result<&Profile> {
+        self.profile
+            .get(name)
+            .ok_or_else(|| std::io::Error::other(format!("unknown profile: {}", name)))
+    }
+}
+
+// the default config is optional, an explicit one is not
+pub fn load(path: Option<&str>) -> Result<Config, ConvertError> {
+    let path = match path {
+        Some(path) => path,
+        None if Path::new(DEFAULT).exists() => DEFAULT,
+        None => return Ok(Config::default()),
+    };
+    let invalid = |source| ConvertError::Config {
+        path: path.to_owned(),
+        source,
+    };
+
+    let config = Config::read(Path::new(path)).map_err(invalid)?;
+    config.check_version(crate::provenance::VERSION).map_err(invalid)?;
+    Ok(config)
+}
+
+// `path` is where `config` was loaded from, for the error
+pub fn profile<'c>(
+    config: &'c Config,
+    path: Option<&str>,
+    name: Option<&str>,
+) -> Result<Option<&'c Profile>, ConvertError> {
+    let name = match name {
+        Some(name) => name,
+        None => return Ok(None),
+    };
+    let profile = config.profile(name).map_err(|source| ConvertError::Config {
+        path: path.unwrap_or(DEFAULT).to_owned(),
+        source,
+    })?;
+    Ok(Some(profile))
+}
+
+// loads the config at `path` and applies its profile `name`, if any
+pub fn apply_profile(
+    path: Option<&str>,
+    name: Option<&str>,
+    registry: &mut Registry,
+    options: &mut WriteOptions,
+) -> Result<(), ConvertError> {
+    let config = load(path)?;
+    if let Some(profile) = profile(&config, path, name)? {
+        profile
+            .apply(registry, options)
+            .map_err(|source| ConvertError::Config {
+                path: path.unwrap_or(DEFAULT).to_owned(),
+                source,
+            })?;
+    }
+    Ok(())
+}
+
+impl Profile {
+    pub fn apply(
+        &self,
+        registry: &mut Registry,
+        options: &mut WriteOptions,
+    ) -> std::io::Result<()> {
+        for name in &self.extra_keys {
+            registry.register(Key::extra(name.clone(), Multiplicity::Many));
+        }
+        for (name, shape) in &self.shapes {
+            if !registry.keys().any(|key| key.name == *name) {
+                registry.register(Key::extra(name.clone(), Multiplicity::Many));
+            }
+            registry.set_shape(name, shape.clone())?;
+        }
+        for (alias, name) in &self.aliases {
+            registry.alias(alias.clone(), name)?;
+        }
+        for (name, template) in &self.commands {
+            registry.register_command(name.clone(), Box::new(Replacement(template.clone())));
+        }
+
+        if let Some(terminator) = &self.terminator {
+            registry.set_terminator(terminator.clone());
+        }
+        if self.lenient {
+            registry.set_lenient(true);
+        }
+        if self.keep_unknown {
+            registry.set_keep_unknown(true);
+        }
+
+        options.opengraph |= self.opengraph;
+        options.jsonld |= self.jsonld;
+        options.abstract_tex |= self.abstract_tex;
+        options.strip_braces |= self.strip_braces;
+        options.raw_typography |= self.raw_typography;
+        options.smart_quotes |= self.smart_quotes;
+        options.inline_html |= self.inline_html;
+        if let Some(math) = self.math {
+            options.math = math;
+        }
+        options.sort_tags |= self.sort_tags;
+        options.disabled.extend(self.disabled.iter().cloned());
+        if let Some(citation_style) = self.citation_style {
+            options.citation_style = citation_style;
+        }
+        if self.citation_separator.is_some() {
+            options.citation_separator = self.citation_separator.clone();
+        }
+        if self.citation_hover.is_some() {
+            options.citation_hover = self.citation_hover;
+        }
+        if self.references.is_some() {
+            options.references = self.references;
+        }
+        if let Some(title_style) = self.title_style {
+            options.title_style = title_style;
+        }
+        if let Some(no_abstract) = &self.no_abstract {
+            options.no_abstract = no_abstract.clone();
+        }
+        if let Some(description) = self.description {
+            options.description = description;
+        }
+        if self.description_length.is_some() {
+            options.description_length = self.description_length;
+        }
+        if let Some(cut) = self.description_cut {
+            options.description_cut = cut;
+        }
+        if let Some(language) = self.language {
+            options.language = language;
+        }
+        options
+            .constants
+            .extend(self.constants.iter().map(|(k, v)| (k.clone(), v.clone())));
+        if self.keyword_case {
+            options.keyword_exceptions = Some(self.keyword_exceptions.clone());
+        }
+        if let Some(front_matter) = self.front_matter {
+            options.front_matter = front_matter;
+        }
+        if let Some(emphasis) = self.emphasis {
+            options.emphasis = emphasis;
+        }
+        if self.image_prefix.is_some() {
+            options.image_prefix = self.image_prefix.clone();
+        }
+        if self.bibliography_prefix.is_some() {
+            options.bibliography_prefix = self.bibliography_prefix.clone();
+        }
+        if self.template.is_some() {
+            options.template = self.template.clone();
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::{bib::BibIndex, metadata::metadata_with};
+
+    const CONFIG: &str = r#"
+        [profile.galo]
+        extra_keys = ["funding"]
+        aliases = { titulo = "title" }
+        citation_style = "author-year"
+        description_length = 100
+        description_cut = "word"
+        template = "{front_matter}\n{body}<!-- galo -->\n"
+
+        [profile.galo.constants]
+        journal = "Galo"
+
+        [profile.galo.check]
+        keywords = [3, 6]
+        semesters = [1, 2]
+
+        [profile.outra]
+        terminator = "blank-line"
+        lenient = true
+        keep_unknown = true
+        front_matter = "toml"
+        title_style = "bold"
+        language = "en"
+        description = "first-sentence"
+        opengraph = true
+        disabled = ["description"]
+
+        [profile.outra.no_abstract]
+        description = "{title}"
+
+        [profile.outra.no_abstract.sections."Apresentação"]
+        body = "Apresentação do dossiê {title}."
+    "#;
+
+    #[test]
+    fn profiles() {
+        let config = Config::parse(CONFIG).unwrap();
+
+        assert_eq!(config.profile("galo").unwrap().check.keywords, Some((3, 6)));
+        assert_eq!(
+            config.profile("galo").unwrap().description_length,
+            Some(100)
+        );
+        assert_eq!(
+            config.profile("galo").unwrap().description_cut,
+            Some(Cut::Word)
+        );
+        assert!(config.profile("outra").unwrap().opengraph);
+        assert!(config.profile("outra").unwrap().lenient);
+        assert!(config.profile("outra").unwrap().keep_unknown);
+        let no_abstract = config.profile("outra").unwrap().no_abstract.as_ref().unwrap();
+        assert_eq!(no_abstract.description.as_deref(), Some("{title}"));
+        assert_eq!(
+            no_abstract.sections["Apresentação"].body.as_deref(),
+            Some("Apresentação do dossiê {title}.")
+        );
+        assert_eq!(
+            config.profile("outra").unwrap().terminator,
+            Some(Terminator::BlankLine)
+        );
+        assert_eq!(
+            config.profile("outra").unwrap().front_matter,
+            Some(FrontMatter::Toml)
+        );
+        assert_eq!(
+            config.profile("outra").unwrap().language,
+            Some(Language::En)
+        );
+        assert_eq!(
+            config.profile("outra").unwrap().description,
+            Some(DescriptionStrategy::FirstSentence)
+        );
+        assert_eq!(
+            config.profile("outra").unwrap().title_style,
+            Some(TitleStyle::Bold)
+        );
+        assert!(config.profile("nenhuma").is_err());
+    }
+
+    #[test]
+    fn required_version() {
+        let config = Config::parse("required_version = \"~0.1\"\n").unwrap();
+
+        assert!(config.check_version("0.1.3").is_ok());
+        assert!(config.check_version("0.2.0").is_err());
+        assert!(Config::default().check_version("0.2.0").is_ok());
+        assert!(Config::parse("required_version = \"abc\"\n")
+            .unwrap()
+            .check_version("0.1.0")
+            .is_err());
+    }
+
+    #[test]
+    fn unknown_field() {
+        assert!(Config::parse("[profile.galo]\ncitation = \"abnt\"\n").is_err());
+    }
+
+    #[test]
+    fn apply_profile() {
+        let config = Config::parse(CONFIG).unwrap();
+
+        let mut registry = Registry::default();
+        let mut options = WriteOptions::default();
+        config
+            .profile("galo")
+            .unwrap()
+            .apply(&mut registry, &mut options)
+            .unwrap();
+
+        let input = r#"titulo=Sertão\par funding=CNPq\par"#;
+        let (_, metadata) = metadata_with(input.as_bytes(), &registry).unwrap();
+
+        let date = chrono::DateTime::parse_from_rfc3339("2022-06-01T00:00:00Z").unwrap();
+        let mut output = Vec::new();
+        metadata
+            .wtite_to(&mut output, &BibIndex::default(), date.into(), &options)
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "---\ntitle: \"Sertão\"\ndate: 2022-06-01T00:00:00+00:00\nfunding: \"CNPq\"\njournal: \"Galo\"\n---\n\n<!-- galo -->\n"
+        );
+    }
+
+    #[test]
+    fn shapes() {
+        let config = Config::parse(
+            r#"
+            [profile.galo]
+            extra_keys = ["funding"]
+            shapes = { volume = "integer", funding = { one-of = ["CNPq", "CAPES"] } }
+
+            [profile.errada]
+            shapes = { year = "integer" }
+            "#,
+        )
+        .unwrap();
+
+        let mut registry = Registry::default();
+        let profile = config.profile("galo").unwrap();
+        profile.apply(&mut registry, &mut WriteOptions::default()).unwrap();
+        assert_eq!(registry.shape("volume"), Some(&Shape::Integer));
+        assert_eq!(
+            registry.shape("funding"),
+            Some(&Shape::OneOf(vec!["CNPq".to_owned(), "CAPES".to_owned()]))
+        );
+        assert_eq!(registry.keys().filter(|key| key.name == "funding").count(), 1);
+
+        let profile = config.profile("errada").unwrap();
+        let mut registry = Registry::default();
+        assert!(profile.apply(&mut registry, &mut WriteOptions::default()).is_err());
+        assert!(Config::parse("[profile.a]\nshapes = { volume = \"number\" }\n").is_err());
+    }
+}
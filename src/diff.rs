@@ -0,0 +1,211 @@
+use std::{borrow::Cow, io::Write};
+
+use crate::{
+    bib::BibIndex,
+    author::Author,
+    metadata::Metadata,
+    r#abstract::{Abstract, Format},
+};
+
+fn fields<'a>(metadata: &Metadata<'a>) -> [(&'static str, Option<Cow<'a, [u8]>>); 9] {
+    let sections = metadata.sections();
+    let section = match sections.as_slice() {
+        [] => None,
+        [section] => Some(Cow::Borrowed(*section)),
+        sections => Some(Cow::Owned(sections.join(&b"; "[..]))),
+    };
+
+    [
+        ("title", metadata.title.map(Cow::Borrowed)),
+        ("first_page", metadata.first_page.map(Cow::Borrowed)),
+        ("last_page", metadata.last_page.map(Cow::Borrowed)),
+        ("keywords", metadata.keywords.map(Cow::Borrowed)),
+        ("section", section),
+        ("number", metadata.number.map(Cow::Borrowed)),
+        ("semester", metadata.semester.map(Cow::Borrowed)),
+        ("year", metadata.year.map(Cow::Borrowed)),
+        ("cover", metadata.cover.map(Cow::Borrowed)),
+    ]
+}
+
+pub fn diff(
+    old: &Metadata,
+    new: &Metadata,
+    bib: &BibIndex,
+    mut write: impl Write,
+) -> std::io::Result<bool> {
+    let mut changed = false;
+
+    for ((key, old), (_, new)) in fields(old).into_iter().zip(fields(new)) {
+        let old = old.as_deref().map(<[u8]>::trim_ascii);
+        let new = new.as_deref().map(<[u8]>::trim_ascii);
+        if old == new {
+            continue;
+        }
+
+        changed = true;
+        match (old, new) {
+            (Some(old), Some(new)) => {
+                writeln!(write, "{} changed", key)?;
+                write.write_all(b"- ")?;
+                write.write_all(old)?;
+                write.write_all(b"\n+ ")?;
+                write.write_all(new)?;
+                write.write_all(b"\n")?;
+            }
+            (None, Some(new)) => {
+                writeln!(write, "{} added", key)?;
+                write.write_all(b"+ ")?;
+                write.write_all(new)?;
+                write.write_all(b"\n")?;
+            }
+            (Some(old), None) => {
+                writeln!(write, "{} removed", key)?;
+                write.write_all(b"- ")?;
+                write.write_all(old)?;
+                write.write_all(b"\n")?;
+            }
+            (None, None) => unreachable!(),
+        }
+    }
+
+    let old_authors = old.authors.as_deref().unwrap_or_default();
+    let new_authors = new.authors.as_deref().unwrap_or_default();
+
+    for author in old_authors.iter().filter(|a| !new_authors.contains(a)) {
+        changed = true;
+        write_author(&mut write, "author removed: ", author)?;
+    }
+    for author in new_authors.iter().filter(|a| !old_authors.contains(a)) {
+        changed = true;
+        write_author(&mut write, "author added: ", author)?;
+    }
+
+    let old_abstract = plain_text(old.r#abstract.as_ref(), bib)?;
+    let new_abstract = plain_text(new.r#abstract.as_ref(), bib)?;
+
+    if old_abstract != new_abstract {
+        changed = true;
+        write.write_all(b"abstract changed\n")?;
+        word_diff(&mut write, &old_abstract, &new_abstract)?;
+        write.write_all(b"\n")?;
+    }
+
+    Ok(changed)
+}
+
+fn write_author(mut write: impl Write, label: &str, author: &Author) -> std::io::Result<()> {
+    write.write_all(label.as_bytes())?;
+    write.write_all(author.given.trim_ascii())?;
+    write.write_all(b" ")?;
+    write.write_all(author.family.trim_ascii())?;
+    write.write_all(b"\n")
+}
+
+fn plain_text(
+    r#abstract: Option<&Abstract>,
+    bib: &BibIndex,
+) -> std::io::Result<String> {
+    let mut buf = Vec::new();
+    if let Some(r#abstract) = r#abstract {
+        r#abstract.write_to(&mut buf, bib, Format::PlainText)?;
+    }
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+fn word_diff(mut write: impl Write, old: &str, new: &str) -> std::io::Result<()> {
+    let old: Vec<_> = old.split_whitespace().collect();
+    let new: Vec<_> = new.split_whitespace().collect();
+
+    // lcs[i][j] is the longest common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut first = true;
+    let mut separator = |write: &mut dyn Write| {
+        if !std::mem::take(&mut first) {
+            write.write_all(b" ")?;
+        }
+        Ok::<_, std::io::Error>(())
+    };
+
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            separator(&mut write)?;
+            write.write_all(old[i].as_bytes())?;
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            separator(&mut write)?;
+            write!(write, "[-{}-]", old[i])?;
+            i += 1;
+        } else {
+            separator(&mut write)?;
+            write!(write, "{{+{}+}}", new[j])?;
+            j += 1;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::metadata::metadata;
+
+    fn run(old: &str, new: &str) -> (bool, String) {
+        let (_, old) = metadata(old.as_bytes()).unwrap();
+        let (_, new) = metadata(new.as_bytes()).unwrap();
+
+        let mut output = Vec::new();
+        let changed = diff(&old, &new, &BibIndex::default(), &mut output).unwrap();
+
+        (changed, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn unchanged() {
+        let input = r#"authors=given> Aurora, family> Leão\par title=Sertão\par abstract=Um resumo.\par"#;
+
+        assert_eq!(run(input, input), (false, String::new()));
+    }
+
+    #[test]
+    fn fields_and_authors() {
+        let (changed, output) = run(
+            r#"authors=given> Aurora, family> Leão\par title=Sertão\par year=2021\par"#,
+            r#"authors=given> Aurora, family> Leão. given> Fulano, family> Tal\par title=O sertão\par section=Dossiê\par"#,
+        );
+
+        assert!(changed);
+        assert_eq!(
+            output,
+            "title changed\n- Sertão\n+ O sertão\nsection added\n+ Dossiê\nyear removed\n- 2021\nauthor added: Fulano Tal\n"
+        );
+    }
+
+    #[test]
+    fn abstract_words() {
+        let (changed, output) = run(
+            r#"abstract=O objeto deste artigo é a série.\par"#,
+            r#"abstract=O objeto do artigo é a \textit{série}.\par"#,
+        );
+
+        assert!(changed);
+        assert_eq!(
+            output,
+            "abstract changed\nO objeto [-deste-] {+do+} artigo é a série.\n"
+        );
+    }
+}
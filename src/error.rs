@@ -0,0 +1,407 @@
+use nom::error::ErrorKind;
+use thiserror::Error;
+
+// a metadata file that does not parse, with the field being read
+#[derive(Debug, PartialEq, Error)]
+pub enum ParseError {
+    #[error("invalid {field} field at line {line}, column {column}")]
+    Field {
+        field: String,
+        line: usize,
+        column: usize,
+    },
+    // a value the profile does not allow, see grammar::Shape
+    #[error("invalid {field} field at line {line}, column {column}: expected {expected}")]
+    Shape {
+        field: String,
+        expected: String,
+        line: usize,
+        column: usize,
+    },
+    // the file ends inside a field, e.g. a key without its value
+    #[error("unexpected end of input while reading key {field} at line {line}, column {column}")]
+    UnexpectedEnd {
+        field: String,
+        line: usize,
+        column: usize,
+    },
+    #[error("{field} given twice, again at line {line}, column {column}")]
+    Duplicated {
+        field: String,
+        line: usize,
+        column: usize,
+    },
+    #[error("unexpected content at line {line}, column {column}: {near}")]
+    Unexpected {
+        line: usize,
+        column: usize,
+        near: String,
+    },
+    // a registered key whose value parser does not fit its field
+    #[error("the parser of {field} at line {line}, column {column} does not produce its field")]
+    Mismatch {
+        field: String,
+        line: usize,
+        column: usize,
+    },
+    #[error("metadata exceeds the size limits")]
+    TooLarge,
+    #[error("parsing was cancelled")]
+    Cancelled,
+}
+
+// something skipped while parsing in lenient mode
+#[derive(Debug, PartialEq, Error)]
+pub enum ParseWarning {
+    #[error("unknown key {key} at line {line}, column {column} skipped")]
+    UnknownKey {
+        key: String,
+        line: usize,
+        column: usize,
+    },
+    // kept unknown keys would collide with what the page writes itself
+    #[error("key {key} at line {line}, column {column} skipped; the page writes its own {key}")]
+    ReservedKey {
+        key: String,
+        line: usize,
+        column: usize,
+    },
+    // figures and tables do not exist outside the pdf
+    #[error("{command} at line {line}, column {column} dropped from the abstract")]
+    DroppedReference {
+        command: String,
+        line: usize,
+        column: usize,
+    },
+    // images need a prefix to be found by the site
+    #[error(
+        "{command} at line {line}, column {column} dropped from the abstract; \
+         set an image prefix to keep it"
+    )]
+    DroppedImage {
+        command: String,
+        line: usize,
+        column: usize,
+    },
+    // the description ends before a citation it would have split
+    #[error(
+        "description of the abstract at line {line}, column {column} cut short \
+         to keep a citation whole"
+    )]
+    DescriptionCut { line: usize, column: usize },
+    // UTF-8 read as Latin-1 somewhere before it reached us
+    #[error(
+        "suspicious {sequence} in {key} at line {line}, column {column}; \
+         was the text converted with the wrong encoding?"
+    )]
+    Mojibake {
+        key: String,
+        sequence: String,
+        line: usize,
+        column: usize,
+    },
+    // online, a DOI citation missing from the bibliography that could not
+    // be looked up; it is rendered as a link
+    #[error("DOI {doi} at line {line}, column {column} not found: {reason}")]
+    UnresolvedDoi {
+        doi: String,
+        reason: String,
+        line: usize,
+        column: usize,
+    },
+}
+
+impl ParseWarning {
+    pub fn position(&self) -> (usize, usize) {
+        match self {
+            ParseWarning::UnknownKey { line, column, .. }
+            | ParseWarning::ReservedKey { line, column, .. }
+            | ParseWarning::DroppedReference { line, column, .. }
+            | ParseWarning::DroppedImage { line, column, .. }
+            | ParseWarning::DescriptionCut { line, column }
+            | ParseWarning::Mojibake { line, column, .. }
+            | ParseWarning::UnresolvedDoi { line, column, .. } => (*line, *column),
+        }
+    }
+}
+
+// 1-based line and column of `offset`, with columns counted in bytes
+pub(crate) fn location(input: &[u8], offset: usize) -> (usize, usize) {
+    let before = &input[..offset];
+    let line = memchr::memchr_iter(b'\n', before).count() + 1;
+    let column = before.len() - memchr::memrchr(b'\n', before).map_or(0, |pos| pos + 1) + 1;
+    (line, column)
+}
+
+pub(crate) fn offset(input: &[u8], rest: &[u8]) -> usize {
+    let offset = (rest.as_ptr() as usize).wrapping_sub(input.as_ptr() as usize);
+    offset.min(input.len())
+}
+
+impl ParseError {
+    // `field` is the key whose value was being read, if any
+    pub(crate) fn new(
+        input: &[u8],
+        err: nom::Err<nom::error::Error<&[u8]>>,
+        field: Option<String>,
+    ) -> Self {
+        let (rest, kind) = match err {
+            nom::Err::Error(err) | nom::Err::Failure(err) => (err.input, err.code),
+            nom::Err::Incomplete(_) => (&input[input.len()..], ErrorKind::Complete),
+        };
+        let (line, column) = location(input, offset(input, rest));
+        let end = offset(input, rest) == input.len();
+
+        match (kind, field) {
+            (ErrorKind::TooLarge, _) => ParseError::TooLarge,
+            (ErrorKind::Fail, _) => ParseError::Cancelled,
+            // a value parser stopped short of the end of the value
+            (ErrorKind::Eof, _) => ParseError::unexpected(input, rest),
+            (ErrorKind::MapRes, Some(field)) => ParseError::Mismatch {
+                field,
+                line,
+                column,
+            },
+            (ErrorKind::Verify, Some(field)) => ParseError::Duplicated {
+                field,
+                line,
+                column,
+            },
+            (_, Some(field)) if end => ParseError::UnexpectedEnd {
+                field,
+                line,
+                column,
+            },
+            (_, Some(field)) => ParseError::Field {
+                field,
+                line,
+                column,
+            },
+            (_, None) => ParseError::unexpected(input, rest),
+        }
+    }
+
+    pub(crate) fn unexpected(input: &[u8], rest: &[u8]) -> Self {
+        let rest = rest.trim_ascii_start();
+        let (line, column) = location(input, offset(input, rest));
+        let near = String::from_utf8_lossy(&rest[..rest.len().min(40)]);
+        ParseError::Unexpected {
+            line,
+            column,
+            near: near.lines().next().unwrap_or_default().to_owned(),
+        }
+    }
+
+    pub fn position(&self) -> Option<(usize, usize)> {
+        match self {
+            ParseError::Field { line, column, .. }
+            | ParseError::UnexpectedEnd { line, column, .. }
+            | ParseError::Shape { line, column, .. }
+            | ParseError::Duplicated { line, column, .. }
+            | ParseError::Mismatch { line, column, .. }
+            | ParseError::Unexpected { line, column, .. } => Some((*line, *column)),
+            ParseError::TooLarge | ParseError::Cancelled => None,
+        }
+    }
+
+    /// The line of `input` where parsing failed with a caret under the
+    /// column, e.g.
+    ///
+    /// ```text
+    ///   |
+    /// 2 | authors=given> Aurora\par
+    ///   |                      ^
+    /// ```
+    ///
+    /// Long lines, such as abstracts, are cut around the caret.
+    pub fn snippet(&self, input: &[u8]) -> Option<String> {
+        let (line, column) = self.position()?;
+        let text = input.split(|c| *c == b'\n').nth(line - 1)?;
+        let text = text.strip_suffix(b"\r").unwrap_or(text);
+
+        let caret = String::from_utf8_lossy(&text[..(column - 1).min(text.len())])
+            .chars()
+            .count();
+        let chars: Vec<char> = String::from_utf8_lossy(text)
+            .chars()
+            .map(|c| if c.is_whitespace() { ' ' } else { c })
+            .collect();
+
+        let start = caret.saturating_sub(SNIPPET_CONTEXT);
+        let end = chars.len().min(caret + SNIPPET_CONTEXT);
+        let mut shown: String = chars[start..end].iter().collect();
+        let mut caret = caret - start;
+        if start > 0 {
+            shown.insert(0, '…');
+            caret += 1;
+        }
+        if end < chars.len() {
+            shown.push('…');
+        }
+
+        let gutter = " ".repeat(line.to_string().len());
+        Some(format!(
+            "{gutter} |\n{line} | {shown}\n{gutter} | {:caret$}^",
+            "",
+        ))
+    }
+}
+
+// characters shown on each side of the caret
+const SNIPPET_CONTEXT: usize = 40;
+
+// what the binary reports; every kind has its own exit code
+#[derive(Debug, Error)]
+pub enum ConvertError {
+    #[error("{0}")]
+    Usage(String),
+    #[error("{path}: {source}")]
+    Read {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("{path}: {source}")]
+    Write {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    // one error per line, each with its snippet below, see
+    // ParseError::snippet
+    #[error("{}", located(path, errors))]
+    Metadata {
+        path: String,
+        errors: Vec<(ParseError, Option<String>)>,
+    },
+    #[error("{path}: invalid bibliography: {reason}")]
+    Bibliography { path: String, reason: String },
+    #[error("{path}: {source}")]
+    Config {
+        path: String,
+        source: std::io::Error,
+    },
+}
+
+fn located(path: &str, errors: &[(ParseError, Option<String>)]) -> String {
+    let errors: Vec<_> = errors
+        .iter()
+        .map(|(err, snippet)| match snippet {
+            Some(snippet) => format!("{}: {}\n{}", path, err, snippet),
+            None => format!("{}: {}", path, err),
+        })
+        .collect();
+    errors.join("\n")
+}
+
+impl ConvertError {
+    // with the snippet of `input` each error points at
+    pub fn metadata(path: &str, input: &[u8], errors: Vec<ParseError>) -> Self {
+        let errors = errors
+            .into_iter()
+            .map(|err| {
+                let snippet = err.snippet(input);
+                (err, snippet)
+            })
+            .collect();
+        ConvertError::Metadata {
+            path: crate::input::shown(path),
+            errors,
+        }
+    }
+
+    // 1 is left to commands reporting problems, e.g. validate and diff
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ConvertError::Usage(_) => 2,
+            ConvertError::Read { .. } | ConvertError::Write { .. } | ConvertError::Io(_) => 3,
+            ConvertError::Metadata { .. } => 4,
+            ConvertError::Bibliography { .. } => 5,
+            ConvertError::Config { .. } => 6,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn locations() {
+        assert_eq!(location(b"ab\ncd", 0), (1, 1));
+        assert_eq!(location(b"ab\ncd", 4), (2, 2));
+    }
+
+    #[test]
+    fn snippets() {
+        let input = "title=Sertão\\par\r\nauthors=given> Aurora\\par\n".as_bytes();
+        let err = ParseError::Field {
+            field: "authors".to_owned(),
+            line: 2,
+            column: 22,
+        };
+        assert_eq!(
+            err.snippet(input).unwrap(),
+            "  |\n2 | authors=given> Aurora\\par\n  |                      ^"
+        );
+
+        let input = format!("title=Sert\tão {}!", "x".repeat(60));
+        let err = ParseError::Unexpected {
+            line: 1,
+            column: 14,
+            near: String::new(),
+        };
+        assert_eq!(
+            err.snippet(input.as_bytes()).unwrap(),
+            format!(
+                "  |\n1 | title=Sert ão {}…\n  |             ^",
+                "x".repeat(38)
+            )
+        );
+
+        let err = ParseError::Field {
+            field: "abstract".to_owned(),
+            line: 1,
+            column: 62,
+        };
+        assert_eq!(
+            err.snippet(input.as_bytes()).unwrap(),
+            format!("  |\n1 | …{}!\n  |  {:40}^", "x".repeat(54), "")
+        );
+
+        assert_eq!(ParseError::TooLarge.snippet(input.as_bytes()), None);
+    }
+
+    #[test]
+    fn messages() {
+        let err = ParseError::Field {
+            field: "authors".to_owned(),
+            line: 2,
+            column: 9,
+        };
+        assert_eq!(err.to_string(), "invalid authors field at line 2, column 9");
+
+        let err = ConvertError::Metadata {
+            path: "artigo.tex".to_owned(),
+            errors: vec![(err, None)],
+        };
+        assert_eq!(
+            err.to_string(),
+            "artigo.tex: invalid authors field at line 2, column 9"
+        );
+        assert_eq!(err.exit_code(), 4);
+
+        let err = ConvertError::Metadata {
+            path: "artigo.tex".to_owned(),
+            errors: vec![
+                (ParseError::TooLarge, Some("  |\n1 | x\n  | ^".to_owned())),
+                (ParseError::Cancelled, None),
+            ],
+        };
+        assert_eq!(
+            err.to_string(),
+            "artigo.tex: metadata exceeds the size limits\n  |\n1 | x\n  | ^\nartigo.tex: parsing was cancelled"
+        );
+    }
+}
@@ -0,0 +1,584 @@
+use std::{borrow::Cow, io::Write};
+
+use serde::Deserialize;
+
+use crate::writer::Escape;
+
+#[derive(Debug, PartialEq, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FrontMatter {
+    // ---, read by Hugo
+    #[default]
+    Yaml,
+    // +++, laid out for Zola
+    Toml,
+}
+
+pub enum Value<'v> {
+    Text(Cow<'v, [u8]>),
+    // text written out by a function as it is quoted, e.g. decoded LaTeX or
+    // a cut description, without a buffer in between
+    Writer(&'v dyn Fn(&mut dyn Write) -> std::io::Result<()>),
+    // numbers, dates and bare words such as summary_large_image; TOML
+    // quotes them unless they are a number or a date
+    Plain(Cow<'v, [u8]>),
+    Date(chrono::DateTime<chrono::Utc>),
+    // one item per line in YAML
+    List(&'v [Value<'v>]),
+    // [a, b]
+    Inline(&'v [Value<'v>]),
+    // a block mapping in YAML, {a: x, b: y} inside [a, b]
+    Map(&'v [(&'v str, Value<'v>)]),
+    Bool(bool),
+}
+
+impl<'v> Value<'v> {
+    pub fn text(text: impl Into<Cow<'v, [u8]>>) -> Self {
+        Value::Text(text.into())
+    }
+
+    pub fn plain(text: impl Into<Cow<'v, [u8]>>) -> Self {
+        Value::Plain(text.into())
+    }
+}
+
+/// Writes the entries of a front matter as they are handed to it, with no
+/// list of them kept in between.
+pub struct Emitter<'w> {
+    write: &'w mut dyn Write,
+    format: FrontMatter,
+    // Zola wants the top-level keys before its tables, so TOML goes over
+    // the entries once per table and writes the keys of that table only
+    table: Option<&'static str>,
+    header: bool,
+}
+
+/// Writes a front matter between its fences. `entries` hands every entry
+/// to the emitter; it is called once per Zola table for TOML.
+pub fn emit(
+    format: FrontMatter,
+    comment: Option<&str>,
+    mut write: impl Write,
+    mut entries: impl FnMut(&mut Emitter) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let fence: &[u8] = match format {
+        FrontMatter::Yaml => b"---\n",
+        FrontMatter::Toml => b"+++\n",
+    };
+    write.write_all(fence)?;
+    if let Some(comment) = comment {
+        write.write_all(b"# ")?;
+        for (i, part) in comment.split(char::is_control).enumerate() {
+            if i > 0 {
+                write.write_all(b" ")?;
+            }
+            write.write_all(part.as_bytes())?;
+        }
+        write.write_all(b"\n")?;
+    }
+
+    let tables: &[Option<&'static str>] = match format {
+        FrontMatter::Yaml => &[None],
+        FrontMatter::Toml => &[None, Some("taxonomies"), Some("extra")],
+    };
+    for table in tables {
+        entries(&mut Emitter {
+            write: &mut write,
+            format,
+            table: *table,
+            header: table.is_none(),
+        })?;
+    }
+    write.write_all(fence)
+}
+
+pub fn write(
+    format: FrontMatter,
+    comment: Option<&str>,
+    entries: &[(&str, Value)],
+    write: impl Write,
+) -> std::io::Result<()> {
+    emit(format, comment, write, |emitter| {
+        for (key, value) in entries {
+            emitter.entry(key, value)?;
+        }
+        Ok(())
+    })
+}
+
+impl Emitter<'_> {
+    /// Whether this pass writes `key`, so that a value costly to build is
+    /// only built when it is written.
+    pub fn wants(&self, key: &str) -> bool {
+        match self.format {
+            FrontMatter::Yaml => true,
+            FrontMatter::Toml => zola_table(key) == self.table,
+        }
+    }
+
+    fn key(&mut self, key: &str) -> std::io::Result<()> {
+        if !self.header {
+            writeln!(self.write, "\n[{}]", self.table.unwrap_or_default())?;
+            self.header = true;
+        }
+        match self.format {
+            FrontMatter::Yaml => yaml_key(self.write, key),
+            FrontMatter::Toml => toml_key(&mut *self.write, key),
+        }
+    }
+
+    pub fn entry(&mut self, key: &str, value: &Value) -> std::io::Result<()> {
+        if let Value::List(items) = value {
+            return self.list(key, |list| {
+                items.iter().try_for_each(|item| list.item(item))
+            });
+        }
+        if !self.wants(key) {
+            return Ok(());
+        }
+
+        self.key(key)?;
+        match (self.format, value) {
+            (FrontMatter::Yaml, Value::Map(fields)) if !fields.is_empty() => {
+                self.write.write_all(b":\n  ")?;
+                yaml_block(self.write, fields)?;
+            }
+            (FrontMatter::Yaml, _) => {
+                self.write.write_all(b": ")?;
+                yaml_scalar(self.write, value)?;
+            }
+            (FrontMatter::Toml, _) => {
+                self.write.write_all(b" = ")?;
+                toml_value(self.write, value)?;
+            }
+        }
+        self.write.write_all(b"\n")
+    }
+
+    /// A list whose items `items` hands over one by one.
+    pub fn list(
+        &mut self,
+        key: &str,
+        items: impl FnOnce(&mut List) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        if !self.wants(key) {
+            return Ok(());
+        }
+
+        self.key(key)?;
+        let mut list = List {
+            write: &mut *self.write,
+            format: self.format,
+            first: true,
+        };
+        match self.format {
+            FrontMatter::Yaml => {
+                list.write.write_all(b":\n")?;
+                items(&mut list)
+            }
+            FrontMatter::Toml => {
+                list.write.write_all(b" = [")?;
+                items(&mut list)?;
+                list.write.write_all(b"]\n")
+            }
+        }
+    }
+}
+
+pub struct List<'l> {
+    write: &'l mut dyn Write,
+    format: FrontMatter,
+    first: bool,
+}
+
+impl List<'_> {
+    pub fn item(&mut self, value: &Value) -> std::io::Result<()> {
+        let first = std::mem::replace(&mut self.first, false);
+        match self.format {
+            FrontMatter::Yaml => {
+                self.write.write_all(b"- ")?;
+                match value {
+                    Value::Map(fields) if !fields.is_empty() => yaml_block(self.write, fields)?,
+                    value => yaml_scalar(self.write, value)?,
+                }
+                self.write.write_all(b"\n")
+            }
+            FrontMatter::Toml => {
+                if !first {
+                    self.write.write_all(b", ")?;
+                }
+                toml_value(self.write, value)
+            }
+        }
+    }
+}
+
+// RFC 3339 as %+ writes it, whose DelayedFormat would build a String first
+fn date(write: &mut dyn Write, date: &chrono::DateTime<chrono::Utc>) -> std::io::Result<()> {
+    use chrono::{Datelike, Timelike};
+
+    match date.year() {
+        year @ 0..=9999 => write!(write, "{:04}", year)?,
+        year => write!(write, "{:+05}", year)?,
+    }
+    // a leap second is kept as :60
+    let (second, nanos) = match date.nanosecond() {
+        nanos @ 1_000_000_000.. => (date.second() + 1, nanos - 1_000_000_000),
+        nanos => (date.second(), nanos),
+    };
+    write!(
+        write,
+        "-{:02}-{:02}T{:02}:{:02}:{:02}",
+        date.month(),
+        date.day(),
+        date.hour(),
+        date.minute(),
+        second
+    )?;
+    match nanos {
+        0 => {}
+        nanos if nanos % 1_000_000 == 0 => write!(write, ".{:03}", nanos / 1_000_000)?,
+        nanos if nanos % 1_000 == 0 => write!(write, ".{:06}", nanos / 1_000)?,
+        nanos => write!(write, ".{:09}", nanos)?,
+    }
+    write.write_all(b"+00:00")
+}
+
+// whether YAML reads `text` back unquoted, also inside [a, b], as the same
+// text or as the number or date it spells
+fn yaml_plain(text: &[u8]) -> bool {
+    let text = match std::str::from_utf8(text) {
+        Ok(text) => text,
+        Err(_) => return false,
+    };
+    let reserved = ["true", "false", "yes", "no", "on", "off", "null", "~"];
+
+    match text.chars().next() {
+        None => false,
+        Some(first) if "-?:,[]{}#&*!|>'\"%@`".contains(first) || first.is_whitespace() => false,
+        Some(_) => {
+            !text.ends_with(char::is_whitespace)
+                && !text.ends_with(':')
+                && !text.contains(": ")
+                && !text.contains(" #")
+                && !text.contains(|c: char| ",[]{}".contains(c) || c.is_control())
+                && !reserved.iter().any(|word| text.eq_ignore_ascii_case(word))
+        }
+    }
+}
+
+fn yaml_string(write: &mut dyn Write, text: &[u8]) -> std::io::Result<()> {
+    write.write_all(b"\"")?;
+    Escape(&mut *write).write_all(text)?;
+    write.write_all(b"\"")
+}
+
+fn yaml_key(write: &mut dyn Write, key: &str) -> std::io::Result<()> {
+    match yaml_plain(key.as_bytes()) {
+        true => write.write_all(key.as_bytes()),
+        false => yaml_string(write, key.as_bytes()),
+    }
+}
+
+fn yaml_scalar(write: &mut dyn Write, value: &Value) -> std::io::Result<()> {
+    match value {
+        Value::Plain(text) if yaml_plain(text) => write.write_all(text),
+        Value::Bool(value) => write!(write, "{}", value),
+        Value::Date(value) => date(write, value),
+        Value::Text(text) | Value::Plain(text) => yaml_string(write, text),
+        Value::Writer(text) => {
+            write.write_all(b"\"")?;
+            text(&mut Escape(&mut *write))?;
+            write.write_all(b"\"")
+        }
+        Value::Inline(items) | Value::List(items) => {
+            write.write_all(b"[")?;
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    write.write_all(b", ")?;
+                }
+                yaml_scalar(write, item)?;
+            }
+            write.write_all(b"]")
+        }
+        Value::Map(fields) => {
+            write.write_all(b"{")?;
+            for (i, (key, value)) in fields.iter().enumerate() {
+                if i > 0 {
+                    write.write_all(b", ")?;
+                }
+                yaml_key(write, key)?;
+                write.write_all(b": ")?;
+                yaml_scalar(write, value)?;
+            }
+            write.write_all(b"}")
+        }
+    }
+}
+
+// a block mapping whose first key goes where the cursor is and the others
+// below it, two spaces in as under a key or after "- "; nested maps are
+// written in flow style
+fn yaml_block(write: &mut dyn Write, fields: &[(&str, Value)]) -> std::io::Result<()> {
+    for (i, (key, value)) in fields.iter().enumerate() {
+        if i > 0 {
+            write.write_all(b"\n  ")?;
+        }
+        yaml_key(write, key)?;
+        write.write_all(b": ")?;
+        yaml_scalar(write, value)?;
+    }
+    Ok(())
+}
+
+// the inside of a TOML basic string
+struct TomlEscape<'w>(&'w mut dyn Write);
+
+impl Write for TomlEscape<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for c in String::from_utf8_lossy(buf).chars() {
+            match c {
+                '"' => self.0.write_all(b"\\\"")?,
+                '\\' => self.0.write_all(b"\\\\")?,
+                '\n' => self.0.write_all(b"\\n")?,
+                '\t' => self.0.write_all(b"\\t")?,
+                c if c.is_control() => write!(self.0, "\\u{:04X}", c as u32)?,
+                c => self.0.write_all(c.encode_utf8(&mut [0; 4]).as_bytes())?,
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+fn toml_string(write: &mut dyn Write, text: &[u8]) -> std::io::Result<()> {
+    write.write_all(b"\"")?;
+    TomlEscape(&mut *write).write_all(text)?;
+    write.write_all(b"\"")
+}
+
+// integers as TOML has them, without leading zeros and within i64; a
+// zero-padded issue number such as 007 stays a string
+fn toml_bare(text: &[u8]) -> bool {
+    let digits = match text {
+        [b'+' | b'-', digits @ ..] => digits,
+        digits => digits,
+    };
+    (digits == b"0" || !digits.starts_with(b"0"))
+        && std::str::from_utf8(text).is_ok_and(|text| text.parse::<i64>().is_ok())
+}
+
+fn toml_key(write: &mut dyn Write, key: &str) -> std::io::Result<()> {
+    let bare = !key.is_empty()
+        && key
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, b'_' | b'-'));
+    match bare {
+        true => write.write_all(key.as_bytes()),
+        false => toml_string(write, key.as_bytes()),
+    }
+}
+
+fn toml_value(write: &mut dyn Write, value: &Value) -> std::io::Result<()> {
+    match value {
+        Value::Plain(text) if toml_bare(text) => write.write_all(text),
+        Value::Bool(value) => write!(write, "{}", value),
+        Value::Date(value) => date(write, value),
+        Value::Text(text) | Value::Plain(text) => toml_string(write, text),
+        Value::Writer(text) => {
+            write.write_all(b"\"")?;
+            text(&mut TomlEscape(&mut *write))?;
+            write.write_all(b"\"")
+        }
+        Value::List(items) | Value::Inline(items) => {
+            write.write_all(b"[")?;
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    write.write_all(b", ")?;
+                }
+                toml_value(write, item)?;
+            }
+            write.write_all(b"]")
+        }
+        Value::Map(fields) => {
+            write.write_all(b"{ ")?;
+            for (i, (key, value)) in fields.iter().enumerate() {
+                if i > 0 {
+                    write.write_all(b", ")?;
+                }
+                toml_key(&mut *write, key)?;
+                write.write_all(b" = ")?;
+                toml_value(write, value)?;
+            }
+            write.write_all(b" }")
+        }
+    }
+}
+
+// Zola rejects unknown keys at the top level; tags are a taxonomy there
+// and everything else belongs to [extra]
+fn zola_table(key: &str) -> Option<&'static str> {
+    match key {
+        "title" | "description" | "date" | "aliases" => None,
+        "tags" => Some("taxonomies"),
+        _ => Some("extra"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn article(format: FrontMatter, comment: Option<&str>) -> String {
+        let author = [
+            ("given", Value::plain(&b"Aurora"[..])),
+            ("family", Value::plain(&b"Leao"[..])),
+        ];
+        let authors = [Value::Map(&author)];
+        let tags = [Value::plain(&b"Sertao"[..])];
+        let pages = [Value::plain(&b"1"[..]), Value::plain(&b"20"[..])];
+        let date = chrono::DateTime::parse_from_rfc3339("2022-06-01T00:00:00Z").unwrap();
+        let entries = [
+            ("title", Value::text(&b"O \"sertao\""[..])),
+            ("date", Value::Date(date.into())),
+            ("authors", Value::List(&authors)),
+            ("tags", Value::List(&tags)),
+            ("pages", Value::Inline(&pages)),
+            ("twitter_card", Value::plain(&b"summary"[..])),
+            ("math", Value::Bool(true)),
+        ];
+
+        let mut output = Vec::new();
+        write(format, comment, &entries, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn yaml() {
+        assert_eq!(
+            article(FrontMatter::Yaml, Some("gerado")),
+            "---\n# gerado\ntitle: \"O \\\"sertao\\\"\"\ndate: 2022-06-01T00:00:00+00:00\nauthors:\n- given: Aurora\n  family: Leao\ntags:\n- Sertao\npages: [1, 20]\ntwitter_card: summary\nmath: true\n---\n"
+        );
+    }
+
+    #[test]
+    fn yaml_round_trip() {
+        let texts = [
+            "C:\\sertao \"mar\"",
+            "linha\nquebrada\ttab",
+            "- lista",
+            "chave: valor",
+            "fim #comentario",
+            "Null",
+            "[a, b]",
+            "&ancora *ref",
+            " espaco ",
+            "Sertão",
+        ];
+
+        for text in texts {
+            let value = |plain: bool| match plain {
+                true => Value::plain(text.as_bytes()),
+                false => Value::text(text.as_bytes()),
+            };
+            let written = |write: &mut dyn Write| write.write_all(text.as_bytes());
+            let list = [value(true)];
+            let inline = [value(true), value(false)];
+            let given = [("given", value(true)), ("family", value(false))];
+            let map = [Value::Map(&given)];
+            let nested = [("name", Value::Map(&given))];
+            let entries = [
+                ("text", value(false)),
+                ("plain", value(true)),
+                ("writer", Value::Writer(&written)),
+                ("list", Value::List(&list)),
+                ("inline", Value::Inline(&inline)),
+                ("map", Value::List(&map)),
+                ("top", Value::Map(&given)),
+                ("flow", Value::Inline(&map)),
+                ("nested", Value::Map(&nested)),
+                (text, value(true)),
+            ];
+            let mut output = Vec::new();
+            write(FrontMatter::Yaml, Some(text), &entries, &mut output).unwrap();
+
+            let output = String::from_utf8(output).unwrap();
+            let yaml = output.trim_start_matches("---\n").trim_end_matches("---\n");
+            let parsed: serde_yaml::Value = serde_yaml::from_str(yaml).expect(&output);
+            let text = serde_yaml::Value::from(text);
+            assert_eq!(parsed["text"], text, "{}", output);
+            assert_eq!(parsed["plain"], text, "{}", output);
+            assert_eq!(parsed["writer"], text, "{}", output);
+            assert_eq!(parsed["list"][0], text, "{}", output);
+            assert_eq!(parsed["inline"][0], text, "{}", output);
+            assert_eq!(parsed["inline"][1], text, "{}", output);
+            assert_eq!(parsed["map"][0]["given"], text, "{}", output);
+            assert_eq!(parsed["map"][0]["family"], text, "{}", output);
+            assert_eq!(parsed["top"]["given"], text, "{}", output);
+            assert_eq!(parsed["top"]["family"], text, "{}", output);
+            assert_eq!(parsed["flow"][0]["family"], text, "{}", output);
+            assert_eq!(parsed["nested"]["name"]["given"], text, "{}", output);
+            assert_eq!(parsed[&text], text, "{}", output);
+        }
+    }
+
+    #[test]
+    fn dates() {
+        for date in [
+            "2022-06-01T00:00:00Z",
+            "2022-06-01T12:30:05.250Z",
+            "2022-06-01T12:30:05.000250Z",
+            "2022-06-01T12:30:05.000000250Z",
+            "2016-12-31T23:59:60.5Z",
+        ] {
+            let date: chrono::DateTime<chrono::Utc> =
+                chrono::DateTime::parse_from_rfc3339(date).unwrap().into();
+            let mut output = Vec::new();
+            super::date(&mut output, &date).unwrap();
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                date.format("%+").to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn toml_integers() {
+        let plain = |text: &'static str| Value::plain(text.as_bytes());
+        let entries = [
+            ("issue", plain("007")),
+            ("volume", plain("12")),
+            ("zero", plain("0")),
+            ("huge", plain("99999999999999999999")),
+            ("pages", Value::Inline(&[plain("1"), plain("20")])),
+            ("date", plain("2022-06-01T00:00:00Z")),
+        ];
+        let mut output = Vec::new();
+        write(FrontMatter::Toml, None, &entries, &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let parsed: toml::Value = toml::from_str(&output[4..output.len() - 4]).expect(&output);
+        let string = |text: &str| toml::Value::String(text.to_owned());
+        let extra = &parsed["extra"];
+        assert_eq!(extra["issue"], string("007"));
+        assert_eq!(extra["volume"], toml::Value::Integer(12));
+        assert_eq!(extra["zero"], toml::Value::Integer(0));
+        assert_eq!(extra["huge"], string("99999999999999999999"));
+        assert_eq!(extra["pages"][0], toml::Value::Integer(1));
+        assert_eq!(parsed["date"], string("2022-06-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn zola() {
+        let output = article(FrontMatter::Toml, None);
+        assert_eq!(
+            output,
+            "+++\ntitle = \"O \\\"sertao\\\"\"\ndate = 2022-06-01T00:00:00+00:00\n\n[taxonomies]\ntags = [\"Sertao\"]\n\n[extra]\nauthors = [{ given = \"Aurora\", family = \"Leao\" }]\npages = [1, 20]\ntwitter_card = \"summary\"\nmath = true\n+++\n"
+        );
+        assert!(toml::from_str::<toml::Value>(&output[4..output.len() - 4]).is_ok());
+    }
+}
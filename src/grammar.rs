@@ -0,0 +1,699 @@
+use std::{borrow::Cow, io::Write};
+
+use nom::{error::ErrorKind, IResult};
+use serde::Deserialize;
+
+use crate::{
+    limits::Limits,
+    paragraph::Terminator,
+    metadata::{abstract_value, authors_value, text_value, Value},
+    r#abstract::{r#abstract, CommandHandler, CustomCommand},
+};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Field {
+    Authors,
+    Title,
+    FirstPage,
+    LastPage,
+    Abstract,
+    Keywords,
+    Section,
+    Number,
+    Semester,
+    Year,
+    Cover,
+    Language,
+    Extra,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Multiplicity {
+    Once,
+    Many,
+}
+
+pub type ValueParser = for<'a> fn(&'a [u8], &'a Registry) -> IResult<&'a [u8], Value<'a>>;
+
+pub struct Key {
+    pub name: Cow<'static, str>,
+    pub parse: ValueParser,
+    pub field: Field,
+    pub multiplicity: Multiplicity,
+    pub description: &'static str,
+    pub example: &'static str,
+}
+
+impl Key {
+    pub fn extra(name: String, multiplicity: Multiplicity) -> Self {
+        Self {
+            name: Cow::Owned(name),
+            parse: text_value,
+            field: Field::Extra,
+            multiplicity,
+            description: "",
+            example: "",
+        }
+    }
+}
+
+// what the value of an extra key must look like, e.g. volume = "integer"
+// or license = { one-of = ["cc-by", "cc-by-sa"] } in a profile
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Shape {
+    Integer,
+    // 2022-06-01
+    Date,
+    OneOf(Vec<String>),
+    Text,
+}
+
+impl Shape {
+    pub fn accepts(&self, value: &[u8]) -> bool {
+        let value = match std::str::from_utf8(value.trim_ascii()) {
+            Ok(value) => value,
+            Err(_) => return *self == Shape::Text,
+        };
+        match self {
+            Shape::Integer => value.parse::<i64>().is_ok(),
+            Shape::Date => chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok(),
+            Shape::OneOf(values) => values.iter().any(|allowed| allowed == value),
+            Shape::Text => true,
+        }
+    }
+
+    // completes "expected ..."
+    pub fn expected(&self) -> String {
+        match self {
+            Shape::Integer => "an integer".to_owned(),
+            Shape::Date => "a date such as 2022-06-01".to_owned(),
+            Shape::OneOf(values) => format!("one of {}", values.join(", ")),
+            Shape::Text => "text".to_owned(),
+        }
+    }
+}
+
+pub struct AuthorPart {
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+pub struct Command {
+    pub name: &'static str,
+    pub argument: &'static str,
+    pub description: &'static str,
+}
+
+pub const KEYS: &[Key] = &[
+    Key {
+        name: Cow::Borrowed("authors"),
+        parse: authors_value,
+        field: Field::Authors,
+        multiplicity: Multiplicity::Once,
+        description: "article authors, in authorship order",
+        example: "given> Aurora Almeida de Miranda, family> Leão. given> Fulano, family> Tal",
+    },
+    Key {
+        name: Cow::Borrowed("title"),
+        parse: text_value,
+        field: Field::Title,
+        multiplicity: Multiplicity::Once,
+        description: "article title",
+        example: "Euclides da Cunha atualizado no sertão da teledramaturgia",
+    },
+    Key {
+        name: Cow::Borrowed("first_page"),
+        parse: text_value,
+        field: Field::FirstPage,
+        multiplicity: Multiplicity::Once,
+        description: "first page of the article in the issue",
+        example: "15",
+    },
+    Key {
+        name: Cow::Borrowed("last_page"),
+        parse: text_value,
+        field: Field::LastPage,
+        multiplicity: Multiplicity::Once,
+        description: "last page of the article in the issue",
+        example: "29",
+    },
+    Key {
+        name: Cow::Borrowed("abstract"),
+        parse: abstract_value,
+        field: Field::Abstract,
+        multiplicity: Multiplicity::Once,
+        description: "article abstract, which may use the commands listed below",
+        example: "A série \\textit{Onde nascem os fortes} remete ao livro \\textit{Os sertões} \\citeyear{EcCUNHA1902sertoes}.",
+    },
+    Key {
+        name: Cow::Borrowed("keywords"),
+        parse: text_value,
+        field: Field::Keywords,
+        multiplicity: Multiplicity::Once,
+        description: "keywords, each one ending with a period",
+        example: "Sertão. Teledramaturgia.",
+    },
+    Key {
+        name: Cow::Borrowed("section"),
+        parse: text_value,
+        field: Field::Section,
+        multiplicity: Multiplicity::Many,
+        description: "journal sections the article belongs to, separated by ; or repeated",
+        example: "Dossiê História dos Sertões",
+    },
+    Key {
+        name: Cow::Borrowed("number"),
+        parse: text_value,
+        field: Field::Number,
+        multiplicity: Multiplicity::Once,
+        description: "issue number",
+        example: "5",
+    },
+    Key {
+        name: Cow::Borrowed("semester"),
+        parse: text_value,
+        field: Field::Semester,
+        multiplicity: Multiplicity::Once,
+        description: "issue semester",
+        example: "1",
+    },
+    Key {
+        name: Cow::Borrowed("year"),
+        parse: text_value,
+        field: Field::Year,
+        multiplicity: Multiplicity::Once,
+        description: "issue year",
+        example: "2022",
+    },
+    Key {
+        name: Cow::Borrowed("cover"),
+        parse: text_value,
+        field: Field::Cover,
+        multiplicity: Multiplicity::Once,
+        description: "cover image used in social previews",
+        example: "/img/capa.jpg",
+    },
+    Key {
+        name: Cow::Borrowed("language"),
+        parse: text_value,
+        field: Field::Language,
+        multiplicity: Multiplicity::Once,
+        description: "article language (pt-BR, en or es), used for generated labels",
+        example: "pt-BR",
+    },
+];
+
+pub const AUTHOR_PARTS: &[AuthorPart] = &[
+    AuthorPart {
+        name: "given",
+        description: "given names",
+    },
+    AuthorPart {
+        name: "family",
+        description: "family name",
+    },
+    AuthorPart {
+        name: "order",
+        description: "position among the authors, e.g. order> 1",
+    },
+];
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "textit",
+        argument: "text",
+        description: "italic text; bold or quoted with the profile's title_style",
+    },
+    Command {
+        name: "emph",
+        argument: "text",
+        description: "emphasized text, always in italics",
+    },
+    Command {
+        name: "textbf",
+        argument: "text",
+        description: "bold text, e.g. **text** in Markdown",
+    },
+    Command {
+        name: "enquote",
+        argument: "text",
+        description: "quoted text, e.g. “text”",
+    },
+    Command {
+        name: "aspas",
+        argument: "text",
+        description: "same as \\enquote",
+    },
+    Command {
+        name: "textsuperscript",
+        argument: "text",
+        description: "superscript text, e.g. XX^o^ in Markdown",
+    },
+    Command {
+        name: "textsubscript",
+        argument: "text",
+        description: "subscript text, e.g. CO~2~ in Markdown",
+    },
+    Command {
+        name: "textsc",
+        argument: "text",
+        description: "small caps; uppercased in plain text",
+    },
+    Command {
+        name: "MakeUppercase",
+        argument: "text",
+        description: "uppercased text",
+    },
+    Command {
+        name: "-",
+        argument: "",
+        description: "discretionary hyphen; removed",
+    },
+    Command {
+        name: "hspace",
+        argument: "length",
+        description: "horizontal space; removed",
+    },
+    Command {
+        name: "ref",
+        argument: "label",
+        description: "figure or table reference; removed unless overridden with --command",
+    },
+    Command {
+        name: "autoref",
+        argument: "label",
+        description: "figure or table reference; removed unless overridden with --command",
+    },
+    Command {
+        name: "includegraphics",
+        argument: "path",
+        description: "inline image; a Markdown image under --image-prefix, removed without one",
+    },
+    Command {
+        name: "url",
+        argument: "address",
+        description: "web address; <address> in Markdown",
+    },
+    Command {
+        name: "begin",
+        argument: "citacao",
+        description: "long quote up to \\end{citacao}, also quote or quotation; a blockquote \
+                      in Markdown, indented in plain text",
+    },
+    Command {
+        name: "end",
+        argument: "citacao",
+        description: "closes \\begin",
+    },
+    Command {
+        name: "citeyear",
+        argument: "key",
+        description: "year of the cited work, e.g. (2003); \\citeyear[p. 42]{key} adds the page",
+    },
+    Command {
+        name: "citeauthor",
+        argument: "key",
+        description: "authors of the cited work in running text, e.g. Bakhtin",
+    },
+    Command {
+        name: "citetitle",
+        argument: "key",
+        description: "title of the cited work in italics, e.g. _Estética da criação verbal_",
+    },
+    Command {
+        name: "citet",
+        argument: "key",
+        description: "textual citation, e.g. Bakhtin (2003); \\citet[p. 42]{key} adds the page",
+    },
+    Command {
+        name: "citeonline",
+        argument: "key",
+        description: "same as \\citet, as in abnTeX2",
+    },
+    Command {
+        name: "textcite",
+        argument: "key",
+        description: "same as \\citet, as in biblatex",
+    },
+    Command {
+        name: "cite",
+        argument: "key",
+        description: "author and year of the cited works, e.g. (BAKHTIN, 2003); \\cite{a,b} \
+                      groups several and \\cite[p. 42]{key} adds the page",
+    },
+    Command {
+        name: "citep",
+        argument: "key",
+        description: "same as \\cite",
+    },
+];
+
+pub fn longest_tag<'n>(
+    input: &[u8],
+    names: impl Iterator<Item = &'n str>,
+) -> IResult<&[u8], &'n str> {
+    names
+        .filter(|name| input.starts_with(name.as_bytes()))
+        .max_by_key(|name| name.len())
+        .map(|name| (&input[name.len()..], name))
+        .ok_or_else(|| nom::Err::Error(nom::error::Error::new(input, ErrorKind::Tag)))
+}
+
+#[derive(Default)]
+pub struct Registry {
+    extra: Vec<Key>,
+    aliases: Vec<(String, String)>,
+    shapes: Vec<(String, Shape)>,
+    pub(crate) commands: Vec<CustomCommand>,
+    pub(crate) limits: Limits,
+    pub(crate) terminator: Terminator,
+    pub(crate) lenient: bool,
+    pub(crate) keep_unknown: bool,
+}
+
+pub static DEFAULT_REGISTRY: Registry = Registry {
+    extra: Vec::new(),
+    aliases: Vec::new(),
+    shapes: Vec::new(),
+    commands: Vec::new(),
+    limits: Limits::NONE,
+    terminator: Terminator::PAR,
+    lenient: false,
+    keep_unknown: false,
+};
+
+impl Registry {
+    pub fn register(&mut self, key: Key) {
+        self.extra.push(key);
+    }
+
+    pub fn alias(&mut self, alias: String, name: &str) -> std::io::Result<()> {
+        if !self.keys().any(|key| key.name == name) {
+            return Err(std::io::Error::other(format!(
+                "cannot alias {} to unknown key {}",
+                alias, name
+            )));
+        }
+        self.aliases.push((alias, name.to_owned()));
+        Ok(())
+    }
+
+    // only extra keys take a shape; the built-in ones have their own parsers
+    pub fn set_shape(&mut self, name: &str, shape: Shape) -> std::io::Result<()> {
+        let extra = self.extra.iter().any(|key| key.name == name);
+        if !extra {
+            return Err(std::io::Error::other(format!(
+                "cannot give a shape to {}, which is not an extra key",
+                name
+            )));
+        }
+        self.shapes.retain(|(key, _)| key != name);
+        self.shapes.push((name.to_owned(), shape));
+        Ok(())
+    }
+
+    pub fn shape(&self, name: &str) -> Option<&Shape> {
+        self.shapes
+            .iter()
+            .find_map(|(key, shape)| (key == name).then_some(shape))
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn set_terminator(&mut self, terminator: Terminator) {
+        self.terminator = terminator;
+    }
+
+    // unknown keys are skipped with a warning instead of ending the metadata
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.lenient = lenient;
+    }
+
+    // unknown keys are kept as extra entries of the front matter
+    pub fn set_keep_unknown(&mut self, keep: bool) {
+        self.keep_unknown = keep;
+    }
+
+    pub fn register_command(&mut self, name: String, handler: Box<dyn CommandHandler>) {
+        self.commands.push(CustomCommand { name, handler });
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Key> {
+        KEYS.iter().chain(&self.extra)
+    }
+
+    // a name counts only when `=` or the end of the input follows it, so
+    // that title_en is not read as title
+    pub fn key<'a>(&self, input: &'a [u8]) -> IResult<&'a [u8], &Key> {
+        let names = self
+            .keys()
+            .map(|key| key.name.as_ref())
+            .chain(self.aliases.iter().map(|(alias, _)| alias.as_str()))
+            .filter(|name| {
+                input
+                    .strip_prefix(name.as_bytes())
+                    .map(<[u8]>::trim_ascii_start)
+                    .is_some_and(|rest| matches!(rest.first(), None | Some(b'=')))
+            });
+        let (input, name) = longest_tag(input, names)?;
+
+        // aliases resolve to the canonical key, so duplicates are still caught
+        let name = match self.aliases.iter().find(|(alias, _)| alias == name) {
+            Some((_, canonical)) => canonical.as_str(),
+            None => name,
+        };
+        let key = self.keys().find(|key| key.name == name).unwrap();
+        Ok((input, key))
+    }
+}
+
+pub fn explain(mut write: impl Write) -> std::io::Result<()> {
+    writeln!(
+        write,
+        "A metadata file is a sequence of fields written as `key=value\\par`."
+    )?;
+    writeln!(write, "Spaces around `=` and between fields are ignored.")?;
+    writeln!(write, "Lines starting with `%` between fields are comments.")?;
+
+    writeln!(write, "\nKeys:")?;
+    for key in KEYS {
+        writeln!(write, "  {:<12} {}", key.name, key.description)?;
+        writeln!(write, "  {:<12} e.g. {}={}\\par", "", key.name, key.example)?;
+    }
+
+    writeln!(
+        write,
+        "\nEach author is a pair of `part> value` entries separated by `,`,"
+    )?;
+    writeln!(write, "and authors are separated from each other by `.`:")?;
+    for part in AUTHOR_PARTS {
+        writeln!(write, "  {:<12} {}", part.name, part.description)?;
+    }
+
+    writeln!(write, "\nThe abstract accepts these commands:")?;
+    for command in COMMANDS {
+        let usage = match command.argument {
+            "" => format!("\\{}", command.name),
+            argument => format!("\\{}{{{}}}", command.name, argument),
+        };
+        writeln!(write, "  {:<20} {}", usage, command.description)?;
+    }
+
+    Ok(())
+}
+
+/// A metadata file using every key of `registry`, each one with its
+/// description in a comment, to be handed to authors as a template.
+pub fn example(mut write: impl Write, registry: &Registry) -> std::io::Result<()> {
+    let terminator = registry.terminator.written();
+
+    writeln!(write, "% An example metadata file. Replace the values and remove the")?;
+    writeln!(write, "% fields that do not apply; lines starting with % are comments.")?;
+
+    for key in registry.keys() {
+        writeln!(write)?;
+        if key.example.is_empty() {
+            // keys of the profile come without an example
+            match registry.shape(&key.name) {
+                Some(shape) => writeln!(
+                    write,
+                    "% {}: journal specific, {}",
+                    key.name,
+                    shape.expected()
+                )?,
+                None => writeln!(write, "% {}: journal specific", key.name)?,
+            }
+            writeln!(write, "% {}=...{}", key.name, terminator)?;
+            continue;
+        }
+
+        writeln!(write, "% {}: {}", key.name, key.description)?;
+        if key.multiplicity == Multiplicity::Many {
+            writeln!(write, "% may be given more than once")?;
+        }
+        match key.field {
+            Field::Authors => {
+                writeln!(write, "% each author is a list of `part> value` separated by `,`,")?;
+                writeln!(write, "% and authors are separated by `.`; the parts are:")?;
+                for part in AUTHOR_PARTS {
+                    writeln!(write, "%   {:<10} {}", part.name, part.description)?;
+                }
+            }
+            Field::Abstract => {
+                writeln!(write, "% commands:")?;
+                for command in COMMANDS {
+                    let usage = match command.argument {
+                        "" => format!("\\{}", command.name),
+                        argument => format!("\\{}{{{}}}", command.name, argument),
+                    };
+                    writeln!(write, "%   {:<20} {}", usage, command.description)?;
+                }
+            }
+            _ => {}
+        }
+        writeln!(write, "{}={}{}", key.name, key.example, terminator)?;
+    }
+
+    Ok(())
+}
+
+/// A bibliography with an entry for every work cited by the example.
+pub fn example_bib(mut write: impl Write) -> std::io::Result<()> {
+    writeln!(write, "% The works cited by the example abstract. Cite keys start with")?;
+    writeln!(write, "% the prefix of the journal, e.g. Ec.")?;
+
+    for key in KEYS.iter().filter(|key| key.field == Field::Abstract) {
+        let (_, summary) = r#abstract(key.example.as_bytes())
+            .map_err(|err| std::io::Error::other(format!("{:?}", err)))?;
+        for cited in summary.citation_keys() {
+            writeln!(write)?;
+            writeln!(write, "@book{{{},", String::from_utf8_lossy(cited))?;
+            writeln!(write, "  author = {{Family, Given AND Other, Author}},")?;
+            writeln!(write, "  title  = {{Title of the cited work}},")?;
+            writeln!(write, "  year   = {{1902}}")?;
+            writeln!(write, "}}")?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::{
+        metadata::{metadata, metadata_with},
+        source::field,
+    };
+
+    #[test]
+    fn examples_are_accepted() {
+        let input: String = KEYS
+            .iter()
+            .map(|key| format!("{}={}\\par\n", key.name, key.example))
+            .collect();
+
+        let (rest, metadata) = metadata(input.as_bytes()).unwrap();
+
+        assert!(rest.is_empty());
+        for key in KEYS {
+            assert_eq!(
+                field(&metadata, &key.name).unwrap().as_deref(),
+                Some(key.example.as_bytes()),
+                "{}",
+                key.name
+            );
+        }
+    }
+
+    #[test]
+    fn shapes() {
+        assert!(Shape::Integer.accepts(b" -12 "));
+        assert!(!Shape::Integer.accepts(b"12a"));
+        assert!(Shape::Date.accepts(b"2022-06-01"));
+        assert!(!Shape::Date.accepts(b"2022-13-01"));
+        let shape = Shape::OneOf(vec!["cc-by".to_owned()]);
+        assert!(shape.accepts(b"cc-by"));
+        assert!(!shape.accepts(b"CC-BY"));
+        assert!(Shape::Text.accepts(b"\xff"));
+        assert_eq!(shape.expected(), "one of cc-by");
+
+        let mut registry = Registry::default();
+        registry.register(Key::extra("license".to_owned(), Multiplicity::Once));
+        registry.set_shape("license", shape).unwrap();
+        let mut output = Vec::new();
+        example(&mut output, &registry).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("% license: journal specific, one of cc-by\n"));
+    }
+
+    #[test]
+    fn longest_match() {
+        let names = COMMANDS.iter().map(|command| command.name);
+
+        assert_eq!(longest_tag(b"citeyear{a}", names.clone()).unwrap().1, "citeyear");
+        assert_eq!(longest_tag(b"cite{a}", names.clone()).unwrap().1, "cite");
+        assert!(longest_tag(b"par", names).is_err());
+    }
+
+    #[test]
+    fn aliases() {
+        let mut registry = Registry::default();
+        registry.alias("titulo".to_owned(), "title").unwrap();
+        assert!(registry.alias("x".to_owned(), "doi").is_err());
+
+        let (_, metadata) = metadata_with(r#"titulo=Sertão\par"#.as_bytes(), &registry).unwrap();
+        assert_eq!(metadata.title, Some("Sertão".as_bytes()));
+
+        let input = r#"titulo=Sertão\par title=Outro\par"#;
+        assert!(metadata_with(input.as_bytes(), &registry).is_err());
+    }
+
+    #[test]
+    fn example_is_accepted() {
+        let mut registry = Registry::default();
+        registry.register(Key::extra("funding".to_owned(), Multiplicity::Many));
+
+        for terminator in [Terminator::PAR, Terminator::BlankLine] {
+            registry.set_terminator(terminator);
+
+            let mut output = Vec::new();
+            example(&mut output, &registry).unwrap();
+            let metadata = crate::metadata::parse(&output, &registry).unwrap();
+
+            for key in KEYS {
+                assert_eq!(
+                    field(&metadata, &key.name).unwrap().as_deref(),
+                    Some(key.example.as_bytes()),
+                    "{}",
+                    key.name
+                );
+            }
+            assert!(metadata.extra.is_empty());
+        }
+
+        let mut bib = Vec::new();
+        example_bib(&mut bib).unwrap();
+        let bib = nom_bibtex::Bibtex::parse(std::str::from_utf8(&bib).unwrap()).unwrap();
+        let bib = crate::bib::BibIndex::new(&bib);
+        assert!(bib.get(b"EcCUNHA1902sertoes").is_some());
+    }
+
+    #[test]
+    fn explain_lists_everything() {
+        let mut output = Vec::new();
+        explain(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        for key in KEYS {
+            assert!(output.contains(key.name.as_ref()));
+        }
+        for command in COMMANDS {
+            assert!(output.contains(&format!("\\{}", command.name)));
+        }
+    }
+}
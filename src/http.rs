@@ -0,0 +1,293 @@
+use std::{
+    collections::HashMap,
+    io::Read,
+    sync::{Arc, Mutex, OnceLock, PoisonError},
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+
+use crate::bib::DoiLookup;
+
+// of every request, redirects included
+const TIMEOUT: Duration = Duration::from_secs(30);
+// a BibTeX entry is a few KiB; anything much larger is not one
+const MAX_BODY: u64 = 1 << 20;
+// a Retry-After longer than this is not waited for
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum HttpError {
+    #[error("{url}: status {status}")]
+    Status { url: String, status: u16 },
+    #[error("{url}: {reason}")]
+    Transport { url: String, reason: String },
+}
+
+#[derive(Clone)]
+pub struct ClientOptions {
+    // Crossref serves clients that give a contact address from its polite
+    // pool, which is throttled less
+    pub mailto: Option<String>,
+    // between the start of two requests, retries included
+    pub interval: Duration,
+    pub retries: u32,
+    // the wait before the first retry, doubled on every further one
+    pub backoff: Duration,
+    // where DOIs are resolved, with a trailing slash
+    pub resolver: String,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            mailto: None,
+            interval: Duration::from_secs(1),
+            retries: 3,
+            backoff: Duration::from_millis(500),
+            resolver: "https://doi.org/".to_owned(),
+        }
+    }
+}
+
+type Response = Result<Arc<[u8]>, HttpError>;
+// a response fetched once; threads asking for it meanwhile wait for it
+type Shared = Arc<OnceLock<Response>>;
+
+/// A client for the online features, shared by every article of a run:
+/// requests are spaced out, failed ones retried with backoff, and each
+/// URL is fetched once however many articles ask for it.
+pub struct Client {
+    agent: ureq::Agent,
+    options: ClientOptions,
+    // when the next request may start
+    next: Mutex<Instant>,
+    // by URL and Accept header
+    responses: Mutex<HashMap<(String, String), Shared>>,
+}
+
+impl Client {
+    pub fn new(options: ClientOptions) -> Self {
+        let mut user_agent = format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        if let Some(mailto) = &options.mailto {
+            user_agent.push_str(&format!(" (mailto:{})", mailto));
+        }
+        let agent = ureq::AgentBuilder::new()
+            .timeout(TIMEOUT)
+            .user_agent(&user_agent)
+            .build();
+
+        Self {
+            agent,
+            options,
+            next: Mutex::new(Instant::now()),
+            responses: Mutex::default(),
+        }
+    }
+
+    /// The body of `url` as `accept`, fetched on the first call and shared
+    /// by later ones, failures included.
+    pub fn get(&self, url: &str, accept: &str) -> Response {
+        let response = self
+            .responses
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry((url.to_owned(), accept.to_owned()))
+            .or_default()
+            .clone();
+        response.get_or_init(|| self.fetch(url, accept)).clone()
+    }
+
+    fn fetch(&self, url: &str, accept: &str) -> Response {
+        let mut attempt = 0;
+        loop {
+            self.wait();
+            let wait = match self.agent.get(url).set("Accept", accept).call() {
+                Ok(response) => return read(url, response),
+                Err(ureq::Error::Status(status, response)) => match status {
+                    429 | 500 | 502 | 503 | 504 if attempt < self.options.retries => {
+                        retry_after(&response).unwrap_or_else(|| self.backoff(attempt))
+                    }
+                    _ => {
+                        let url = url.to_owned();
+                        return Err(HttpError::Status { url, status });
+                    }
+                },
+                Err(ureq::Error::Transport(_)) if attempt < self.options.retries => {
+                    self.backoff(attempt)
+                }
+                Err(ureq::Error::Transport(err)) => {
+                    let (url, reason) = (url.to_owned(), err.to_string());
+                    return Err(HttpError::Transport { url, reason });
+                }
+            };
+            std::thread::sleep(wait);
+            attempt += 1;
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.options.backoff * 2u32.saturating_pow(attempt)
+    }
+
+    // takes the next free slot, so that concurrent callers queue up
+    fn wait(&self) {
+        let start = {
+            let mut next = self.next.lock().unwrap_or_else(PoisonError::into_inner);
+            let start = (*next).max(Instant::now());
+            *next = start + self.options.interval;
+            start
+        };
+        std::thread::sleep(start.saturating_duration_since(Instant::now()));
+    }
+}
+
+fn read(url: &str, response: ureq::Response) -> Response {
+    let mut body = Vec::new();
+    let read = response
+        .into_reader()
+        .take(MAX_BODY + 1)
+        .read_to_end(&mut body);
+    let reason = match read {
+        Ok(_) if body.len() as u64 > MAX_BODY => "response too large".to_owned(),
+        Ok(_) => return Ok(body.into()),
+        Err(err) => err.to_string(),
+    };
+    let url = url.to_owned();
+    Err(HttpError::Transport { url, reason })
+}
+
+// only the delay in seconds; servers rarely send a date
+fn retry_after(response: &ureq::Response) -> Option<Duration> {
+    let seconds = response.header("Retry-After")?.trim().parse().ok()?;
+    Some(Duration::from_secs(seconds).min(MAX_RETRY_AFTER))
+}
+
+impl DoiLookup for Client {
+    fn bibtex(&self, doi: &[u8]) -> std::io::Result<String> {
+        let mut url = self.options.resolver.clone();
+        for c in doi {
+            match c {
+                c if c.is_ascii_alphanumeric() || b"/-._;:".contains(c) => url.push(*c as char),
+                c => url.push_str(&format!("%{:02X}", c)),
+            }
+        }
+
+        let body = self
+            .get(&url, "application/x-bibtex")
+            .map_err(std::io::Error::other)?;
+        String::from_utf8(body.to_vec())
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::*;
+
+    // answers with `responses` in turn, the last one over and over, and
+    // counts the requests
+    fn server(responses: &'static [&'static str]) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = format!("http://{}", listener.local_addr().unwrap());
+        let count = Arc::new(AtomicUsize::new(0));
+
+        let requests = count.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+
+                let i = requests.fetch_add(1, Ordering::SeqCst);
+                let (status, body) = responses[i.min(responses.len() - 1)]
+                    .split_once('\n')
+                    .unwrap();
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+            }
+        });
+        (addr, count)
+    }
+
+    fn options() -> ClientOptions {
+        ClientOptions {
+            interval: Duration::from_millis(20),
+            backoff: Duration::from_millis(10),
+            ..ClientOptions::default()
+        }
+    }
+
+    #[test]
+    fn retries_then_caches() {
+        let (addr, count) = server(&["503 Service Unavailable\n", "200 OK\n@book{x}"]);
+        let client = Client::new(options());
+
+        let start = Instant::now();
+        let url = format!("{}/10.1590/x", addr);
+        assert_eq!(
+            &*client.get(&url, "application/x-bibtex").unwrap(),
+            b"@book{x}"
+        );
+        assert_eq!(
+            &*client.get(&url, "application/x-bibtex").unwrap(),
+            b"@book{x}"
+        );
+
+        // the retry waited for its turn
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn gives_up() {
+        let (addr, count) = server(&["404 Not Found\n", "429 Too Many Requests\n"]);
+        let client = Client::new(options());
+
+        let missing = format!("{}/10.1590/missing", addr);
+        let status = |status| {
+            let url = missing.clone();
+            Err(HttpError::Status { url, status })
+        };
+        assert_eq!(client.get(&missing, "text/plain"), status(404));
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        let throttled = format!("{}/10.1590/throttled", addr);
+        let err = client.get(&throttled, "text/plain").unwrap_err();
+        assert!(matches!(err, HttpError::Status { status: 429, .. }));
+        assert_eq!(count.load(Ordering::SeqCst), 2 + options().retries as usize);
+    }
+
+    #[test]
+    fn doi_lookup() {
+        let (addr, _) = server(&["200 OK\n@article{Santos_2004, title={Para}}"]);
+        let client = Client::new(ClientOptions {
+            resolver: format!("{}/", addr),
+            ..options()
+        });
+
+        let bibtex = client.bibtex(b"10.1002/(SICI)1097").unwrap();
+        assert_eq!(bibtex, "@article{Santos_2004, title={Para}}");
+        let url = format!("{}/10.1002/%28SICI%291097", addr);
+        assert!(client
+            .responses
+            .lock()
+            .unwrap()
+            .contains_key(&(url, "application/x-bibtex".to_owned())));
+    }
+}
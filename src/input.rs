@@ -0,0 +1,155 @@
+use std::{
+    borrow::Cow,
+    io::{Read, Stdin},
+    path::Path,
+};
+
+pub trait InputSource {
+    fn read_input(&self) -> std::io::Result<Cow<'_, [u8]>>;
+}
+
+impl InputSource for Path {
+    fn read_input(&self) -> std::io::Result<Cow<'_, [u8]>> {
+        std::fs::read(self).map(Cow::Owned)
+    }
+}
+
+impl InputSource for Stdin {
+    fn read_input(&self) -> std::io::Result<Cow<'_, [u8]>> {
+        let mut buf = Vec::new();
+        self.lock().read_to_end(&mut buf)?;
+        Ok(Cow::Owned(buf))
+    }
+}
+
+// an entry of a zip archive, read without unpacking the archive
+#[cfg(feature = "zip")]
+pub struct ZipEntry<'p> {
+    pub archive: &'p Path,
+    pub name: &'p str,
+}
+
+#[cfg(feature = "zip")]
+impl ZipEntry<'_> {
+    // archive.zip!article.meta, the notation jar: URLs use
+    pub fn parse(path: &str) -> Option<ZipEntry<'_>> {
+        let (archive, name) = path.split_once(".zip!")?;
+        let archive = &path[..archive.len() + ".zip".len()];
+        Some(ZipEntry {
+            archive: Path::new(archive),
+            name,
+        })
+    }
+}
+
+#[cfg(feature = "zip")]
+impl InputSource for ZipEntry<'_> {
+    fn read_input(&self) -> std::io::Result<Cow<'_, [u8]>> {
+        let archive = std::fs::File::open(self.archive)?;
+        let mut archive = zip::ZipArchive::new(archive).map_err(zip_error)?;
+        let mut entry = archive.by_name(self.name).map_err(zip_error)?;
+        let mut buf = Vec::new();
+        entry.read_to_end(&mut buf)?;
+        Ok(Cow::Owned(buf))
+    }
+}
+
+// a missing entry reads as a missing file does
+#[cfg(feature = "zip")]
+fn zip_error(err: zip::result::ZipError) -> std::io::Error {
+    use std::io::{Error, ErrorKind};
+
+    match err {
+        zip::result::ZipError::Io(err) => err,
+        zip::result::ZipError::FileNotFound => Error::new(ErrorKind::NotFound, err),
+        err => Error::new(ErrorKind::InvalidData, err),
+    }
+}
+
+impl InputSource for [u8] {
+    fn read_input(&self) -> std::io::Result<Cow<'_, [u8]>> {
+        Ok(Cow::Borrowed(self))
+    }
+}
+
+impl InputSource for str {
+    fn read_input(&self) -> std::io::Result<Cow<'_, [u8]>> {
+        Ok(Cow::Borrowed(self.as_bytes()))
+    }
+}
+
+// how errors name a path, "-" being stdin
+pub fn shown(path: &str) -> String {
+    match path {
+        "-" => "<stdin>".to_owned(),
+        path => path.to_owned(),
+    }
+}
+
+// "-" reads stdin, as most command line tools do; with the zip feature
+// archive.zip!article.meta reads an entry of the archive
+pub fn read_path(path: &str) -> std::io::Result<Vec<u8>> {
+    #[cfg(feature = "zip")]
+    if let Some(entry) = ZipEntry::parse(path) {
+        return entry.read_input().map(Cow::into_owned);
+    }
+
+    match path {
+        "-" => std::io::stdin().read_input().map(Cow::into_owned),
+        path => Path::new(path).read_input().map(Cow::into_owned),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::borrow::Cow;
+
+    use super::*;
+
+    #[test]
+    fn in_memory() {
+        let input = "title=Sertão\\par";
+
+        let read = input.read_input().unwrap();
+
+        assert!(matches!(read, Cow::Borrowed(_)));
+        assert_eq!(&*read, input.as_bytes());
+    }
+
+    #[test]
+    fn path() {
+        let read = Path::new("Cargo.toml").read_input().unwrap();
+
+        assert!(read.starts_with(b"[package]"));
+    }
+
+    #[test]
+    fn missing_path() {
+        assert!(Path::new("does/not/exist.meta").read_input().is_err());
+        assert!(read_path("does/not/exist.meta").is_err());
+    }
+
+    #[test]
+    fn read_path_file() {
+        assert!(read_path("Cargo.toml").unwrap().starts_with(b"[package]"));
+    }
+    #[cfg(feature = "zip")]
+    #[test]
+    fn zip_entry() {
+        let archive = std::env::temp_dir().join(format!("input-{}.zip", std::process::id()));
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&archive).unwrap());
+        zip.start_file("v5/sertao.meta", zip::write::FileOptions::default())
+            .unwrap();
+        std::io::Write::write_all(&mut zip, "title=Sertão\\par".as_bytes()).unwrap();
+        zip.finish().unwrap();
+
+        let path = format!("{}!v5/sertao.meta", archive.display());
+        let read = read_path(&path).unwrap();
+        let missing = read_path(&format!("{}!v5/mar.meta", archive.display()));
+        std::fs::remove_file(&archive).unwrap();
+
+        assert_eq!(read, "title=Sertão\\par".as_bytes());
+        assert_eq!(missing.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,283 @@
+use std::{
+    borrow::Cow,
+    io::{self, Write},
+};
+
+// characters LaTeX wants escaped, e.g. Comunicação \& Cultura
+pub const SYMBOLS: &[u8] = b"&%$#_{}";
+
+pub fn is_symbol(input: &[u8]) -> bool {
+    matches!(input, [b'\\', c, ..] if SYMBOLS.contains(c))
+}
+
+// accent macros and the letters they apply to, e.g. \'e, \~{a} and \c{c}
+const ACCENTS: &[(u8, &str, &str)] = &[
+    (b'\'', "aeiouAEIOUcnCNyY", "áéíóúÁÉÍÓÚćńĆŃýÝ"),
+    (b'`', "aeiouAEIOU", "àèìòùÀÈÌÒÙ"),
+    (b'^', "aeiouAEIOU", "âêîôûÂÊÎÔÛ"),
+    (b'~', "aonAON", "ãõñÃÕÑ"),
+    (b'"', "aeiouAEIOUy", "äëïöüÄËÏÖÜÿ"),
+    (b'c', "cC", "çÇ"),
+];
+
+// the accented letter of an accent macro at the start of input and the
+// length of the macro; \c takes its letter in braces or after a space
+pub fn accent(input: &[u8]) -> Option<(char, usize)> {
+    let [b'\\', mark, rest @ ..] = input else {
+        return None;
+    };
+    let (_, letters, accented) = ACCENTS.iter().find(|(m, ..)| m == mark)?;
+    let (letter, len) = match rest {
+        [b'{', b'\\', b'i', b'}', ..] => (b'i', 6),
+        [b'{', letter, b'}', ..] => (*letter, 5),
+        [b' ', letter, ..] if mark.is_ascii_alphabetic() => (*letter, 4),
+        _ if mark.is_ascii_alphabetic() => return None,
+        [b'\\', b'i', ..] => (b'i', 4),
+        [letter, ..] => (*letter, 3),
+        [] => return None,
+    };
+    let pos = letters.bytes().position(|l| l == letter)?;
+    Some((accented.chars().nth(pos)?, len))
+}
+
+// for values written as plain text (titles, sections and keywords), which
+// do not go through the abstract parser
+pub fn decode(value: &[u8]) -> Cow<'_, [u8]> {
+    decode_with(value, true)
+}
+
+// typography turns ~ into a non-breaking space, -- into an en dash and ---
+// into an em dash; without it they are kept as written
+pub fn decode_with(value: &[u8], typography: bool) -> Cow<'_, [u8]> {
+    let typographic = |value: &[u8]| {
+        memchr::memchr(b'~', value).is_some() || memchr::memmem::find(value, b"--").is_some()
+    };
+    if memchr::memchr(b'\\', value).is_none() && !(typography && typographic(value)) {
+        return Cow::Borrowed(value);
+    }
+
+    let mut output = Vec::with_capacity(value.len());
+    // writing to a Vec does not fail
+    let _ = write_decoded(&mut output, value, typography);
+    Cow::Owned(output)
+}
+
+// decode_with straight into a writer, for values that are not kept
+pub fn write_decoded(mut write: impl Write, value: &[u8], typography: bool) -> io::Result<()> {
+    let mut rest = value;
+    loop {
+        let special = match typography {
+            true => memchr::memchr3(b'\\', b'~', b'-', rest),
+            false => memchr::memchr(b'\\', rest),
+        };
+        let Some(pos) = special else {
+            return write.write_all(rest);
+        };
+        write.write_all(&rest[..pos])?;
+        rest = &rest[pos..];
+
+        if let Some((letter, len)) = accent(rest) {
+            write.write_all(letter.encode_utf8(&mut [0; 4]).as_bytes())?;
+            rest = &rest[len..];
+            continue;
+        }
+        let (text, len) = match rest {
+            [b'\\', next, ..] if SYMBOLS.contains(next) => (&rest[1..2], 2),
+            // other commands are left alone
+            [b'\\', _, ..] => (&rest[..2], 2),
+            [b'~', ..] => ("\u{a0}".as_bytes(), 1),
+            [b'-', b'-', b'-', ..] => ("—".as_bytes(), 3),
+            [b'-', b'-', ..] => ("–".as_bytes(), 2),
+            _ => (&rest[..1], 1),
+        };
+        write.write_all(text)?;
+        rest = &rest[len..];
+    }
+}
+
+// ``quoted'' and `quoted' as typographic quotes; a lone ' is an apostrophe
+pub fn smart_quotes(text: &[u8]) -> Cow<'_, [u8]> {
+    if memchr::memchr2(b'`', b'\'', text).is_none() {
+        return Cow::Borrowed(text);
+    }
+
+    let mut output = Vec::with_capacity(text.len());
+    let mut rest = text;
+    while let [c, tail @ ..] = rest {
+        let (quote, tail) = match (c, tail) {
+            (b'`', [b'`', tail @ ..]) => ("“", tail),
+            (b'\'', [b'\'', tail @ ..]) => ("”", tail),
+            (b'`', _) => ("‘", tail),
+            (b'\'', _) => ("’", tail),
+            _ => {
+                output.push(*c);
+                rest = tail;
+                continue;
+            }
+        };
+        output.extend_from_slice(quote.as_bytes());
+        rest = tail;
+    }
+
+    Cow::Owned(output)
+}
+
+// math commands with a character of their own
+const MATH_SYMBOLS: &[(&str, &str)] = &[
+    ("alpha", "α"),
+    ("beta", "β"),
+    ("gamma", "γ"),
+    ("delta", "δ"),
+    ("epsilon", "ε"),
+    ("varepsilon", "ε"),
+    ("zeta", "ζ"),
+    ("eta", "η"),
+    ("theta", "θ"),
+    ("kappa", "κ"),
+    ("lambda", "λ"),
+    ("mu", "μ"),
+    ("nu", "ν"),
+    ("xi", "ξ"),
+    ("pi", "π"),
+    ("rho", "ρ"),
+    ("sigma", "σ"),
+    ("tau", "τ"),
+    ("phi", "φ"),
+    ("varphi", "φ"),
+    ("chi", "χ"),
+    ("psi", "ψ"),
+    ("omega", "ω"),
+    ("Gamma", "Γ"),
+    ("Delta", "Δ"),
+    ("Theta", "Θ"),
+    ("Lambda", "Λ"),
+    ("Pi", "Π"),
+    ("Sigma", "Σ"),
+    ("Phi", "Φ"),
+    ("Omega", "Ω"),
+    ("times", "×"),
+    ("cdot", "·"),
+    ("pm", "±"),
+    ("le", "≤"),
+    ("leq", "≤"),
+    ("ge", "≥"),
+    ("geq", "≥"),
+    ("neq", "≠"),
+    ("ne", "≠"),
+    ("approx", "≈"),
+    ("infty", "∞"),
+    ("to", "→"),
+    ("rightarrow", "→"),
+    ("sum", "∑"),
+    ("sqrt", "√"),
+];
+
+// a readable fallback for inline math, e.g. \alpha \leq \frac{1}{2} as
+// α ≤ 1/2; braces are dropped and unknown commands with them
+pub fn math_text(tex: &[u8]) -> String {
+    let mut output = Vec::with_capacity(tex.len());
+    // what each open brace writes when it closes
+    let mut groups: Vec<&str> = Vec::new();
+    // the groups of a \frac still to be opened
+    let mut fraction = 0;
+    let mut rest = tex;
+    while let [c, tail @ ..] = rest {
+        rest = match (c, tail) {
+            (b'\\', [next, ..]) if next.is_ascii_alphabetic() => {
+                let len = tail.iter().take_while(|c| c.is_ascii_alphabetic()).count();
+                let name = std::str::from_utf8(&tail[..len]).unwrap_or_default();
+                match MATH_SYMBOLS.iter().find(|(command, _)| *command == name) {
+                    Some((_, symbol)) => output.extend_from_slice(symbol.as_bytes()),
+                    None if name == "frac" => fraction = 2,
+                    None => {}
+                }
+                &tail[len..]
+            }
+            (b'\\', [next, tail @ ..]) if SYMBOLS.contains(next) => {
+                output.push(*next);
+                tail
+            }
+            // spacing such as \, and line breaks
+            (b'\\', [_, tail @ ..]) => {
+                output.push(b' ');
+                tail
+            }
+            (b'{', _) => {
+                groups.push(if fraction == 2 { "/" } else { "" });
+                fraction = if fraction > 0 { fraction - 1 } else { 0 };
+                tail
+            }
+            (b'}', _) => {
+                output.extend_from_slice(groups.pop().unwrap_or_default().as_bytes());
+                tail
+            }
+            (b'~', _) => {
+                output.push(b' ');
+                tail
+            }
+            _ => {
+                output.push(*c);
+                tail
+            }
+        };
+    }
+
+    String::from_utf8_lossy(&output).into_owned()
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn symbols() {
+        assert_eq!(
+            &*decode(br"Comunica\c{c}\~ao \& Cultura: 100\% em~\#1"),
+            "Comunicação & Cultura: 100% em\u{a0}#1".as_bytes()
+        );
+        assert!(matches!(decode(b"Sem escapes"), Cow::Borrowed(_)));
+        assert_eq!(&*decode(br"fim\"), br"fim\");
+    }
+
+    #[test]
+    fn typography() {
+        assert_eq!(
+            &*decode(b"p. 10--20 --- Euclides~da~Cunha"),
+            "p. 10–20 — Euclides\u{a0}da\u{a0}Cunha".as_bytes()
+        );
+        assert_eq!(&*decode(b"----"), "—-".as_bytes());
+
+        let raw = b"p. 10--20 --- Euclides~da~Cunha";
+        assert!(matches!(decode_with(raw, false), Cow::Borrowed(_)));
+        assert_eq!(&*decode_with(br"10--20 \& mais", false), b"10--20 & mais");
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(
+            &*smart_quotes(b"``Os sert\xc3\xb5es'' e `mar', d'\xc3\xa1gua"),
+            "“Os sertões” e ‘mar’, d’água".as_bytes()
+        );
+        assert!(matches!(smart_quotes(b"Sem aspas"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn accents() {
+        assert_eq!(
+            &*decode(br#"Jos\'e, \'{E}poca, a\c c\~oes, \^{o}nibus, ma\"{\i}s, \`a"#),
+            "José, Época, ações, ônibus, maïs, à".as_bytes()
+        );
+        assert_eq!(accent(br"\'\i"), Some(('í', 4)));
+        assert_eq!(accent(br"\cite{a}"), None);
+        assert_eq!(accent(br"\'x"), None);
+    }
+
+    #[test]
+    fn math() {
+        assert_eq!(math_text(br"\alpha \leq \frac{1}{2}"), "α ≤ 1/2");
+        assert_eq!(math_text(br"x^{2} \times 10\,\%"), "x^2 × 10 %");
+        assert_eq!(math_text(br"\frac{a+b}{\sqrt{n}}"), "a+b/√n");
+        assert_eq!(math_text(br"\mathbb{R}^n"), "R^n");
+    }
+}
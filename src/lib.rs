@@ -0,0 +1,31 @@
+pub mod r#abstract;
+pub mod author;
+pub mod batch;
+pub mod bib;
+pub mod check;
+pub mod citation;
+pub mod config;
+pub mod diff;
+pub mod error;
+pub mod front_matter;
+pub mod grammar;
+#[cfg(feature = "online")]
+pub mod http;
+pub mod input;
+pub mod latex;
+pub mod limits;
+pub mod locale;
+pub mod metadata;
+pub mod output;
+pub mod page;
+pub mod paragraph;
+pub mod provenance;
+pub mod query;
+pub mod sentence;
+pub mod serve;
+pub mod slug;
+pub mod source;
+pub mod stats;
+pub mod space;
+pub mod verify;
+pub mod writer;
@@ -0,0 +1,96 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use nom::error::ErrorKind;
+
+// the nesting depth when Limits::max_depth is unset, which keeps the stack
+// of the recursive abstract parser bounded
+pub const MAX_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    pub max_input: Option<usize>,
+    // fields of the metadata and pieces of the abstract
+    pub max_parts: Option<usize>,
+    // of command arguments within arguments; deeper groups are kept as
+    // literal text, MAX_DEPTH when unset
+    pub max_depth: Option<usize>,
+    // set from another thread to abandon the parse
+    pub cancel: Option<Arc<AtomicBool>>,
+}
+
+impl Limits {
+    pub const NONE: Limits = Limits {
+        max_input: None,
+        max_parts: None,
+        max_depth: None,
+        cancel: None,
+    };
+
+    pub fn depth(&self) -> usize {
+        self.max_depth.unwrap_or(MAX_DEPTH)
+    }
+
+    // checked by the parsers before each step; failures are not recoverable
+    pub fn check<'a>(
+        &self,
+        input: &'a [u8],
+        parts: usize,
+    ) -> Result<(), nom::Err<nom::error::Error<&'a [u8]>>> {
+        let failure = |kind| Err(nom::Err::Failure(nom::error::Error::new(input, kind)));
+
+        if self.max_input.is_some_and(|max| input.len() > max) {
+            return failure(ErrorKind::TooLarge);
+        }
+        if self.max_parts.is_some_and(|max| parts >= max) {
+            return failure(ErrorKind::TooLarge);
+        }
+        if let Some(cancel) = &self.cancel {
+            if cancel.load(Ordering::Relaxed) {
+                return failure(ErrorKind::Fail);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn unlimited() {
+        assert!(Limits::NONE.check(&[0; 1 << 16], 1 << 16).is_ok());
+    }
+
+    #[test]
+    fn limits() {
+        let limits = Limits {
+            max_input: Some(4),
+            max_parts: Some(2),
+            max_depth: None,
+            cancel: Some(Arc::new(AtomicBool::new(false))),
+        };
+
+        assert!(limits.check(b"abcd", 1).is_ok());
+        assert!(limits.check(b"abcde", 1).is_err());
+        assert!(limits.check(b"abcd", 2).is_err());
+
+        limits
+            .cancel
+            .as_ref()
+            .unwrap()
+            .store(true, Ordering::Relaxed);
+        assert_eq!(
+            limits.check(b"", 0),
+            Err(nom::Err::Failure(nom::error::Error::new(
+                &b""[..],
+                ErrorKind::Fail
+            )))
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use std::sync::OnceLock;
+
+use fluent::{concurrent::FluentBundle, FluentResource};
+use serde::Deserialize;
+use unic_langid::LanguageIdentifier;
+
+#[derive(Debug, PartialEq, Clone, Copy, Default, Deserialize)]
+pub enum Language {
+    #[default]
+    #[serde(rename = "pt-BR")]
+    PtBr,
+    #[serde(rename = "en")]
+    En,
+    #[serde(rename = "es")]
+    Es,
+}
+
+const LANGUAGES: [(Language, &str, &str); 3] = [
+    (
+        Language::PtBr,
+        "pt-BR",
+        include_str!("../locales/pt-BR.ftl"),
+    ),
+    (Language::En, "en", include_str!("../locales/en.ftl")),
+    (Language::Es, "es", include_str!("../locales/es.ftl")),
+];
+
+impl Language {
+    // accepts regional variants, e.g. en-US or es-AR
+    pub fn parse(tag: &str) -> Option<Self> {
+        let tag: LanguageIdentifier = tag.trim().parse().ok()?;
+        LANGUAGES.iter().find_map(|(language, id, _)| {
+            let id: LanguageIdentifier = id.parse().unwrap();
+            if tag == id || tag.language == id.language {
+                Some(*language)
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn message(self, id: &str) -> String {
+        static BUNDLES: OnceLock<Vec<FluentBundle<FluentResource>>> = OnceLock::new();
+
+        let bundles = BUNDLES.get_or_init(|| {
+            LANGUAGES
+                .iter()
+                .map(|(_, id, source)| {
+                    let resource = FluentResource::try_new(source.to_string())
+                        .expect("valid bundled translations");
+                    let mut bundle = FluentBundle::new_concurrent(vec![id.parse().unwrap()]);
+                    bundle.set_use_isolating(false);
+                    bundle
+                        .add_resource(resource)
+                        .expect("unique bundled messages");
+                    bundle
+                })
+                .collect()
+        });
+
+        let index = LANGUAGES
+            .iter()
+            .position(|(language, _, _)| *language == self)
+            .unwrap();
+        let bundle = &bundles[index];
+
+        let pattern = bundle
+            .get_message(id)
+            .and_then(|message| message.value())
+            .unwrap_or_else(|| panic!("missing translation: {}", id));
+
+        let mut errors = Vec::new();
+        bundle
+            .format_pattern(pattern, None, &mut errors)
+            .into_owned()
+    }
+
+    // the marks \enquote and quoted titles are rendered with
+    pub fn quotes(self) -> (&'static str, &'static str) {
+        match self {
+            Language::PtBr | Language::En => ("“", "”"),
+            Language::Es => ("«", "»"),
+        }
+    }
+
+    // Portuguese and Spanish keywords are sentence case; proper nouns come
+    // from `exceptions` (one word each) and acronyms such as TV are kept
+    pub fn keyword_case(self, keyword: &str, exceptions: &[String]) -> String {
+        if self == Language::En {
+            return keyword.to_owned();
+        }
+
+        // in an all caps keyword nothing is an acronym
+        let shouting = !keyword.chars().any(char::is_lowercase);
+        let words: Vec<String> = keyword
+            .split(' ')
+            .enumerate()
+            .map(|(i, word)| {
+                let lower = word.to_lowercase();
+                if let Some(exception) = exceptions.iter().find(|e| e.to_lowercase() == lower) {
+                    return exception.clone();
+                }
+                if !shouting
+                    && word.chars().filter(|c| c.is_alphabetic()).count() > 1
+                    && !word.chars().any(char::is_lowercase)
+                {
+                    return word.to_owned();
+                }
+
+                let mut chars = lower.chars();
+                match chars.next() {
+                    Some(first) if i == 0 => first.to_uppercase().chain(chars).collect(),
+                    _ => lower,
+                }
+            })
+            .collect();
+        words.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn languages() {
+        assert_eq!(Language::parse("pt-BR"), Some(Language::PtBr));
+        assert_eq!(Language::parse("en-US"), Some(Language::En));
+        assert_eq!(Language::parse("es"), Some(Language::Es));
+        assert_eq!(Language::parse("de"), None);
+    }
+
+    #[test]
+    fn messages() {
+        assert_eq!(Language::PtBr.message("abstract"), "Resumo");
+        assert_eq!(Language::En.message("keywords"), "Keywords");
+        assert_eq!(Language::Es.message("abstract"), "Resumen");
+    }
+
+    #[test]
+    fn keyword_case() {
+        let exceptions = ["Euclides".to_owned(), "Cunha".to_owned()];
+
+        assert_eq!(
+            Language::PtBr.keyword_case("EUCLIDES DA CUNHA", &exceptions),
+            "Euclides da Cunha"
+        );
+        assert_eq!(
+            Language::PtBr.keyword_case("Onde Nascem Os Fortes", &exceptions),
+            "Onde nascem os fortes"
+        );
+        assert_eq!(
+            Language::Es.keyword_case("teledramaturgia da TV", &[]),
+            "Teledramaturgia da TV"
+        );
+        assert_eq!(
+            Language::En.keyword_case("Cultural Studies", &[]),
+            "Cultural Studies"
+        );
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(Language::PtBr.quotes(), ("“", "”"));
+        assert_eq!(Language::Es.quotes(), ("«", "»"));
+    }
+
+    #[test]
+    fn bundles_are_complete() {
+        let ids = ["abstract", "keywords", "no-date"];
+        for (language, _, _) in LANGUAGES {
+            for id in ids {
+                language.message(id);
+            }
+        }
+    }
+}
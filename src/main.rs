@@ -1,46 +1,1020 @@
-use std::{fs::File, io::Read, time::SystemTime};
+use std::{path::Path, time::SystemTime};
 
+use clap::{Args, Parser, Subcommand};
 use nom_bibtex::Bibtex;
+use parse_joural_meta_nom::{
+    batch,
+    bib::{BibIndex, DoiLookup},
+    check, citation, config, diff,
+    error::ConvertError,
+    front_matter,
+    grammar::{self, Key, Multiplicity, Registry},
+    input::{self, shown, InputSource},
+    locale,
+    metadata::{self, Metadata, Only, WriteOptions},
+    output, page, provenance, query,
+    r#abstract::{self, Replacement},
+    serve, source, stats, verify,
+    writer::{self, Newline},
+};
+#[cfg(feature = "online")]
+use parse_joural_meta_nom::http;
 
-mod r#abstract;
-mod author;
-mod metadata;
-mod paragraph;
-mod space;
+fn read(path: &str) -> Result<Vec<u8>, ConvertError> {
+    input::read_path(path).map_err(|source| ConvertError::Read {
+        path: shown(path),
+        source,
+    })
+}
+
+fn parse<'a>(
+    path: &str,
+    input: &'a [u8],
+    registry: &'a Registry,
+) -> Result<Metadata<'a>, ConvertError> {
+    let (metadata, errors) = metadata::parse_all(input, registry);
+    if !errors.is_empty() {
+        return Err(ConvertError::metadata(path, input, errors));
+    }
+    for warning in &metadata.warnings {
+        eprintln!("warning: {}: {}", shown(path), warning);
+    }
+    Ok(metadata)
+}
+
+fn read_bib(path: Option<&str>) -> Result<Bibtex, ConvertError> {
+    parse_bib(path, &read_bib_text(path)?)
+}
+
+fn read_bib_text(path: Option<&str>) -> Result<String, ConvertError> {
+    let path = match path {
+        Some(path) => path,
+        None => return Ok(String::new()),
+    };
+    String::from_utf8(read(path)?).map_err(|err| ConvertError::Bibliography {
+        path: shown(path),
+        reason: err.to_string(),
+    })
+}
+
+fn parse_bib(path: Option<&str>, bib: &str) -> Result<Bibtex, ConvertError> {
+    Bibtex::parse(bib).map_err(|err| ConvertError::Bibliography {
+        path: shown(path.unwrap_or_default()),
+        reason: err.to_string(),
+    })
+}
+
+fn bib_index(bib: &Bibtex) -> BibIndex<'_> {
+    let index = BibIndex::new(bib);
+    for key in index.duplicates() {
+        eprintln!("warning: duplicated bibliography key {}; using the first entry", key);
+    }
+    index
+}
+
+fn date(date: Option<&str>) -> Result<chrono::DateTime<chrono::Utc>, ConvertError> {
+    match date {
+        Some(date) => chrono::DateTime::parse_from_rfc3339(date)
+            .map(Into::into)
+            .map_err(|_| {
+                ConvertError::Usage(format!(
+                    "invalid date {}: expected RFC 3339, e.g. 2022-06-01T00:00:00Z",
+                    date
+                ))
+            }),
+        None => Ok(SystemTime::now().into()),
+    }
+}
+
+fn newline(newline: &str) -> Newline {
+    match newline {
+        "crlf" => Newline::Crlf,
+        _ => Newline::Lf,
+    }
+}
+
+// stdin has no name to point the bibliography: field at
+fn file_name(path: Option<&str>) -> Option<String> {
+    let name = Path::new(path.filter(|path| *path != "-")?).file_name()?;
+    Some(name.to_string_lossy().into_owned())
+}
+
+#[derive(Parser)]
+#[command(version, about = "Converts journal article metadata into Hugo pages")]
+#[command(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    // without a subcommand the arguments are those of convert
+    #[command(flatten)]
+    convert: ConvertArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Convert a metadata file into a Markdown page (the default)
+    Convert(Box<ConvertArgs>),
+    /// Convert every metadata file below a directory into a mirrored tree
+    Batch(BatchArgs),
+    /// Report problems in a metadata file against the journal guidelines
+    #[command(alias = "check")]
+    Validate(ValidateArgs),
+    /// Print one field of a metadata file, e.g. title or authors[0].family
+    Get {
+        path: String,
+        field: String,
+        /// Print the value as written, without decoding LaTeX
+        #[arg(long, conflicts_with = "json")]
+        raw: bool,
+        /// Print the value as JSON
+        #[arg(long)]
+        json: bool,
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Change fields of a metadata file in place, e.g. year=2023
+    Set {
+        path: String,
+        /// KEY=VALUE, the value as LaTeX source
+        assignments: Vec<String>,
+        /// A JSON object of fields to change; null removes a field
+        #[arg(long)]
+        from_json: Option<String>,
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Serve POST /render over HTTP, answering with the page and diagnostics as JSON
+    Serve {
+        #[arg(default_value = "127.0.0.1:8080")]
+        addr: String,
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Compare two versions of a metadata file
+    Diff {
+        old: String,
+        new: String,
+        bib: Option<String>,
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Describe the metadata format
+    ExplainFormat,
+    /// Write a commented example metadata file, or its bibliography
+    AboutFormat {
+        /// Write the bibliography of the example instead
+        #[arg(long)]
+        bib: bool,
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Rewrite a metadata file in canonical form
+    Fmt {
+        path: String,
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// List pages generated by another version of the tool
+    ScanStale {
+        #[arg(default_value = ".")]
+        dir: String,
+    },
+    /// Count unknown abstract commands below a directory
+    Stats {
+        #[arg(default_value = ".")]
+        dir: String,
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Compare published pages with their metadata sources
+    Verify {
+        content: String,
+        meta: String,
+        bib: Option<String>,
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Three-way merge of metadata files
+    Merge {
+        base: String,
+        ours: String,
+        theirs: String,
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+}
+
+#[derive(Args)]
+struct ConfigArgs {
+    /// Config file, galo.toml when it exists
+    #[arg(long)]
+    config: Option<String>,
+    /// Profile of the config file to apply
+    #[arg(long)]
+    profile: Option<String>,
+    /// What ends a field: blank-line, auto or a literal, \par by default
+    #[arg(long)]
+    terminator: Option<String>,
+    /// Skip unknown keys with a warning instead of failing
+    #[arg(long)]
+    lenient: bool,
+    /// Keep unknown keys as extra front matter entries
+    #[arg(long)]
+    keep_unknown: bool,
+}
+
+#[derive(Args)]
+struct OnlineArgs {
+    /// Look up works cited by DOI that the bibliography lacks at doi.org
+    #[cfg(feature = "online")]
+    #[arg(long)]
+    online: bool,
+    /// Contact address sent with online lookups, as Crossref asks
+    #[cfg(feature = "online")]
+    #[arg(long, requires = "online")]
+    mailto: Option<String>,
+}
+
+impl OnlineArgs {
+    // one client for the whole run, so that it paces every request
+    fn dois(&self) -> Option<Box<dyn DoiLookup>> {
+        #[cfg(feature = "online")]
+        if self.online {
+            return Some(Box::new(http::Client::new(http::ClientOptions {
+                mailto: self.mailto.clone(),
+                ..http::ClientOptions::default()
+            })));
+        }
+        None
+    }
+}
+
+#[derive(Args)]
+struct ValidateArgs {
+    path: String,
+    /// Prefix every citation key must start with, e.g. Ec
+    #[arg(long)]
+    key_prefix: Option<String>,
+    /// Bibliography every cited work must be in
+    #[arg(long)]
+    bib: Option<String>,
+    #[command(flatten)]
+    config: ConfigArgs,
+}
+
+#[derive(Args)]
+struct BatchArgs {
+    /// Directory of metadata files, optionally with a .bib file per article
+    meta: String,
+    /// Directory the pages are written to
+    #[arg(required_unless_present = "stdout_multiplex")]
+    out: Option<String>,
+    /// Bibliography for articles without a .bib file of their own
+    #[arg(long)]
+    bib: Option<String>,
+    /// Page date in RFC 3339 instead of the current time
+    #[arg(long)]
+    date: Option<String>,
+    /// Write every page to stdout as NDJSON records
+    #[arg(long, conflicts_with = "out")]
+    stdout_multiplex: bool,
+    #[arg(long, value_parser = ["lf", "crlf"], default_value = "lf")]
+    newline: String,
+    /// Write a JSON summary of the run to this file, for CI annotations
+    #[arg(long)]
+    summary: Option<String>,
+    /// Write the search index entries of all converted articles to this
+    /// file as one JSON array
+    #[arg(long)]
+    search_index: Option<String>,
+    /// Extension of the metadata files; other files are skipped
+    #[arg(long, default_value = "tex")]
+    extension: String,
+    #[command(flatten)]
+    config: ConfigArgs,
+    #[command(flatten)]
+    online: OnlineArgs,
+}
+
+#[derive(Args)]
+struct ConvertArgs {
+    /// Metadata file, - for stdin
+    metadata: Option<String>,
+    /// Bibliography file, same as --bib
+    bibliography: Option<String>,
+    /// Bibliography file, - for stdin
+    #[arg(long, conflicts_with = "bibliography")]
+    bib: Option<String>,
+    /// Write the page to this file instead of stdout; a directory, such as
+    /// content/n5/, gets a file named after the title slug, which replaces
+    /// the page written there for the same metadata file before
+    #[arg(long, short)]
+    output: Option<String>,
+    #[arg(long, value_parser = ["markdown", "json"], default_value = "markdown")]
+    format: String,
+    /// YAML for Hugo or TOML for Zola
+    #[arg(long, value_parser = ["yaml", "toml"])]
+    front_matter: Option<String>,
+    /// Page date in RFC 3339 instead of the current time
+    #[arg(long)]
+    date: Option<String>,
+    /// Write the search index entry of the article to this file as a JSON array
+    #[arg(long)]
+    search_index: Option<String>,
+    /// Write the schema.org JSON-LD of the article to this file
+    #[arg(long)]
+    jsonld_file: Option<String>,
+    /// Write every generated file to stdout as NDJSON records
+    #[arg(long)]
+    stdout_multiplex: bool,
+    /// Article body to put under the generated front matter
+    #[arg(long)]
+    body_from: Option<String>,
+    /// Keep the front matter entries of --body-from that are not generated
+    #[arg(long)]
+    merge_front_matter: bool,
+    #[command(flatten)]
+    config: ConfigArgs,
+    #[command(flatten)]
+    online: OnlineArgs,
+    /// Comment the page with the tool version, input hash and flags
+    #[arg(long)]
+    provenance: bool,
+    /// Language of the generated labels, e.g. en; pt-BR by default
+    #[arg(long)]
+    language: Option<String>,
+    /// Line endings of the written files
+    #[arg(long, value_parser = ["lf", "crlf"], default_value = "lf")]
+    newline: String,
+    /// Add OpenGraph and Twitter card fields for link previews
+    #[arg(long)]
+    opengraph: bool,
+    /// Add the schema.org JSON-LD of the article as a jsonld: field
+    #[arg(long)]
+    jsonld: bool,
+    /// Add the abstract LaTeX, as written, as an abstract_tex: field
+    #[arg(long)]
+    abstract_tex: bool,
+    /// How the description is taken from the abstract
+    #[arg(long, value_parser = ["truncate-140", "first-sentence"])]
+    description: Option<String>,
+    /// Characters kept by truncate-140 before the ellipsis
+    #[arg(long)]
+    description_length: Option<usize>,
+    /// Where truncate-140 cuts a long description
+    #[arg(long, value_parser = ["character", "word", "sentence"])]
+    description_cut: Option<String>,
+    /// Markdown delimiter of italics
+    #[arg(long, value_parser = ["underscore", "asterisk"])]
+    emphasis: Option<String>,
+    /// Text between the works of \cite{a,b}, "; " by default
+    #[arg(long)]
+    citation_separator: Option<String>,
+    /// Show the full reference when hovering a citation in the Markdown abstract
+    #[arg(long, value_parser = ["abbr", "shortcode"])]
+    citation_hover: Option<String>,
+    /// List the works cited in the abstract after the keywords
+    #[arg(long)]
+    references: bool,
+    /// Write the tags in the capitalization of the article language
+    #[arg(long)]
+    keyword_case: bool,
+    /// Write the tags in alphabetical order
+    #[arg(long)]
+    sort_tags: bool,
+    /// Path the abstract images are served under; without it they are dropped
+    #[arg(long)]
+    image_prefix: Option<String>,
+    /// Add a bibliography: field with the bibliography file name under this prefix
+    #[arg(long)]
+    bibliography_prefix: Option<String>,
+    /// Drop {} and protective brace groups from the abstract text
+    #[arg(long)]
+    strip_braces: bool,
+    /// Keep ~, -- and --- as written instead of non-breaking spaces and dashes
+    #[arg(long)]
+    raw_typography: bool,
+    /// Turn ``quotes'' and `quotes' in the abstract into typographic ones
+    #[arg(long)]
+    smart_quotes: bool,
+    /// Write superscripts, subscripts and small caps as inline HTML instead
+    /// of ^x^, ~x~ and plain text, for renderers that keep raw HTML
+    #[arg(long)]
+    inline_html: bool,
+    /// Keep $...$ in the abstract for KaTeX or write it as plain text
+    #[arg(long, value_parser = ["text", "katex"])]
+    math: Option<String>,
+    /// Leave out the description field
+    #[arg(long)]
+    no_description: bool,
+    /// Front matter key to leave out
+    #[arg(long)]
+    no_field: Vec<String>,
+    /// Write only this part of the page
+    #[arg(long, value_parser = ["front-matter", "body"])]
+    only: Option<String>,
+    /// Metadata key to accept and write into the front matter as is
+    #[arg(long)]
+    extra_key: Vec<String>,
+    /// Abstract command rule as NAME=TEMPLATE
+    #[arg(long)]
+    command: Vec<String>,
+}
+
+fn apply_profile(
+    config: &ConfigArgs,
+    registry: &mut Registry,
+    options: &mut WriteOptions,
+) -> Result<(), ConvertError> {
+    config::apply_profile(
+        config.config.as_deref(),
+        config.profile.as_deref(),
+        registry,
+        options,
+    )?;
+    if let Some(terminator) = &config.terminator {
+        registry.set_terminator(terminator.clone().into());
+    }
+    if config.lenient {
+        registry.set_lenient(true);
+    }
+    if config.keep_unknown {
+        registry.set_keep_unknown(true);
+    }
+    Ok(())
+}
+
+fn write(path: &str, contents: &[u8]) -> Result<(), ConvertError> {
+    output::write_atomic(path, contents).map_err(|source| ConvertError::Write {
+        path: path.to_owned(),
+        source,
+    })
+}
 
 fn main() {
-    let mut args = std::env::args().fuse().skip(1);
-    let metadata = args.next().expect("valid metadata file");
+    let cli = Cli::parse();
+
+    if let Err(err) = run(cli) {
+        eprintln!("error: {}", err);
+        std::process::exit(err.exit_code());
+    }
+}
 
-    let mut metadata = File::open(metadata).unwrap();
-    let mut buf = Vec::new();
+fn run(cli: Cli) -> Result<(), ConvertError> {
+    let command = match cli.command {
+        Some(command) => command,
+        None => Command::Convert(Box::new(cli.convert)),
+    };
 
-    metadata.read_to_end(&mut buf).unwrap();
+    match command {
+        Command::Convert(args) => convert(*args)?,
+        Command::Batch(args) => run_batch(args)?,
+        Command::Validate(args) => validate(args)?,
+        Command::Get {
+            path,
+            field,
+            raw,
+            json,
+            config,
+        } => {
+            let mut registry = Registry::default();
+            apply_profile(&config, &mut registry, &mut WriteOptions::default())?;
 
-    let (input, metadata) = crate::metadata::metadata(buf.as_slice()).unwrap();
+            let input = read(&path)?;
+            let metadata = parse(&path, &input, &registry)?;
+            let mode = match (raw, json) {
+                (true, _) => query::Mode::Raw,
+                (_, true) => query::Mode::Json,
+                _ => query::Mode::Text,
+            };
+            if !query::get(&metadata, &registry, &field, mode, std::io::stdout())? {
+                std::process::exit(1);
+            }
+        }
+        Command::Set {
+            path,
+            assignments,
+            from_json,
+            config,
+        } => {
+            let mut registry = Registry::default();
+            apply_profile(&config, &mut registry, &mut WriteOptions::default())?;
 
-    assert!(input.is_empty());
+            let mut changes = Vec::new();
+            if let Some(patch) = from_json {
+                let invalid = |reason: String| {
+                    ConvertError::Usage(format!("invalid patch {}: {}", shown(&patch), reason))
+                };
+                let fields: serde_json::Map<String, serde_json::Value> =
+                    serde_json::from_slice(&read(&patch)?)
+                        .map_err(|err| invalid(err.to_string()))?;
+                for (key, value) in &fields {
+                    let value = source::json_source(key, value)
+                        .map_err(|err| invalid(err.to_string()))?;
+                    changes.push((key.clone(), value));
+                }
+            }
+            for assignment in &assignments {
+                let (key, value) = assignment.split_once('=').ok_or_else(|| {
+                    ConvertError::Usage(format!("{} is not KEY=VALUE", assignment))
+                })?;
+                changes.push((key.trim().to_owned(), Some(value.as_bytes().to_vec())));
+            }
 
-    let bib = args
-        .next()
-        .map(|path| {
-            let mut file = File::open(path).unwrap();
-            let mut buf = Vec::new();
-            file.read_to_end(&mut buf).unwrap();
+            let input = read(&path)?;
+            let mut output = input.clone();
+            for (key, value) in &changes {
+                output = source::set(&output, &registry, key, value.as_deref()).map_err(
+                    |source| ConvertError::Read {
+                        path: shown(&path),
+                        source,
+                    },
+                )?;
+            }
+            if output != input {
+                write(&path, &output)?;
+            }
+        }
+        Command::Serve { addr, config } => {
+            let mut registry = Registry::default();
+            let mut options = WriteOptions::default();
+            apply_profile(&config, &mut registry, &mut options)?;
 
-            String::from_utf8(buf).unwrap()
-        })
-        .unwrap_or_default();
+            serve::serve(&addr, registry, &options)?;
+        }
+        Command::Diff {
+            old,
+            new,
+            bib,
+            config,
+        } => {
+            let mut registry = Registry::default();
+            apply_profile(&config, &mut registry, &mut WriteOptions::default())?;
 
-    let bib = Bibtex::parse(&bib).expect("valid bibliographies");
+            let old_input = read(&old)?;
+            let new_input = read(&new)?;
+            let bib = read_bib(bib.as_deref())?;
+            let bib = bib_index(&bib);
 
-    let bib = bib
-        .bibliographies()
+            let old = parse(&old, &old_input, &registry)?;
+            let new = parse(&new, &new_input, &registry)?;
+
+            if diff::diff(&old, &new, &bib, std::io::stdout())? {
+                std::process::exit(1);
+            }
+        }
+        Command::ExplainFormat => grammar::explain(std::io::stdout())?,
+        Command::AboutFormat { bib: true, .. } => grammar::example_bib(std::io::stdout())?,
+        Command::AboutFormat { config, .. } => {
+            let mut registry = Registry::default();
+            apply_profile(&config, &mut registry, &mut WriteOptions::default())?;
+
+            grammar::example(std::io::stdout(), &registry)?;
+        }
+        Command::Fmt { path, config } => {
+            let mut registry = Registry::default();
+            apply_profile(&config, &mut registry, &mut WriteOptions::default())?;
+
+            let input = Path::new(&path)
+                .read_input()
+                .map_err(|source| ConvertError::Read {
+                    path: path.clone(),
+                    source,
+                })?;
+
+            let output = source::fmt(&input, &registry).map_err(|source| ConvertError::Read {
+                path: path.clone(),
+                source,
+            })?;
+            if output != *input {
+                write(&path, &output)?;
+            }
+        }
+        Command::ScanStale { dir } => {
+            if provenance::scan_stale(Path::new(&dir), std::io::stdout())? {
+                std::process::exit(1);
+            }
+        }
+        Command::Stats { dir, config } => {
+            let mut registry = Registry::default();
+            apply_profile(&config, &mut registry, &mut WriteOptions::default())?;
+
+            stats::stats(Path::new(&dir), &registry, std::io::stdout())?;
+        }
+        Command::Verify {
+            content,
+            meta,
+            bib,
+            config,
+        } => {
+            let mut registry = Registry::default();
+            let mut options = WriteOptions::default();
+            apply_profile(&config, &mut registry, &mut options)?;
+
+            let bib = read_bib(bib.as_deref())?;
+            let bib = bib_index(&bib);
+
+            let drift = verify::verify(
+                Path::new(&content),
+                Path::new(&meta),
+                &bib,
+                &registry,
+                &options,
+                std::io::stdout(),
+            )?;
+            if drift {
+                std::process::exit(1);
+            }
+        }
+        Command::Merge {
+            base,
+            ours,
+            theirs,
+            config,
+        } => {
+            let mut registry = Registry::default();
+            apply_profile(&config, &mut registry, &mut WriteOptions::default())?;
+
+            let paths = [base, ours, theirs];
+            let files = paths
+                .iter()
+                .map(|path| read(path))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let base = parse(&paths[0], &files[0], &registry)?;
+            let ours = parse(&paths[1], &files[1], &registry)?;
+            let theirs = parse(&paths[2], &files[2], &registry)?;
+
+            if source::merge(&base, &ours, &theirs, std::io::stdout())? {
+                std::process::exit(1);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn run_batch(args: BatchArgs) -> Result<(), ConvertError> {
+    let mut registry = Registry::default();
+    let mut options = WriteOptions::default();
+    apply_profile(&args.config, &mut registry, &mut options)?;
+
+    let bib = match args.bib.as_deref() {
+        Some(path) => {
+            Some(
+                String::from_utf8(read(path)?).map_err(|err| ConvertError::Bibliography {
+                    path: path.to_owned(),
+                    reason: err.to_string(),
+                })?,
+            )
+        }
+        None => None,
+    };
+    let bib_file = file_name(args.bib.as_deref());
+    let dois = args.online.dois();
+    let options = batch::BatchOptions {
+        registry: &registry,
+        options: &options,
+        date: args
+            .date
+            .as_deref()
+            .map(|value| date(Some(value)))
+            .transpose()?,
+        newline: newline(&args.newline),
+        bib: bib.as_deref(),
+        bib_file: bib_file.as_deref(),
+        search_index: args.search_index.is_some(),
+        extension: &args.extension,
+        dois: dois.as_deref(),
+    };
+
+    let meta = Path::new(&args.meta);
+    let start = std::time::Instant::now();
+    let report = match args.out {
+        Some(out) => batch::batch(
+            meta,
+            batch::Destination::<std::io::Stdout>::Directory(out.into()),
+            &options,
+        ),
+        None => batch::batch(
+            meta,
+            batch::Destination::Multiplex(std::io::stdout().lock()),
+            &options,
+        ),
+    }?;
+
+    let failed = report
         .iter()
-        .map(|b| (b.citation_key().as_bytes(), b))
-        .collect();
+        .filter(|outcome| outcome.result.is_err())
+        .count();
+    for outcome in &report {
+        let source = outcome.source.display();
+        for warning in &outcome.warnings {
+            eprintln!("warning: {}: {}", source, warning);
+        }
+        match &outcome.result {
+            Ok(page) => eprintln!("{}: ok, {}", source, page.display()),
+            Err(failure) => eprintln!("{}: failed: {}", source, failure),
+        }
+    }
+    if let Some(path) = &args.summary {
+        let mut summary = Vec::new();
+        batch::write_summary(&mut summary, &report, start.elapsed()).map_err(|source| {
+            ConvertError::Write {
+                path: path.clone(),
+                source,
+            }
+        })?;
+        write(path, &summary)?;
+    }
+    if let Some(path) = &args.search_index {
+        let mut index = Vec::new();
+        batch::write_search_index(&mut index, &report)?;
+        write(path, &index)?;
+    }
+    eprintln!("{} converted, {} failed", report.len() - failed, failed);
+    if failed > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn validate(args: ValidateArgs) -> Result<(), ConvertError> {
+    let mut options = check::CheckOptions::default();
+    let path = args.config.config.as_deref();
+    let config = config::load(path)?;
+    if let Some(profile) = config::profile(&config, path, args.config.profile.as_deref())? {
+        options = profile.check.clone();
+        options.key_prefix = options
+            .key_prefix
+            .take()
+            .or_else(|| profile.key_prefix.clone());
+    }
+    if args.key_prefix.is_some() {
+        options.key_prefix = args.key_prefix;
+    }
+
+    let mut registry = Registry::default();
+    apply_profile(&args.config, &mut registry, &mut WriteOptions::default())?;
+    let bib = read_bib(args.bib.as_deref())?;
+
+    // parse errors come first, then the checks on whatever could be read
+    let input = read(&args.path)?;
+    let (metadata, errors) = metadata::parse_all(&input, &registry);
+    let mut problems = !errors.is_empty();
+    for err in &errors {
+        println!("{}", err);
+        if let Some(snippet) = err.snippet(&input) {
+            println!("{}", snippet);
+        }
+    }
 
-    metadata
-        .wtite_to(std::io::stdout(), &bib, SystemTime::now().into())
+    problems |= check::check(&metadata, &options, std::io::stdout())?;
+    if args.bib.is_some() {
+        problems |= check::citations(&metadata, &bib_index(&bib), std::io::stdout())?;
+    }
+    if problems {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+// the profile first, so that the flags given on the command line win
+fn convert_options(args: &ConvertArgs) -> Result<(Registry, WriteOptions), ConvertError> {
+    let mut options = WriteOptions::default();
+    let mut registry = Registry::default();
+    apply_profile(&args.config, &mut registry, &mut options)?;
+
+    if let Some(tag) = &args.language {
+        options.language = locale::Language::parse(tag)
+            .ok_or_else(|| ConvertError::Usage(format!("unsupported language: {}", tag)))?;
+    }
+    options.opengraph |= args.opengraph;
+    options.jsonld |= args.jsonld;
+    options.abstract_tex |= args.abstract_tex;
+    match args.front_matter.as_deref() {
+        Some("toml") => options.front_matter = front_matter::FrontMatter::Toml,
+        Some(_) => options.front_matter = front_matter::FrontMatter::Yaml,
+        None => {}
+    }
+    match args.description.as_deref() {
+        Some("first-sentence") => {
+            options.description = metadata::DescriptionStrategy::FirstSentence
+        }
+        Some(_) => options.description = metadata::DescriptionStrategy::Truncate140,
+        None => {}
+    }
+    if args.description_length.is_some() {
+        options.description_length = args.description_length;
+    }
+    match args.description_cut.as_deref() {
+        Some("character") => options.description_cut = writer::Cut::Character,
+        Some("word") => options.description_cut = writer::Cut::Word,
+        Some(_) => options.description_cut = writer::Cut::Sentence,
+        None => {}
+    }
+    match args.emphasis.as_deref() {
+        Some("asterisk") => options.emphasis = r#abstract::Emphasis::Asterisk,
+        Some(_) => options.emphasis = r#abstract::Emphasis::Underscore,
+        None => {}
+    }
+    if args.references && options.references.is_none() {
+        options.references = Some(citation::References::default());
+    }
+    if args.citation_separator.is_some() {
+        options.citation_separator = args.citation_separator.clone();
+    }
+    match args.math.as_deref() {
+        Some("katex") => options.math = r#abstract::MathStyle::Katex,
+        Some(_) => options.math = r#abstract::MathStyle::Text,
+        None => {}
+    }
+    match args.citation_hover.as_deref() {
+        Some("abbr") => options.citation_hover = Some(citation::Hover::Abbr),
+        Some(_) => options.citation_hover = Some(citation::Hover::Shortcode),
+        None => {}
+    }
+    if args.keyword_case && options.keyword_exceptions.is_none() {
+        options.keyword_exceptions = Some(Vec::new());
+    }
+    if args.image_prefix.is_some() {
+        options.image_prefix = args.image_prefix.clone();
+    }
+    if args.bibliography_prefix.is_some() {
+        options.bibliography_prefix = args.bibliography_prefix.clone();
+    }
+    options.strip_braces |= args.strip_braces;
+    options.raw_typography |= args.raw_typography;
+    options.smart_quotes |= args.smart_quotes;
+    options.inline_html |= args.inline_html;
+    options.sort_tags |= args.sort_tags;
+    if args.no_description {
+        options.disabled.push("description".to_owned());
+    }
+    options.disabled.extend(args.no_field.iter().cloned());
+    options.only = match args.only.as_deref() {
+        Some("front-matter") => Some(Only::FrontMatter),
+        Some(_) => Some(Only::Body),
+        None => None,
+    };
+    for name in &args.extra_key {
+        registry.register(Key::extra(name.clone(), Multiplicity::Many));
+    }
+    for rule in &args.command {
+        let (name, template) = rule.split_once('=').ok_or_else(|| {
+            ConvertError::Usage(format!("command rule {} is not NAME=TEMPLATE", rule))
+        })?;
+        registry.register_command(name.to_owned(), Box::new(Replacement(template.to_owned())));
+    }
+
+    Ok((registry, options))
+}
+
+fn convert(args: ConvertArgs) -> Result<(), ConvertError> {
+    let (registry, mut options) = convert_options(&args)?;
+    let existing = match args.merge_front_matter {
+        true => page::Existing::Merge,
+        false => page::Existing::default(),
+    };
+
+    let metadata_path = args
+        .metadata
+        .ok_or_else(|| ConvertError::Usage("a metadata file is required".to_owned()))?;
+    let bib_path = args.bib.or(args.bibliography);
+    options.bibliography_file = file_name(bib_path.as_deref());
+
+    if metadata_path == "-" && bib_path.as_deref() == Some("-") {
+        return Err(ConvertError::Usage(
+            "only one of the metadata and the bibliography can be read from stdin".to_owned(),
+        ));
+    }
+    let buf = read(&metadata_path)?;
+
+    if args.provenance {
+        let positional = [Some(&metadata_path), bib_path.as_ref()];
+        let mut flags: Vec<_> = std::env::args().skip(1).collect();
+        flags.retain(|arg| !positional.contains(&Some(arg)));
+        options.provenance = Some(provenance::header(&buf, &flags));
+    }
+
+    let body = args.body_from.as_deref().map(read).transpose()?;
+    let dois = args.online.dois();
+    // otherwise page::convert keeps the date of the page being replaced
+    let fixed_date = match &args.date {
+        Some(_) => Some(date(args.date.as_deref())?),
+        None => None,
+    };
+    let sources = page::Sources {
+        path: &metadata_path,
+        metadata: &buf,
+        bib_path: bib_path.as_deref(),
+        bib: read_bib_text(bib_path.as_deref())?,
+        body: body.as_deref(),
+        output: args.output.as_deref(),
+    };
+    let mut converted = page::convert(
+        sources,
+        page::ConvertOptions {
+            registry: &registry,
+            options,
+            existing,
+            newline: newline(&args.newline),
+            date: fixed_date,
+            json: args.format == "json",
+            search_index: args.search_index.is_some(),
+            jsonld: args.jsonld_file.is_some(),
+            dois: dois.as_deref(),
+        },
+    )?;
+    for key in &converted.duplicates {
+        eprintln!("warning: duplicated bibliography key {}; using the first entry", key);
+    }
+    for warning in &converted.warnings {
+        eprintln!("warning: {}: {}", shown(&metadata_path), warning);
+    }
+
+    // files written next to the page
+    let mut files = Vec::new();
+    files.extend(args.search_index.zip(converted.search_index.take()));
+    files.extend(args.jsonld_file.zip(converted.jsonld.take()));
+
+    if args.stdout_multiplex {
+        let path = match &converted.output {
+            Some(path) => path.clone(),
+            None if metadata_path == "-" => {
+                return Err(ConvertError::Usage(
+                    "--output naming the page is required when reading metadata from stdin"
+                        .to_owned(),
+                ))
+            }
+            None => Path::new(&metadata_path)
+                .with_extension("md")
+                .to_string_lossy()
+                .into_owned(),
+        };
+        let mut stdout = std::io::stdout().lock();
+        output::write_record(&mut stdout, &path, &converted.page)?;
+        for (path, file) in files {
+            output::write_record(&mut stdout, &path, &file)?;
+        }
+        return Ok(());
+    }
+
+    converted.commit(std::io::stdout())?;
+    for (path, file) in files {
+        write(&path, &file)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn flags_over_profile() {
+        let dir = std::env::temp_dir().join(format!("flags-over-profile-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = dir.join("galo.toml");
+        std::fs::write(
+            &config,
+            r#"
+            [profile.en]
+            language = "en"
+            front_matter = "toml"
+            emphasis = "asterisk"
+            title_style = "bold"
+            image_prefix = "/img/"
+            opengraph = true
+            "#,
+        )
         .unwrap();
+
+        let cli = Cli::parse_from([
+            "galo",
+            "--config",
+            config.to_str().unwrap(),
+            "--profile",
+            "en",
+            "--language",
+            "pt-BR",
+            "--front-matter",
+            "yaml",
+            "--emphasis",
+            "underscore",
+            "artigo.tex",
+        ]);
+        let (_, options) = convert_options(&cli.convert).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(options.language, locale::Language::PtBr);
+        assert_eq!(options.front_matter, front_matter::FrontMatter::Yaml);
+        assert_eq!(options.emphasis, r#abstract::Emphasis::Underscore);
+        // what the flags leave alone still comes from the profile
+        assert_eq!(options.title_style, r#abstract::TitleStyle::Bold);
+        assert_eq!(options.image_prefix.as_deref(), Some("/img/"));
+        assert!(options.opengraph);
+    }
 }
@@ -1,42 +1,146 @@
-use std::{collections::HashMap, io::Write};
+use std::{borrow::Cow, collections::BTreeMap, io::Write, ops::Range};
 
-use nom::{branch::alt, bytes::complete::tag, character::streaming::char, multi::many1, IResult};
-use nom_bibtex::Bibliography;
+use nom::{
+    bytes::complete::take_while1, character::complete::char, error::ErrorKind, multi::many1,
+    IResult,
+};
+use serde::Deserialize;
 
 use crate::{
+    bib::BibIndex,
+    error::{location, offset, ParseError, ParseWarning},
     author::{author, Author},
-    paragraph::paragraph,
-    r#abstract::{r#abstract, Abstract},
+    front_matter::{self, FrontMatter},
+    grammar::{Field, Key, Multiplicity, Registry, DEFAULT_REGISTRY},
+    latex,
+    locale::Language,
+    paragraph::paragraph_with,
+    citation::{self, CitationStyle, Hover, References},
+    r#abstract::{abstract_with, Abstract, Emphasis, MathStyle, RenderOptions, TitleStyle},
+    slug::slug,
     space::space,
+    writer::{Cut, Truncate, DESCRIPTION_LENGTH},
 };
 
+#[derive(Default)]
 pub struct Metadata<'a> {
-    authors: Option<Vec<Author<'a>>>,
-    title: Option<&'a [u8]>,
-    first_page: Option<&'a [u8]>,
-    last_page: Option<&'a [u8]>,
-    r#abstract: Option<Abstract<'a>>,
-    keywords: Option<&'a [u8]>,
-    section: Option<&'a [u8]>,
-    number: Option<&'a [u8]>,
-    semester: Option<&'a [u8]>,
-    year: Option<&'a [u8]>,
+    pub authors: Option<Vec<Author<'a>>>,
+    pub title: Option<&'a [u8]>,
+    pub first_page: Option<&'a [u8]>,
+    pub last_page: Option<&'a [u8]>,
+    pub r#abstract: Option<Abstract<'a>>,
+    pub keywords: Option<&'a [u8]>,
+    pub section: Vec<&'a [u8]>,
+    pub number: Option<&'a [u8]>,
+    pub semester: Option<&'a [u8]>,
+    pub year: Option<&'a [u8]>,
+    pub cover: Option<&'a [u8]>,
+    pub language: Option<&'a [u8]>,
+    pub extra: Vec<(String, &'a [u8])>,
+    pub warnings: Vec<ParseWarning>,
+    // where each field lies in the input, from its key to its terminator
+    pub spans: Vec<(String, Range<usize>)>,
 }
 
-impl<'a> Default for Metadata<'a> {
-    fn default() -> Self {
-        Self {
-            authors: Default::default(),
-            title: Default::default(),
-            first_page: Default::default(),
-            last_page: Default::default(),
-            r#abstract: Default::default(),
-            keywords: Default::default(),
-            section: Default::default(),
-            number: Default::default(),
-            semester: Default::default(),
-            year: Default::default(),
-        }
+#[derive(PartialEq, Clone, Copy)]
+pub enum Only {
+    FrontMatter,
+    Body,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default, Deserialize)]
+pub enum DescriptionStrategy {
+    #[default]
+    #[serde(rename = "truncate-140")]
+    Truncate140,
+    #[serde(rename = "first-sentence")]
+    FirstSentence,
+}
+
+// what stands in for the abstract of articles without one, such as
+// editorials; {title} is replaced by the article title
+#[derive(Debug, PartialEq, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NoAbstract {
+    pub description: Option<String>,
+    // markdown written where the abstract would be
+    pub body: Option<String>,
+    // settings of articles in a section, e.g. "Editorial", over the above
+    pub sections: BTreeMap<String, NoAbstract>,
+}
+
+impl NoAbstract {
+    fn for_sections(&self, sections: &[&[u8]]) -> (Option<&str>, Option<&str>) {
+        let section = sections.iter().find_map(|section| {
+            let name = latex::decode(section);
+            self.sections.get(String::from_utf8_lossy(&name).as_ref())
+        });
+        (
+            section
+                .and_then(|section| section.description.as_deref())
+                .or(self.description.as_deref()),
+            section
+                .and_then(|section| section.body.as_deref())
+                .or(self.body.as_deref()),
+        )
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct WriteOptions {
+    pub opengraph: bool,
+    pub disabled: Vec<String>,
+    pub only: Option<Only>,
+    pub citation_style: CitationStyle,
+    pub citation_separator: Option<String>,
+    pub citation_hover: Option<Hover>,
+    // a list of the works cited in the abstract after the keywords
+    pub references: Option<References>,
+    pub constants: Vec<(String, String)>,
+    pub template: Option<String>,
+    pub provenance: Option<String>,
+    pub language: Language,
+    pub description: DescriptionStrategy,
+    // characters kept by truncate-140 before the "...", 140 when unset
+    pub description_length: Option<usize>,
+    pub description_cut: Cut,
+    pub image_prefix: Option<String>,
+    pub emphasis: Emphasis,
+    pub title_style: TitleStyle,
+    pub strip_braces: bool,
+    // keeps ~, -- and --- as written in the page
+    pub raw_typography: bool,
+    // ``these'' and `these' in the abstract as typographic quotes
+    pub smart_quotes: bool,
+    // with KaTeX the page gets math: true and $...$ as written
+    pub math: MathStyle,
+    // <sup>, <sub> and small caps spans in the abstract
+    pub inline_html: bool,
+    // when set, tags are cased by the article language keeping these words
+    pub keyword_exceptions: Option<Vec<String>>,
+    // tags alphabetically instead of in the order of the keywords
+    pub sort_tags: bool,
+    // earlier slugs of the page, see page::previous_slugs
+    pub aliases: Vec<String>,
+    // e.g. "/bib/"; with the file name of the article bibliography this
+    // becomes the bibliography: field read by the citation shortcode
+    pub bibliography_prefix: Option<String>,
+    pub bibliography_file: Option<String>,
+    pub front_matter: FrontMatter,
+    // the JSON-LD of the article as a jsonld: field
+    pub jsonld: bool,
+    // the abstract LaTeX as an abstract_tex: field
+    pub abstract_tex: bool,
+    pub no_abstract: NoAbstract,
+}
+
+impl WriteOptions {
+    fn enabled(&self, key: &str) -> bool {
+        !self.disabled.iter().any(|disabled| disabled == key)
+    }
+
+    fn decode<'t>(&self, text: &'t [u8]) -> Cow<'t, [u8]> {
+        latex::decode_with(text, !self.raw_typography)
     }
 }
 
@@ -44,223 +148,884 @@ impl<'a> Metadata<'a> {
     pub fn wtite_to(
         &self,
         mut write: impl Write,
-        bib: &HashMap<&[u8], &Bibliography>,
+        bib: &BibIndex,
         date: chrono::DateTime<chrono::Utc>,
+        options: &WriteOptions,
     ) -> std::io::Result<()> {
-        fn escape(mut write: impl Write, mut text: &[u8]) -> std::io::Result<()> {
-            loop {
-                match text.iter().position(|b| *b == b'"') {
-                    Some(pos) => {
-                        write.write_all(&text[..pos])?;
-                        write.write_all(b"\\\"")?;
-                        text = &text[pos + 1..];
-                    }
-                    None => {
-                        write.write_all(text)?;
-                        break;
+        if let (Some(template), None) = (&options.template, options.only) {
+            let mut front_matter = Vec::new();
+            self.write_front_matter(&mut front_matter, bib, date, options)?;
+            let mut body = Vec::new();
+            self.write_body(&mut body, bib, options)?;
+
+            let page = template
+                .replace("{front_matter}", &String::from_utf8_lossy(&front_matter))
+                .replace("{body}", &String::from_utf8_lossy(&body));
+            return write.write_all(page.as_bytes());
+        }
+
+        if options.only != Some(Only::Body) {
+            self.write_front_matter(&mut write, bib, date, options)?;
+        }
+        if options.only.is_none() {
+            write.write_all(b"\n")?;
+        }
+        if options.only != Some(Only::FrontMatter) {
+            self.write_body(&mut write, bib, options)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_front_matter(
+        &self,
+        write: impl Write,
+        bib: &BibIndex,
+        date: chrono::DateTime<chrono::Utc>,
+        options: &WriteOptions,
+    ) -> std::io::Result<()> {
+        front_matter::emit(
+            options.front_matter,
+            options.provenance.as_deref(),
+            write,
+            |emitter| self.front_matter_entries(emitter, bib, date, options),
+        )
+    }
+
+    // the entries in the order they are written; called once per Zola table
+    fn front_matter_entries(
+        &self,
+        emitter: &mut front_matter::Emitter,
+        bib: &BibIndex,
+        date: chrono::DateTime<chrono::Utc>,
+        options: &WriteOptions,
+    ) -> std::io::Result<()> {
+        use front_matter::Value;
+
+        let typography = !options.raw_typography;
+        if let Some(title) = self.title.filter(|_| options.enabled("title")) {
+            let title = |write: &mut dyn Write| latex::write_decoded(write, title, typography);
+            emitter.entry("title", &Value::Writer(&title))?;
+        }
+
+        if !options.aliases.is_empty() && options.enabled("aliases") {
+            emitter.list("aliases", |list| {
+                options
+                    .aliases
+                    .iter()
+                    .try_for_each(|alias| list.item(&Value::text(alias.as_bytes())))
+            })?;
+        }
+
+        let description = self.r#abstract.as_ref().filter(|_| options.enabled("description"));
+        if let Some(r#abstract) = description {
+            let description = |write: &mut dyn Write| match options.description {
+                DescriptionStrategy::Truncate140 => {
+                    self.truncated(r#abstract, bib, options, write).map(drop)
+                }
+                DescriptionStrategy::FirstSentence => {
+                    let sentences = r#abstract.sentences(bib, self.render_options(options))?;
+                    match sentences.first() {
+                        Some(sentence) => write.write_all(sentence.as_bytes()),
+                        None => Ok(()),
                     }
                 }
+            };
+            emitter.entry("description", &Value::Writer(&description))?;
+        } else if self.r#abstract.is_none() && options.enabled("description") {
+            let sections: Vec<_> = self.section_names().collect();
+            if let (Some(description), _) = options.no_abstract.for_sections(&sections) {
+                let description = Value::text(self.fill(description).into_bytes());
+                emitter.entry("description", &description)?;
             }
-            Ok(())
         }
 
-        write.write_all(b"---\n")?;
-        if let Some(title) = self.title {
-            write.write_all(b"title: \"")?;
-            escape(&mut write, title)?;
-            write.write_all(b"\"\n")?;
+        if options.enabled("date") {
+            emitter.entry("date", &Value::Date(date))?;
         }
 
-        if let Some(r#abstract) = self.r#abstract.as_ref() {
-            write.write_all(b"description: \"")?;
-            let mut buf = Vec::new();
-            r#abstract.write_to(&mut buf, bib, crate::r#abstract::Format::PlainText)?;
-            if buf.len() > 143 {
-                buf.truncate(140);
-                buf.push(b'.');
-                buf.push(b'.');
-                buf.push(b'.');
+        if let Some(authors) = self.authors.as_ref().filter(|_| options.enabled("authors")) {
+            emitter.list("authors", |list| {
+                authors.iter().try_for_each(|author| {
+                    list.item(&Value::Map(&[
+                        ("given", Value::Plain(options.decode(author.given))),
+                        ("family", Value::Plain(options.decode(author.family))),
+                    ]))
+                })
+            })?;
+        }
+
+        if let Some(keywords) = self.keywords.filter(|_| options.enabled("tags")) {
+            if emitter.wants("tags") {
+                self.write_tags(emitter, keywords, options)?;
             }
-            escape(&mut write, buf.as_slice())?;
-            write.write_all(b"\"\n")?;
         }
 
-        write.write_all(b"date: ")?;
-        write.write_all(format!("{}", date.format("%+")).as_bytes())?;
-        write.write_all(b"\n")?;
+        if let Some(first_page) = self.first_page.filter(|_| options.enabled("pages")) {
+            if let Some(last_page) = self.last_page {
+                let pages = [Value::plain(first_page), Value::plain(last_page)];
+                emitter.entry("pages", &Value::Inline(&pages))?;
+            }
+        }
 
-        if let Some(authors) = self.authors.as_ref() {
-            write.write_all(b"authors:")?;
-            for author in authors {
-                write.write_all(b"\n- given: ")?;
-                write.write_all(author.given)?;
-                write.write_all(b"\n  family: ")?;
-                write.write_all(author.family)?;
+        let mut sections = self.section_names();
+        match (sections.next(), sections.next()) {
+            _ if !options.enabled("section") => {}
+            (None, _) => {}
+            (Some(section), None) => {
+                let section =
+                    |write: &mut dyn Write| latex::write_decoded(write, section, typography);
+                emitter.entry("section", &Value::Writer(&section))?;
             }
-            write.write_all(b"\n")?;
+            (Some(_), Some(_)) => emitter.list("section", |list| {
+                self.section_names().try_for_each(|section| {
+                    let section =
+                        |write: &mut dyn Write| latex::write_decoded(write, section, typography);
+                    list.item(&Value::Writer(&section))
+                })
+            })?,
         }
 
-        if let Some(keywords) = self.keywords {
-            write.write_all(b"tags:")?;
-            for kw in String::from_utf8_lossy(keywords).split(".") {
-                let kw = kw.trim();
-                if !kw.is_empty() {
-                    write.write_all(b"\n- ")?;
-                    write.write_all(kw.as_bytes())?;
+        if let Some(number) = self.number.filter(|_| options.enabled("series")) {
+            // n12, built on the stack unless the number is unusually long
+            let mut series = [0; 16];
+            let series: Cow<[u8]> = match series.get_mut(..number.len() + 1) {
+                Some(series) => {
+                    series[0] = b'n';
+                    series[1..].copy_from_slice(number);
+                    Cow::Borrowed(series)
                 }
+                None => Cow::Owned([b"n", number].concat()),
+            };
+            emitter.entry("series", &Value::Inline(&[Value::Plain(series)]))?;
+        }
+
+        if let Some(number) = self.number.filter(|_| options.enabled("number")) {
+            emitter.entry("number", &Value::plain(number))?;
+        }
+
+        if let Some(semester) = self.semester.filter(|_| options.enabled("semester")) {
+            emitter.entry("semester", &Value::plain(semester))?;
+        }
+
+        if let Some(year) = self.year.filter(|_| options.enabled("year")) {
+            emitter.entry("year", &Value::plain(year.trim_ascii()))?;
+        }
+
+        if let (Some(prefix), Some(file)) =
+            (&options.bibliography_prefix, &options.bibliography_file)
+        {
+            if options.enabled("bibliography") {
+                let path = |write: &mut dyn Write| {
+                    write.write_all(prefix.as_bytes())?;
+                    write.write_all(file.as_bytes())
+                };
+                emitter.entry("bibliography", &Value::Writer(&path))?;
             }
-            write.write_all(b"\n")?;
         }
 
-        if let Some(first_page) = self.first_page {
-            if let Some(last_page) = self.last_page {
-                write.write_all(b"pages: [")?;
-                write.write_all(first_page)?;
-                write.write_all(b", ")?;
-                write.write_all(last_page)?;
-                write.write_all(b"]\n")?;
+        for (i, (name, value)) in self.extra.iter().enumerate() {
+            let written = self.extra[..i].iter().any(|(n, _)| n == name);
+            if written || !options.enabled(name) {
+                continue;
+            }
+
+            let values = || {
+                self.extra[i..]
+                    .iter()
+                    .filter(move |(n, _)| n == name)
+                    .map(|(_, value)| Value::text(value.trim_ascii()))
+            };
+            match values().count() {
+                1 => emitter.entry(name, &Value::text(value.trim_ascii()))?,
+                _ => emitter.list(name, |list| values().try_for_each(|v| list.item(&v)))?,
             }
         }
 
-        if let Some(section) = self.section {
-            write.write_all(b"section: \"")?;
-            escape(&mut write, section)?;
-            write.write_all(b"\"\n")?;
+        for (name, value) in &options.constants {
+            if options.enabled(name) {
+                emitter.entry(name, &Value::text(value.as_bytes()))?;
+            }
         }
 
-        if let Some(number) = self.number {
-            write.write_all(b"series: [n")?;
-            escape(&mut write, number)?;
-            write.write_all(b"]\n")?;
+        if options.opengraph {
+            if options.enabled("og_type") {
+                emitter.entry("og_type", &Value::plain(&b"article"[..]))?;
+            }
+            if let Some(cover) = self.cover.filter(|_| options.enabled("images")) {
+                let images = [Value::text(cover.trim_ascii())];
+                emitter.entry("images", &Value::Inline(&images))?;
+            }
+            if options.enabled("twitter_card") {
+                let card: &[u8] = match self.cover {
+                    Some(_) => b"summary_large_image",
+                    None => b"summary",
+                };
+                emitter.entry("twitter_card", &Value::plain(card))?;
+            }
+        }
 
-            write.write_all(b"number: ")?;
-            escape(&mut write, number)?;
-            write.write_all(b"\n")?;
+        if options.jsonld && options.enabled("jsonld") && emitter.wants("jsonld") {
+            let mut jsonld = Vec::new();
+            self.write_jsonld(&mut jsonld, bib)?;
+            emitter.entry("jsonld", &Value::text(jsonld))?;
         }
 
-        if let Some(semester) = self.semester {
-            write.write_all(b"semester: ")?;
-            escape(&mut write, semester)?;
-            write.write_all(b"\n")?;
+        let math = self.r#abstract.as_ref().is_some_and(|r#abstract| r#abstract.has_math());
+        if math && options.math == MathStyle::Katex && options.enabled("math") {
+            emitter.entry("math", &Value::Bool(true))?;
         }
 
-        if let Some(year) = self.year {
-            write.write_all(b"year: ")?;
-            escape(&mut write, String::from_utf8_lossy(year).trim().as_bytes())?;
-            write.write_all(b"\n")?;
+        let source = self.r#abstract.as_ref().map(|r#abstract| r#abstract.source());
+        if let Some(source) = source.filter(|_| options.abstract_tex) {
+            if options.enabled("abstract_tex") {
+                emitter.entry("abstract_tex", &Value::text(source.trim_ascii()))?;
+            }
         }
 
-        write.write_all(b"---\n\n")?;
+        Ok(())
+    }
+
+    // keywords split on periods; cased or sorted tags need owned strings,
+    // the others are written as they are in the input
+    fn write_tags(
+        &self,
+        emitter: &mut front_matter::Emitter,
+        keywords: &[u8],
+        options: &WriteOptions,
+    ) -> std::io::Result<()> {
+        use front_matter::Value;
+
+        let keywords = options.decode(keywords);
+        if options.keyword_exceptions.is_none() && !options.sort_tags {
+            return emitter.list("tags", |list| {
+                keywords
+                    .split(|c| *c == b'.')
+                    .map(<[u8]>::trim_ascii)
+                    .filter(|kw| !kw.is_empty())
+                    .try_for_each(|kw| list.item(&Value::plain(kw)))
+            });
+        }
+
+        let language = self.language(options);
+        let mut tags: Vec<_> = split_keywords(&keywords)
+            .into_iter()
+            .map(|kw| match options.keyword_exceptions.as_deref() {
+                Some(exceptions) => language.keyword_case(&kw, exceptions),
+                None => kw,
+            })
+            .collect();
+        if options.sort_tags {
+            // accents and case only break ties, so Sertão sorts next to Sertanejo
+            tags.sort_by_cached_key(|tag| (slug(tag), tag.clone()));
+        }
+        emitter.list("tags", |list| {
+            tags.iter()
+                .try_for_each(|tag| list.item(&Value::plain(tag.as_bytes())))
+        })
+    }
+
+    fn write_body(
+        &self,
+        mut write: impl Write,
+        bib: &BibIndex,
+        options: &WriteOptions,
+    ) -> std::io::Result<()> {
+        // without a front matter the provenance goes into the markdown itself
+        if let (Some(provenance), Some(Only::Body)) = (&options.provenance, options.only) {
+            writeln!(write, "<!-- {} -->\n", provenance)?;
+        }
+
+        let language = self.language(options);
 
         if let Some(r#abstract) = self.r#abstract.as_ref() {
-            write.write_all(b"**Resumo:** ")?;
-            r#abstract.write_to(&mut write, bib, crate::r#abstract::Format::Markdown)?;
+            write!(write, "**{}:** ", language.message("abstract"))?;
+            r#abstract.write_with_citations(
+                &mut write,
+                bib,
+                crate::r#abstract::Format::Markdown,
+                self.render_options(options),
+            )?;
             write.write_all(b"\n\n")?;
+        } else if let (_, Some(body)) = options.no_abstract.for_sections(&self.sections()) {
+            let body = self.fill(body);
+            if !body.is_empty() {
+                write.write_all(body.trim_end().as_bytes())?;
+                write.write_all(b"\n\n")?;
+            }
         }
 
         if let Some(keywords) = self.keywords {
-            write.write_all(b"**Palavras-chave:** ")?;
-            write.write_all(keywords)?;
+            write!(write, "**{}:** ", language.message("keywords"))?;
+            write.write_all(&options.decode(keywords))?;
             write.write_all(b"\n")?;
         }
 
+        if let (Some(references), Some(summary)) = (&options.references, &self.r#abstract) {
+            // in order of first citation, once each
+            let mut keys = Vec::new();
+            for key in summary.citation_keys() {
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+            let entries: Vec<_> = keys.into_iter().filter_map(|key| bib.get(key)).collect();
+            if !entries.is_empty() {
+                if self.keywords.is_some() {
+                    write.write_all(b"\n")?;
+                }
+                writeln!(write, "**{}**\n", language.message("references"))?;
+                citation::write_references(&mut write, entries, references, options.emphasis)?;
+                write.write_all(b"\n")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // a NoAbstract text for this article
+    fn fill(&self, text: &str) -> String {
+        let title = self.title.map(latex::decode).unwrap_or_default();
+        text.replace("{title}", &String::from_utf8_lossy(&title))
+    }
+
+    pub fn slug(&self) -> Option<String> {
+        let title = latex::decode(self.title?);
+        Some(slug(&String::from_utf8_lossy(&title)))
+    }
+
+    // the article language wins over the one chosen for the run
+    pub fn language(&self, options: &WriteOptions) -> Language {
+        self.language
+            .and_then(|language| Language::parse(&String::from_utf8_lossy(language)))
+            .unwrap_or(options.language)
+    }
+
+    // both section=A; B\par and a repeated section key list several sections
+    pub fn sections(&self) -> Vec<&'a [u8]> {
+        self.section_names().collect()
+    }
+
+    fn section_names(&self) -> impl Iterator<Item = &'a [u8]> + '_ {
+        self.section
+            .iter()
+            .flat_map(|section| section.split(|c| *c == b';'))
+            .map(<[u8]>::trim_ascii)
+            .filter(|section| !section.is_empty())
+    }
+
+    // the abstract cut down to the description length; returns whether the
+    // cut was moved back to keep a citation whole
+    fn truncated(
+        &self,
+        r#abstract: &Abstract,
+        bib: &BibIndex,
+        options: &WriteOptions,
+        write: impl Write,
+    ) -> std::io::Result<bool> {
+        let mut description = Truncate::new(
+            options.description_length.unwrap_or(DESCRIPTION_LENGTH),
+            options.description_cut,
+        );
+        let citations = r#abstract.write_with_citations(
+            &mut description,
+            bib,
+            crate::r#abstract::Format::PlainText,
+            self.render_options(options),
+        )?;
+        description.finish(&citations, write)
+    }
+
+    /// What writing the page with `options` leaves out or shortens, located
+    /// in `input`, the bytes the metadata was read from.
+    pub fn write_warnings(
+        &self,
+        input: &[u8],
+        bib: &BibIndex,
+        options: &WriteOptions,
+    ) -> std::io::Result<Vec<ParseWarning>> {
+        let mut warnings = Vec::new();
+        let images = self.r#abstract.iter().flat_map(Abstract::images);
+        for source in images.filter(|_| options.image_prefix.is_none()) {
+            let (line, column) = location(input, offset(input, source));
+            warnings.push(ParseWarning::DroppedImage {
+                command: String::from_utf8_lossy(source).into_owned(),
+                line,
+                column,
+            });
+        }
+
+        let truncated = options.description == DescriptionStrategy::Truncate140
+            && options.enabled("description");
+        if let Some(r#abstract) = self.r#abstract.as_ref().filter(|_| truncated) {
+            if self.truncated(r#abstract, bib, options, std::io::sink())? {
+                let (line, column) = location(input, offset(input, r#abstract.source()));
+                warnings.push(ParseWarning::DescriptionCut { line, column });
+            }
+        }
+        Ok(warnings)
+    }
+
+    fn render_options<'o>(&self, options: &'o WriteOptions) -> RenderOptions<'o> {
+        RenderOptions {
+            image_prefix: options.image_prefix.as_deref(),
+            emphasis: options.emphasis,
+            title_style: options.title_style,
+            strip_braces: options.strip_braces,
+            raw_typography: options.raw_typography,
+            hover: options.citation_hover,
+            smart_quotes: options.smart_quotes,
+            math: options.math,
+            citation_separator: options.citation_separator.as_deref(),
+            inline_html: options.inline_html,
+            style: options.citation_style,
+            language: self.language(options),
+        }
+    }
+
+    // one entry of the client-side search index
+    pub fn search_entry(&self, bib: &BibIndex) -> std::io::Result<serde_json::Value> {
+        let title = self.title.map(latex::decode).unwrap_or_default();
+        let authors: Vec<_> = self.authors.iter().flatten().collect();
+        let names: Vec<_> = authors
+            .iter()
+            .map(|author| {
+                let name = [author.given, b" ", author.family].concat();
+                String::from_utf8_lossy(&latex::decode(&name)).into_owned()
+            })
+            .collect();
+        let slugs: Vec<_> = authors.iter().map(|author| author.slug()).collect();
+
+        let mut buf = Vec::new();
+        if let Some(r#abstract) = self.r#abstract.as_ref() {
+            r#abstract.write_to(&mut buf, bib, crate::r#abstract::Format::PlainText)?;
+        }
+
+        Ok(serde_json::json!({
+            "title": String::from_utf8_lossy(&title).trim(),
+            "authors": names,
+            "author_slugs": slugs,
+            "abstract": String::from_utf8_lossy(&buf).trim(),
+            "keywords": self.keywords.map(keyword_list).unwrap_or_default(),
+            "slug": self.slug().unwrap_or_default(),
+        }))
+    }
+
+    pub fn write_search_entry(
+        &self,
+        mut write: impl Write,
+        bib: &BibIndex,
+    ) -> std::io::Result<()> {
+        serde_json::to_writer(&mut write, &self.search_entry(bib)?)?;
+        Ok(())
+    }
+
+    // schema.org ScholarlyArticle, read by search engines for rich results
+    pub fn jsonld(&self, bib: &BibIndex) -> std::io::Result<serde_json::Value> {
+        let mut jsonld = serde_json::json!({
+            "@context": "https://schema.org",
+            "@type": "ScholarlyArticle",
+        });
+
+        if let Some(title) = self.title {
+            let title = latex::decode(title);
+            jsonld["headline"] = String::from_utf8_lossy(&title).trim().into();
+        }
+
+        if let Some(authors) = &self.authors {
+            let authors: Vec<_> = authors
+                .iter()
+                .map(|author| {
+                    let given = latex::decode(author.given.trim_ascii());
+                    let family = latex::decode(author.family.trim_ascii());
+                    let (given, family) =
+                        (String::from_utf8_lossy(&given), String::from_utf8_lossy(&family));
+                    serde_json::json!({
+                        "@type": "Person",
+                        "name": format!("{} {}", given, family),
+                        "givenName": given,
+                        "familyName": family,
+                    })
+                })
+                .collect();
+            jsonld["author"] = authors.into();
+        }
+
+        if let (Some(first_page), Some(last_page)) = (self.first_page, self.last_page) {
+            jsonld["pageStart"] = String::from_utf8_lossy(first_page.trim_ascii()).into();
+            jsonld["pageEnd"] = String::from_utf8_lossy(last_page.trim_ascii()).into();
+        }
+
+        if let Some(keywords) = self.keywords {
+            jsonld["keywords"] = keyword_list(keywords).into();
+        }
+
+        if let Some(r#abstract) = &self.r#abstract {
+            let mut buf = Vec::new();
+            r#abstract.write_to(&mut buf, bib, crate::r#abstract::Format::PlainText)?;
+            jsonld["abstract"] = String::from_utf8_lossy(&buf).trim().into();
+        }
+
+        Ok(jsonld)
+    }
+
+    pub fn write_jsonld(&self, mut write: impl Write, bib: &BibIndex) -> std::io::Result<()> {
+        serde_json::to_writer(&mut write, &self.jsonld(bib)?)?;
         Ok(())
     }
 }
 
-fn divisor(input: &[u8]) -> IResult<&[u8], ()> {
+pub(crate) fn keyword_list(keywords: &[u8]) -> Vec<String> {
+    split_keywords(&latex::decode(keywords))
+}
+
+fn split_keywords(keywords: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(keywords)
+        .split('.')
+        .map(str::trim)
+        .filter(|kw| !kw.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+pub(crate) fn divisor(input: &[u8]) -> IResult<&[u8], ()> {
     let (input, _) = space(input)?;
     let (input, _) = char('=')(input)?;
     let (input, _) = space(input)?;
     Ok((input, ()))
 }
 
-pub fn metadata(input: &[u8]) -> IResult<&[u8], Metadata> {
-    let mut key = alt::<&[u8], _, nom::error::Error<&[u8]>, _>((
-        tag("authors"),
-        tag("title"),
-        tag("first_page"),
-        tag("last_page"),
-        tag("abstract"),
-        tag("keywords"),
-        tag("section"),
-        tag("number"),
-        tag("semester"),
-        tag("year"),
-        tag("\\par"),
-    ));
-
-    let mut input = input;
+pub enum Value<'a> {
+    Text(&'a [u8]),
+    Authors(Vec<Author<'a>>),
+    Abstract(Abstract<'a>),
+}
+
+pub fn text_value<'a>(input: &'a [u8], registry: &'a Registry) -> IResult<&'a [u8], Value<'a>> {
+    let (input, text) = paragraph_with(input, &registry.terminator)?;
+    Ok((input, Value::Text(text)))
+}
+
+// what the parser of a value leaves of it, such as an unknown command in
+// the abstract, is an error rather than silently dropped
+fn value_end(rest: &[u8]) -> IResult<&[u8], ()> {
+    match rest.trim_ascii() {
+        [] => Ok((rest, ())),
+        _ => Err(nom::Err::Error(nom::error::Error::new(rest, ErrorKind::Eof))),
+    }
+}
+
+// the value is cut at the terminator first, so that authors and abstracts
+// also end at a blank line
+pub fn authors_value<'a>(input: &'a [u8], registry: &'a Registry) -> IResult<&'a [u8], Value<'a>> {
+    let (input, value) = paragraph_with(input, &registry.terminator)?;
+    let (rest, authors) = many1(author)(value)?;
+    value_end(rest)?;
+    Ok((input, Value::Authors(authors)))
+}
+
+pub fn abstract_value<'a>(input: &'a [u8], registry: &'a Registry) -> IResult<&'a [u8], Value<'a>> {
+    let (input, value) = paragraph_with(input, &registry.terminator)?;
+    let (rest, summary) = abstract_with(value, &registry.commands, &registry.limits)?;
+    value_end(rest)?;
+    Ok((input, Value::Abstract(summary)))
+}
+
+impl<'a> Metadata<'a> {
+    // false when the value is not of the field of `key`, as with a key
+    // registered with the parser of another field
+    fn assign(&mut self, key: &Key, value: Value<'a>) -> bool {
+        match (key.field, value) {
+            (Field::Authors, Value::Authors(mut authors)) => {
+                // authors without order> follow the ordered ones, as written
+                authors.sort_by_key(|author| author.order.unwrap_or(u32::MAX));
+                self.authors = Some(authors);
+            }
+            (Field::Abstract, Value::Abstract(summary)) => self.r#abstract = Some(summary),
+            (Field::Title, Value::Text(text)) => self.title = Some(text),
+            (Field::FirstPage, Value::Text(text)) => self.first_page = Some(text),
+            (Field::LastPage, Value::Text(text)) => self.last_page = Some(text),
+            (Field::Keywords, Value::Text(text)) => self.keywords = Some(text),
+            (Field::Section, Value::Text(text)) => self.section.push(text),
+            (Field::Number, Value::Text(text)) => self.number = Some(text),
+            (Field::Semester, Value::Text(text)) => self.semester = Some(text),
+            (Field::Year, Value::Text(text)) => self.year = Some(text),
+            (Field::Cover, Value::Text(text)) => self.cover = Some(text),
+            (Field::Language, Value::Text(text)) => self.language = Some(text),
+            (Field::Extra, Value::Text(text)) => self.extra.push((key.name.to_string(), text)),
+            _ => return false,
+        }
+        true
+    }
+}
+
+pub fn metadata(input: &[u8]) -> IResult<&[u8], Metadata<'_>> {
+    metadata_with(input, &DEFAULT_REGISTRY)
+}
+
+pub fn metadata_with<'a>(
+    input: &'a [u8],
+    registry: &'a Registry,
+) -> IResult<&'a [u8], Metadata<'a>> {
+    fields(input, registry, None).map_err(|(err, _)| err)
+}
+
+/// The whole of `input` as metadata, failing with the field that does not
+/// parse and where.
+pub fn parse<'a>(input: &'a [u8], registry: &'a Registry) -> Result<Metadata<'a>, ParseError> {
+    match fields(input, registry, None) {
+        Ok((rest, metadata)) if rest.trim_ascii().is_empty() => Ok(metadata),
+        Ok((rest, _)) => Err(ParseError::unexpected(input, rest)),
+        Err((err, field)) => Err(ParseError::new(input, err, field)),
+    }
+}
+
+/// Like [parse], but a field that does not parse is skipped up to its
+/// terminator, so every such field is reported at once. What could be
+/// read comes along, e.g. to run the checks on it.
+pub fn parse_all<'a>(
+    input: &'a [u8],
+    registry: &'a Registry,
+) -> (Metadata<'a>, Vec<ParseError>) {
+    let mut errors = Vec::new();
+    match fields(input, registry, Some(&mut errors)) {
+        Ok((_, metadata)) => (metadata, errors),
+        // the limits were hit, nothing after that point is read
+        Err((err, field)) => {
+            errors.push(ParseError::new(input, err, field));
+            (Metadata::default(), errors)
+        }
+    }
+}
+
+// the error comes with the key being read, if any
+type FieldError<'a> = (nom::Err<nom::error::Error<&'a [u8]>>, Option<String>);
+
+// an unregistered `name=`, as skipped in lenient mode
+fn unknown_key(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    let (rest, name) =
+        take_while1(|c: u8| c.is_ascii_alphanumeric() || matches!(c, b'_' | b'-'))(input)?;
+    let (rest, _) = divisor(rest)?;
+    Ok((rest, name))
+}
+
+// front matter entries the page writes itself, and slug, which Hugo would
+// take over the file name; kept unknown keys must not repeat them
+const GENERATED: &[&str] = &[
+    "title",
+    "aliases",
+    "description",
+    "date",
+    "authors",
+    "tags",
+    "pages",
+    "section",
+    "series",
+    "number",
+    "semester",
+    "year",
+    "bibliography",
+    "og_type",
+    "images",
+    "twitter_card",
+    "jsonld",
+    "math",
+    "abstract_tex",
+    "slug",
+];
+
+// the Windows-1252 characters UTF-8 continuation bytes turn into
+const CP1252: &str = "€‚ƒ„…†‡ˆ‰Š‹ŒŽ‘’“”•–—˜™š›œžŸ";
+
+// where `text` first looks like UTF-8 decoded as Latin-1 or Windows-1252,
+// e.g. Ã© for é and â€“ for –, and the two characters seen there
+fn mojibake(text: &[u8]) -> Option<(usize, &str)> {
+    let text = std::str::from_utf8(text).ok()?;
+    text.char_indices().find_map(|(i, c)| {
+        let rest = &text[i + c.len_utf8()..];
+        let next = rest.chars().next()?;
+        let suspicious = match c {
+            'â' => next == '€',
+            'Ã' | 'Â' => ('\u{80}'..='\u{bf}').contains(&next) || CP1252.contains(next),
+            _ => false,
+        };
+        suspicious.then(|| (i, &text[i..i + c.len_utf8() + next.len_utf8()]))
+    })
+}
+
+// warns about the first suspicious sequence of the field spanning `range`
+fn check_encoding(metadata: &mut Metadata, start: &[u8], key: &str, range: Range<usize>) {
+    if let Some((pos, sequence)) = mojibake(&start[range.clone()]) {
+        let (line, column) = location(start, range.start + pos);
+        metadata.warnings.push(ParseWarning::Mojibake {
+            key: key.to_owned(),
+            sequence: sequence.to_owned(),
+            line,
+            column,
+        });
+    }
+}
+
+// warns once about every \ref the abstract loses
+fn check_references(metadata: &mut Metadata, start: &[u8]) {
+    let references = metadata.r#abstract.iter().flat_map(Abstract::references);
+    let warnings: Vec<_> = references
+        .map(|source| {
+            let (line, column) = location(start, offset(start, source));
+            ParseWarning::DroppedReference {
+                command: String::from_utf8_lossy(source).into_owned(),
+                line,
+                column,
+            }
+        })
+        .collect();
+    metadata.warnings.extend(warnings);
+}
+
+// with `errors`, a failing field is recorded there and skipped; only the
+// limits end the parse
+fn recover<'a>(
+    errors: &mut Option<&mut Vec<ParseError>>,
+    start: &'a [u8],
+    field_start: &'a [u8],
+    err: nom::Err<nom::error::Error<&'a [u8]>>,
+    field: Option<String>,
+    registry: &Registry,
+) -> Result<&'a [u8], FieldError<'a>> {
+    let errors = match errors {
+        Some(errors) => errors,
+        None => return Err((err, field)),
+    };
+    if let nom::Err::Failure(nom::error::Error {
+        code: ErrorKind::TooLarge | ErrorKind::Fail,
+        ..
+    }) = err
+    {
+        return Err((err, field));
+    }
+
+    errors.push(ParseError::new(start, err, field));
+    Ok(match registry.terminator.find(field_start) {
+        Some(range) => &field_start[range.end..],
+        None => &field_start[field_start.len()..],
+    })
+}
+
+fn fields<'a>(
+    mut input: &'a [u8],
+    registry: &'a Registry,
+    mut errors: Option<&mut Vec<ParseError>>,
+) -> Result<(&'a [u8], Metadata<'a>), FieldError<'a>> {
+    let start = input;
     let mut metadata = Metadata::default();
+    let mut seen = Vec::new();
 
     loop {
-        let (inp, _) = space(input)?;
-        let (inp, key) = match key(inp) {
-            Ok(ok) => ok,
-            Err(_) => break,
-        };
+        registry
+            .limits
+            .check(input, seen.len())
+            .map_err(|err| (err, None))?;
+
+        let (inp, _) = space(input).map_err(|err| (err, None))?;
+        input = inp;
 
-        if key == b"\\par" {
+        if let Some(inp) = registry.terminator.strip(inp) {
             input = inp;
             continue;
         }
 
-        let (inp, _) = divisor(inp)?;
+        // a LaTeX comment, up to the end of the line
+        if let Some(comment) = inp.strip_prefix(b"%") {
+            input = match memchr::memchr(b'\n', comment) {
+                Some(end) => &comment[end + 1..],
+                None => &comment[comment.len()..],
+            };
+            continue;
+        }
 
-        input = match key {
-            b"authors" => {
-                let (inp, authors) = many1(author)(inp)?;
-                let (inp, _) = paragraph(inp)?;
-                metadata.authors = Some(authors);
-                inp
-            }
-            b"title" => {
-                let (inp, title) = paragraph(inp)?;
-                metadata.title = Some(title);
-                inp
-            }
-            b"first_page" => {
-                let (inp, first_page) = paragraph(inp)?;
-                metadata.first_page = Some(first_page);
-                inp
-            }
-            b"last_page" => {
-                let (inp, last_page) = paragraph(inp)?;
-                metadata.last_page = Some(last_page);
-                inp
-            }
-            b"abstract" => {
-                let (inp, summary) = r#abstract(inp)?;
-                let (inp, _) = paragraph(inp)?;
-                metadata.r#abstract = Some(summary);
-                inp
-            }
-            b"keywords" => {
-                let (inp, keywords) = paragraph(inp)?;
-                metadata.keywords = Some(keywords);
-                inp
-            }
-            b"section" => {
-                let (inp, section) = paragraph(inp)?;
-                metadata.section = Some(section);
-                inp
-            }
-            b"number" => {
-                let (inp, number) = paragraph(inp)?;
-                metadata.number = Some(number);
-                inp
+        let (inp, key) = match registry.key(inp) {
+            Ok(ok) => ok,
+            Err(_) => {
+                let lenient = registry.lenient || registry.keep_unknown;
+                if let Some((value, name)) = unknown_key(inp).ok().filter(|_| lenient) {
+                    let name = String::from_utf8_lossy(name).into_owned();
+                    let (line, column) = location(start, start.len() - inp.len());
+                    let (inp, value) = paragraph_with(value, &registry.terminator)
+                        .map_err(|err| (err, None))?;
+                    if registry.keep_unknown && GENERATED.contains(&name.as_str()) {
+                        metadata.warnings.push(ParseWarning::ReservedKey {
+                            key: name,
+                            line,
+                            column,
+                        });
+                    } else if registry.keep_unknown {
+                        let span = start.len() - input.len()..start.len() - inp.len();
+                        check_encoding(&mut metadata, start, &name, span.clone());
+                        metadata.spans.push((name.clone(), span));
+                        metadata.extra.push((name, value));
+                    } else {
+                        metadata.warnings.push(ParseWarning::UnknownKey {
+                            key: name,
+                            line,
+                            column,
+                        });
+                    }
+                    input = inp;
+                    continue;
+                }
+
+                // the end of the metadata, unless recovering
+                if errors.is_none() || inp.is_empty() {
+                    break;
+                }
+                let err = nom::Err::Error(nom::error::Error::new(inp, ErrorKind::Tag));
+                input = recover(&mut errors, start, inp, err, None, registry)?;
+                continue;
             }
-            b"semester" => {
-                let (inp, semester) = paragraph(inp)?;
-                metadata.semester = Some(semester);
-                inp
+        };
+        let name = || Some(key.name.to_string());
+
+        if key.multiplicity == Multiplicity::Once && seen.contains(&key.name) {
+            let err = nom::Err::Error(nom::error::Error::new(input, ErrorKind::Verify));
+            input = recover(&mut errors, start, input, err, name(), registry)?;
+            continue;
+        }
+        seen.push(key.name.clone());
+
+        match divisor(inp).and_then(|(inp, _)| (key.parse)(inp, registry)) {
+            Ok((inp, Value::Text(text)))
+                if registry.shape(&key.name).is_some_and(|shape| !shape.accepts(text)) =>
+            {
+                // reported with what was expected when recovering
+                let err = nom::Err::Error(nom::error::Error::new(text, ErrorKind::Satisfy));
+                let errors = match errors.as_mut() {
+                    Some(errors) => errors,
+                    None => return Err((err, name())),
+                };
+                let (line, column) = location(start, offset(start, text));
+                errors.push(ParseError::Shape {
+                    field: key.name.to_string(),
+                    expected: registry.shape(&key.name).unwrap().expected(),
+                    line,
+                    column,
+                });
+                input = inp;
             }
-            b"year" => {
-                let (inp, year) = paragraph(inp)?;
-                metadata.year = Some(year);
-                inp
+            Ok((inp, value)) => {
+                if !metadata.assign(key, value) {
+                    let err = nom::Err::Error(nom::error::Error::new(input, ErrorKind::MapRes));
+                    input = recover(&mut errors, start, input, err, name(), registry)?;
+                    continue;
+                }
+                if key.field == Field::Abstract {
+                    check_references(&mut metadata, start);
+                }
+                let span = start.len() - input.len()..start.len() - inp.len();
+                check_encoding(&mut metadata, start, &key.name, span.clone());
+                metadata.spans.push((key.name.to_string(), span));
+                input = inp;
             }
-            _ => unreachable!(),
+            Err(err) => input = recover(&mut errors, start, input, err, name(), registry)?,
         }
     }
 
@@ -271,6 +1036,7 @@ pub fn metadata(input: &[u8]) -> IResult<&[u8], Metadata> {
 mod test {
 
     use super::*;
+    use crate::{grammar::Shape, limits::Limits, paragraph::Terminator};
 
     #[test]
     fn euclides_da_cunha() {
@@ -280,4 +1046,862 @@ mod test {
 
         assert!(input.is_empty());
     }
+
+    #[test]
+    fn trailing_newline() {
+        let (input, metadata) = metadata(b"title=Sertao\\par\nyear=2022\\par\n").unwrap();
+
+        assert!(input.is_empty());
+        assert_eq!(metadata.year, Some(&b"2022"[..]));
+    }
+
+    #[test]
+    fn blank_line_fields() {
+        let mut registry = Registry::default();
+        registry.set_terminator(Terminator::BlankLine);
+
+        let input = "authors=given> Aurora, family> Leão\n\ntitle=Sertão\n\nabstract=Sobre \\textit{Os sertões}.\n   \nyear=2022\n";
+        let (input, metadata) = metadata_with(input.as_bytes(), &registry).unwrap();
+
+        assert!(input.is_empty());
+        assert_eq!(metadata.authors.unwrap()[0].family, "Leão".as_bytes());
+        assert_eq!(metadata.title, Some(&b"Sert\xc3\xa3o"[..]));
+        let mut source = Vec::new();
+        metadata.r#abstract.unwrap().write_source(&mut source).unwrap();
+        assert_eq!(source, "Sobre \\textit{Os sertões}.".as_bytes());
+        assert_eq!(metadata.year, Some(&b"2022"[..]));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let parse = |input: &str| parse(input.as_bytes(), &DEFAULT_REGISTRY).map(|_| ());
+
+        assert_eq!(
+            parse("title=Sertao\\par\nauthors=given> Aurora\\par"),
+            Err(ParseError::Field {
+                field: "authors".to_owned(),
+                line: 2,
+                column: 22,
+            })
+        );
+        assert_eq!(
+            parse("title=A\\par\ntitle=B\\par"),
+            Err(ParseError::Duplicated {
+                field: "title".to_owned(),
+                line: 2,
+                column: 1,
+            })
+        );
+        assert_eq!(
+            parse("title=A\\par\n  titel=B\\par"),
+            Err(ParseError::Unexpected {
+                line: 2,
+                column: 3,
+                near: "titel=B\\par".to_owned(),
+            })
+        );
+        assert_eq!(parse("title=A\\par\n"), Ok(()));
+    }
+
+    #[test]
+    fn shapes() {
+        let mut registry = Registry::default();
+        for name in ["volume", "published", "license", "funding"] {
+            registry.register(Key::extra(name.to_owned(), Multiplicity::Many));
+        }
+        registry.set_shape("volume", Shape::Integer).unwrap();
+        registry.set_shape("published", Shape::Date).unwrap();
+        let licenses = vec!["cc-by".to_owned(), "cc-by-sa".to_owned()];
+        registry.set_shape("license", Shape::OneOf(licenses)).unwrap();
+        registry.set_shape("funding", Shape::Text).unwrap();
+
+        let input = "volume= 12 \\par published=2022-06-01\\par license=cc-by\\par funding=X\\par";
+        let (metadata, errors) = parse_all(input.as_bytes(), &registry);
+        assert!(errors.is_empty());
+        assert_eq!(metadata.extra.len(), 4);
+
+        let input = "volume=doze\\par\npublished=01/06/2022\\par\nlicense=cc0\\par\nyear=2022\\par";
+        let (metadata, errors) = parse_all(input.as_bytes(), &registry);
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "invalid volume field at line 1, column 8: expected an integer",
+                "invalid published field at line 2, column 11: expected a date such as 2022-06-01",
+                "invalid license field at line 3, column 9: expected one of cc-by, cc-by-sa",
+            ]
+        );
+        assert!(metadata.extra.is_empty());
+        assert_eq!(metadata.year, Some(&b"2022"[..]));
+
+        assert_eq!(
+            parse(b"volume=doze\\par", &registry).map(|_| ()),
+            Err(ParseError::Field {
+                field: "volume".to_owned(),
+                line: 1,
+                column: 8,
+            })
+        );
+        assert!(registry.set_shape("year", Shape::Integer).is_err());
+    }
+
+    #[test]
+    fn truncated() {
+        let parse = |input: &str| parse(input.as_bytes(), &DEFAULT_REGISTRY).map(|_| ());
+        let end = |field: &str, line, column| {
+            Err(ParseError::UnexpectedEnd {
+                field: field.to_owned(),
+                line,
+                column,
+            })
+        };
+
+        assert_eq!(parse("title=Sertao\\par\nyear"), end("year", 2, 5));
+        assert_eq!(parse("title=Sertao\\par\nyear  "), end("year", 2, 7));
+        assert_eq!(parse("authors=given> Aurora"), end("authors", 1, 22));
+        assert_eq!(parse("authors=given> Aurora, family> Le"), Ok(()));
+        assert_eq!(
+            parse("year").unwrap_err().to_string(),
+            "unexpected end of input while reading key year at line 1, column 5"
+        );
+
+        let (_, errors) = parse_all(b"title=A\\par\nyear", &DEFAULT_REGISTRY);
+        assert_eq!(
+            errors,
+            [ParseError::UnexpectedEnd {
+                field: "year".to_owned(),
+                line: 2,
+                column: 5,
+            }]
+        );
+    }
+
+    #[test]
+    fn every_error() {
+        let input = "authors=given> Aurora\\par\ntitle=A\\par\ntitel=B\\par\ntitle=C\\par\nyear=2022\\par";
+        let (metadata, errors) = parse_all(input.as_bytes(), &DEFAULT_REGISTRY);
+
+        assert_eq!(
+            errors,
+            [
+                ParseError::Field {
+                    field: "authors".to_owned(),
+                    line: 1,
+                    column: 22,
+                },
+                ParseError::Unexpected {
+                    line: 3,
+                    column: 1,
+                    near: "titel=B\\par".to_owned(),
+                },
+                ParseError::Duplicated {
+                    field: "title".to_owned(),
+                    line: 4,
+                    column: 1,
+                },
+            ]
+        );
+        assert_eq!(metadata.title, Some(&b"A"[..]));
+        assert_eq!(metadata.year, Some(&b"2022"[..]));
+
+        let (_, errors) = parse_all(b"title=A\\par year=2022\\par", &DEFAULT_REGISTRY);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn mismatched_key() {
+        let mut registry = Registry::default();
+        registry.register(Key {
+            name: "resumo".into(),
+            parse: abstract_value,
+            field: Field::Title,
+            multiplicity: Multiplicity::Once,
+            description: "",
+            example: "",
+        });
+
+        let input = "title=Sertão\\par\nresumo=Sobre o sertão.\\par\nyear=2022\\par";
+        assert_eq!(
+            parse(input.as_bytes(), &registry).map(|_| ()),
+            Err(ParseError::Mismatch {
+                field: "resumo".to_owned(),
+                line: 2,
+                column: 1,
+            })
+        );
+        let (metadata, errors) = parse_all(input.as_bytes(), &registry);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(metadata.year, Some(&b"2022"[..]));
+    }
+
+    #[test]
+    fn unread_rest() {
+        let parse = |input: &str| parse(input.as_bytes(), &DEFAULT_REGISTRY).map(|_| ());
+
+        assert_eq!(
+            parse("abstract=Como \\gls{x} disse.\\par\nyear=2022\\par"),
+            Err(ParseError::Unexpected {
+                line: 1,
+                column: 15,
+                near: "\\gls{x} disse.".to_owned(),
+            })
+        );
+        assert_eq!(
+            parse("authors=given> Aurora, family> Leão, given> Ana\\par"),
+            Err(ParseError::Unexpected {
+                line: 1,
+                column: 39,
+                near: "given> Ana".to_owned(),
+            })
+        );
+
+        // the rest of the file is still read
+        let input = "abstract=Como \\gls{x} disse.\\par\nyear=2022\\par";
+        let (metadata, errors) = parse_all(input.as_bytes(), &DEFAULT_REGISTRY);
+        assert_eq!(errors.len(), 1);
+        assert!(metadata.r#abstract.is_none());
+        assert_eq!(metadata.year, Some(&b"2022"[..]));
+    }
+
+    #[test]
+    fn comments() {
+        let input = "% gerado para a edição 5\ntitle=Sertão\\par % fim do título\n%\nyear=2022\\par\n%";
+        let metadata = parse(input.as_bytes(), &DEFAULT_REGISTRY).unwrap();
+
+        assert_eq!(metadata.title, Some("Sertão".as_bytes()));
+        assert_eq!(metadata.year, Some(&b"2022"[..]));
+    }
+
+    #[test]
+    fn lenient() {
+        let mut registry = Registry::default();
+        registry.set_lenient(true);
+
+        let input = "title=Sertão\\par\n  titel=B\\par\nfunding=CNPq, \\textit{Capes}\\par year=2022\\par title_en = Backlands\\par";
+        let metadata = parse(input.as_bytes(), &registry).unwrap();
+
+        assert_eq!(metadata.title, Some("Sertão".as_bytes()));
+        assert_eq!(metadata.year, Some(&b"2022"[..]));
+        assert_eq!(
+            metadata.warnings,
+            [
+                ParseWarning::UnknownKey {
+                    key: "titel".to_owned(),
+                    line: 2,
+                    column: 3,
+                },
+                ParseWarning::UnknownKey {
+                    key: "funding".to_owned(),
+                    line: 3,
+                    column: 1,
+                },
+                ParseWarning::UnknownKey {
+                    key: "title_en".to_owned(),
+                    line: 3,
+                    column: 48,
+                },
+            ]
+        );
+
+        // still an error when no key can be read
+        assert!(parse(b"title=A\\par\n= B", &registry).is_err());
+    }
+
+    #[test]
+    fn mojibake_warnings() {
+        let input = "title=SertÃ£o e Cultura\\par\nabstract=O sertÃ£o â€“ e o mar.\\par\n\
+                     keywords=Sertão. MÍDIA. SÃO PAULO.\\par";
+        let metadata = parse(input.as_bytes(), &DEFAULT_REGISTRY).unwrap();
+
+        assert_eq!(
+            metadata.warnings,
+            [
+                ParseWarning::Mojibake {
+                    key: "title".to_owned(),
+                    sequence: "Ã£".to_owned(),
+                    line: 1,
+                    column: 11,
+                },
+                ParseWarning::Mojibake {
+                    key: "abstract".to_owned(),
+                    sequence: "Ã£".to_owned(),
+                    line: 2,
+                    column: 16,
+                },
+            ]
+        );
+        assert_eq!(
+            metadata.warnings[0].to_string(),
+            "suspicious Ã£ in title at line 1, column 11; was the text converted with the \
+             wrong encoding?"
+        );
+        assert_eq!(mojibake("â€“ e Ã‰poca".as_bytes()), Some((0, "â€")));
+        assert_eq!(mojibake("Ã‰poca".as_bytes()), Some((0, "Ã‰")));
+        assert_eq!(mojibake("Â\u{a0}".as_bytes()), Some((0, "Â\u{a0}")));
+        assert_eq!(mojibake("Época, SÃO, Âmbito, â".as_bytes()), None);
+    }
+
+    #[test]
+    fn reference_warnings() {
+        let input = "title=Sertão\\par\nabstract=Ver \\ref{fig:mapa} e \\textit{a \\autoref{tab:1}}.\\par";
+        let metadata = parse(input.as_bytes(), &DEFAULT_REGISTRY).unwrap();
+
+        assert_eq!(
+            metadata.warnings,
+            [
+                ParseWarning::DroppedReference {
+                    command: "\\ref{fig:mapa}".to_owned(),
+                    line: 2,
+                    column: 14,
+                },
+                ParseWarning::DroppedReference {
+                    command: "\\autoref{tab:1}".to_owned(),
+                    line: 2,
+                    column: 41,
+                },
+            ]
+        );
+        assert_eq!(
+            metadata.warnings[0].to_string(),
+            "\\ref{fig:mapa} at line 2, column 14 dropped from the abstract"
+        );
+    }
+
+    #[test]
+    fn image_warnings() {
+        let input = "abstract=Mapa:\n\\includegraphics{mapa.png}.\\par";
+        let metadata = parse(input.as_bytes(), &DEFAULT_REGISTRY).unwrap();
+
+        assert_eq!(
+            metadata
+                .write_warnings(input.as_bytes(), &BibIndex::default(), &WriteOptions::default())
+                .unwrap(),
+            [ParseWarning::DroppedImage {
+                command: "\\includegraphics{mapa.png}".to_owned(),
+                line: 2,
+                column: 1,
+            }]
+        );
+        let options = WriteOptions {
+            image_prefix: Some("/img/".to_owned()),
+            ..Default::default()
+        };
+        let warnings = metadata.write_warnings(input.as_bytes(), &BibIndex::default(), &options);
+        assert!(warnings.unwrap().is_empty());
+    }
+
+    #[test]
+    fn description_cut_warning() {
+        let bib = nom_bibtex::Bibtex::parse(
+            "@book{Key, author = {Cunha, E.}, title = {Os sertões}, year = {1902}}",
+        )
+        .unwrap();
+        let bib = BibIndex::new(&bib);
+        let input = "title=Sertão\\par\nabstract=Sobre o sertão \\cite{Key} e mais.\\par";
+        let metadata = parse(input.as_bytes(), &DEFAULT_REGISTRY).unwrap();
+
+        let mut options = WriteOptions {
+            description_length: Some(20),
+            description_cut: crate::writer::Cut::Character,
+            ..Default::default()
+        };
+        assert_eq!(
+            metadata.write_warnings(input.as_bytes(), &bib, &options).unwrap(),
+            [ParseWarning::DescriptionCut { line: 2, column: 10 }]
+        );
+
+        options.description_length = Some(40);
+        assert!(metadata.write_warnings(input.as_bytes(), &bib, &options).unwrap().is_empty());
+    }
+
+    #[test]
+    fn jsonld() {
+        const INPUT_STR: &str = r#"authors=given> Aurora, family> Leão\par title=O \& "sertão"\par first_page=15\par last_page=29\par abstract=Sobre \textit{Os sertões}.\par keywords=Sertão. Mar.\par"#;
+
+        let (_, metadata) = metadata(INPUT_STR.as_bytes()).unwrap();
+
+        let mut output = Vec::new();
+        metadata.write_jsonld(&mut output, &BibIndex::default()).unwrap();
+
+        let jsonld: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(
+            jsonld,
+            serde_json::json!({
+                "@context": "https://schema.org",
+                "@type": "ScholarlyArticle",
+                "headline": "O & \"sertão\"",
+                "author": [{
+                    "@type": "Person",
+                    "name": "Aurora Leão",
+                    "givenName": "Aurora",
+                    "familyName": "Leão",
+                }],
+                "pageStart": "15",
+                "pageEnd": "29",
+                "keywords": ["Sertão", "Mar"],
+                "abstract": "Sobre Os sertões.",
+            })
+        );
+
+        let date = chrono::DateTime::parse_from_rfc3339("2022-06-01T00:00:00Z").unwrap();
+        let options = WriteOptions {
+            jsonld: true,
+            only: Some(Only::FrontMatter),
+            disabled: ["title", "description", "date", "authors", "tags", "pages"]
+                .map(str::to_owned)
+                .to_vec(),
+            ..Default::default()
+        };
+        let mut page = Vec::new();
+        metadata
+            .wtite_to(&mut page, &BibIndex::default(), date.into(), &options)
+            .unwrap();
+
+        let page = String::from_utf8(page).unwrap();
+        assert!(page.starts_with("---\njsonld: \"{\\\"@context\\\":"), "{}", page);
+    }
+
+    #[test]
+    fn duplicated_key() {
+        assert!(metadata(b"title=A\\par title=B\\par").is_err());
+    }
+
+    #[test]
+    fn extra_keys() {
+        let mut registry = Registry::default();
+        registry.register(Key::extra("doi".to_owned(), Multiplicity::Once));
+        registry.register(Key::extra("funding".to_owned(), Multiplicity::Many));
+
+        const INPUT_STR: &str = r#"title=Sertão\par doi=10.1234/x\par funding=CAPES\par funding=CNPq\par"#;
+
+        let (input, metadata) = metadata_with(INPUT_STR.as_bytes(), &registry).unwrap();
+        assert!(input.is_empty());
+
+        let date = chrono::DateTime::parse_from_rfc3339("2022-06-01T00:00:00Z").unwrap();
+
+        let mut output = Vec::new();
+        metadata
+            .wtite_to(&mut output, &BibIndex::default(), date.into(), &WriteOptions::default())
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "---\ntitle: \"Sertão\"\ndate: 2022-06-01T00:00:00+00:00\ndoi: \"10.1234/x\"\nfunding:\n- \"CAPES\"\n- \"CNPq\"\n---\n\n"
+        );
+    }
+
+    #[test]
+    fn unknown_keys() {
+        let mut registry = Registry::default();
+        registry.set_keep_unknown(true);
+
+        const INPUT_STR: &str = r#"title=Sertão\par doi=10.1234/x\par funding=CAPES\par funding=CNPq\par title_en=Backlands\par date=2020-01-01\par"#;
+
+        let metadata = parse(INPUT_STR.as_bytes(), &registry).unwrap();
+        assert_eq!(
+            metadata.warnings,
+            [ParseWarning::ReservedKey {
+                key: "date".to_owned(),
+                line: 1,
+                column: 95,
+            }]
+        );
+
+        let date = chrono::DateTime::parse_from_rfc3339("2022-06-01T00:00:00Z").unwrap();
+
+        let mut output = Vec::new();
+        metadata
+            .wtite_to(&mut output, &BibIndex::default(), date.into(), &WriteOptions::default())
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "---\ntitle: \"Sertão\"\ndate: 2022-06-01T00:00:00+00:00\ndoi: \"10.1234/x\"\nfunding:\n- \"CAPES\"\n- \"CNPq\"\ntitle_en: \"Backlands\"\n---\n\n"
+        );
+    }
+
+    #[test]
+    fn no_abstract() {
+        let no_abstract = NoAbstract {
+            description: Some("{title}".to_owned()),
+            body: None,
+            sections: BTreeMap::from([(
+                "Apresentação".to_owned(),
+                NoAbstract {
+                    description: Some("Apresentação do dossiê".to_owned()),
+                    body: Some("Leia o dossiê _{title}_.".to_owned()),
+                    ..Default::default()
+                },
+            )]),
+        };
+        let options = WriteOptions {
+            no_abstract,
+            ..Default::default()
+        };
+        let date = chrono::DateTime::parse_from_rfc3339("2022-06-01T00:00:00Z").unwrap();
+        let write = |input: &str| {
+            let (_, metadata) = metadata(input.as_bytes()).unwrap();
+            let mut output = Vec::new();
+            metadata
+                .wtite_to(&mut output, &BibIndex::default(), date.into(), &options)
+                .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(
+            write(r#"title=Editorial\par section=Editorial\par"#),
+            "---\ntitle: \"Editorial\"\ndescription: \"Editorial\"\ndate: 2022-06-01T00:00:00+00:00\nsection: \"Editorial\"\n---\n\n"
+        );
+        assert_eq!(
+            write(r#"title=Sertões\par section=Apresentação\par"#),
+            "---\ntitle: \"Sertões\"\ndescription: \"Apresentação do dossiê\"\ndate: 2022-06-01T00:00:00+00:00\nsection: \"Apresentação\"\n---\n\nLeia o dossiê _Sertões_.\n\n"
+        );
+    }
+
+    #[test]
+    fn abstract_tex() {
+        let input = r#"title=Sertão\par abstract=Sobre \textit {Os sertões} \citeyear {EcCUNHA1902sertoes}.\par"#;
+        let (_, metadata) = metadata(input.as_bytes()).unwrap();
+
+        let date = chrono::DateTime::parse_from_rfc3339("2022-06-01T00:00:00Z").unwrap();
+        let options = WriteOptions {
+            abstract_tex: true,
+            disabled: vec!["description".to_owned()],
+            only: Some(Only::FrontMatter),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        metadata
+            .wtite_to(&mut output, &BibIndex::default(), date.into(), &options)
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "---\ntitle: \"Sertão\"\ndate: 2022-06-01T00:00:00+00:00\nabstract_tex: \"Sobre \\\\textit {Os sertões} \\\\citeyear {EcCUNHA1902sertoes}.\"\n---\n"
+        );
+    }
+
+    #[test]
+    fn opengraph() {
+        const INPUT_STR: &str = r#"title=Sertão\par cover=/img/capa.jpg\par"#;
+
+        let (_, metadata) = metadata(INPUT_STR.as_bytes()).unwrap();
+
+        let date = chrono::DateTime::parse_from_rfc3339("2022-06-01T00:00:00Z").unwrap();
+        let options = WriteOptions {
+            opengraph: true,
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        metadata
+            .wtite_to(&mut output, &BibIndex::default(), date.into(), &options)
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "---\ntitle: \"Sertão\"\ndate: 2022-06-01T00:00:00+00:00\nog_type: article\nimages: [\"/img/capa.jpg\"]\ntwitter_card: summary_large_image\n---\n\n"
+        );
+    }
+
+    #[test]
+    fn disabled_fields() {
+        const INPUT_STR: &str = r#"title=Sertão\par abstract=Sobre o sertão.\par number=5\par"#;
+
+        let (_, metadata) = metadata(INPUT_STR.as_bytes()).unwrap();
+
+        let date = chrono::DateTime::parse_from_rfc3339("2022-06-01T00:00:00Z").unwrap();
+        let options = WriteOptions {
+            disabled: vec!["description".to_owned(), "series".to_owned()],
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        metadata
+            .wtite_to(&mut output, &BibIndex::default(), date.into(), &options)
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "---\ntitle: \"Sertão\"\ndate: 2022-06-01T00:00:00+00:00\nnumber: 5\n---\n\n**Resumo:** Sobre o sertão.\n\n"
+        );
+    }
+
+    #[test]
+    fn only_parts() {
+        const INPUT_STR: &str = r#"title=Sertão\par abstract=Sobre o sertão.\par"#;
+
+        let (_, metadata) = metadata(INPUT_STR.as_bytes()).unwrap();
+
+        let date = chrono::DateTime::parse_from_rfc3339("2022-06-01T00:00:00Z").unwrap();
+        let write = |only| {
+            let options = WriteOptions {
+                only: Some(only),
+                ..Default::default()
+            };
+            let mut output = Vec::new();
+            metadata
+                .wtite_to(&mut output, &BibIndex::default(), date.into(), &options)
+                .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(
+            write(Only::FrontMatter),
+            "---\ntitle: \"Sertão\"\ndescription: \"Sobre o sertão.\"\ndate: 2022-06-01T00:00:00+00:00\n---\n"
+        );
+        assert_eq!(write(Only::Body), "**Resumo:** Sobre o sertão.\n\n");
+    }
+
+    #[test]
+    fn localized_labels() {
+        const INPUT_STR: &str = r#"abstract=Sobre o sertão.\par keywords=Sertão.\par language=en\par"#;
+
+        let (_, metadata) = metadata(INPUT_STR.as_bytes()).unwrap();
+
+        let options = WriteOptions {
+            only: Some(Only::Body),
+            language: Language::Es,
+            ..Default::default()
+        };
+        let date = chrono::DateTime::parse_from_rfc3339("2022-06-01T00:00:00Z").unwrap();
+        let mut output = Vec::new();
+        metadata
+            .wtite_to(&mut output, &BibIndex::default(), date.into(), &options)
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "**Abstract:** Sobre o sertão.\n\n**Keywords:** Sertão.\n"
+        );
+    }
+
+    #[test]
+    fn limits() {
+        let mut registry = Registry::default();
+        registry.set_limits(Limits {
+            max_input: Some(16),
+            ..Default::default()
+        });
+
+        assert!(metadata_with(br#"title=Sertao\par"#, &registry).is_ok());
+        assert!(matches!(
+            metadata_with(br#"title=Sertao\par year=2022\par"#, &registry),
+            Err(nom::Err::Failure(_))
+        ));
+    }
+
+    #[test]
+    fn first_sentence_description() {
+        const INPUT_STR: &str = r#"abstract=A série remete a \textit{Os sertões} (2018, p. 3). Outra frase.\par"#;
+
+        let (_, metadata) = metadata(INPUT_STR.as_bytes()).unwrap();
+
+        let date = chrono::DateTime::parse_from_rfc3339("2022-06-01T00:00:00Z").unwrap();
+        let options = WriteOptions {
+            only: Some(Only::FrontMatter),
+            description: DescriptionStrategy::FirstSentence,
+            disabled: vec!["date".to_owned()],
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        metadata
+            .wtite_to(&mut output, &BibIndex::default(), date.into(), &options)
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "---\ndescription: \"A série remete a Os sertões (2018, p. 3).\"\n---\n"
+        );
+    }
+
+    #[test]
+    fn multiple_sections() {
+        let date = chrono::DateTime::parse_from_rfc3339("2022-06-01T00:00:00Z").unwrap();
+        let options = WriteOptions {
+            only: Some(Only::FrontMatter),
+            disabled: vec!["date".to_owned()],
+            ..Default::default()
+        };
+        let render = |input: &str| {
+            let (_, metadata) = metadata(input.as_bytes()).unwrap();
+            let mut output = Vec::new();
+            metadata
+                .wtite_to(&mut output, &BibIndex::default(), date.into(), &options)
+                .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(render(r#"section=Dossiê\par"#), "---\nsection: \"Dossiê\"\n---\n");
+        assert_eq!(
+            render(r#"section=Dossiê; Artigos\par"#),
+            "---\nsection:\n- \"Dossiê\"\n- \"Artigos\"\n---\n"
+        );
+        assert_eq!(
+            render(r#"section=Dossiê\par section=Artigos\par"#),
+            "---\nsection:\n- \"Dossiê\"\n- \"Artigos\"\n---\n"
+        );
+    }
+
+    #[test]
+    fn escaped_symbols() {
+        let input = r#"title=Comunicação \& Cultura\par section=Dossiê \#1\par keywords=Mídia \& poder. 100\%.\par abstract=Rádio \& TV.\par"#;
+        let (_, metadata) = metadata(input.as_bytes()).unwrap();
+
+        let date = chrono::DateTime::parse_from_rfc3339("2022-06-01T00:00:00Z").unwrap();
+        let options = WriteOptions {
+            disabled: vec!["date".to_owned(), "description".to_owned()],
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        metadata
+            .wtite_to(&mut output, &BibIndex::default(), date.into(), &options)
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "---\ntitle: \"Comunicação & Cultura\"\ntags:\n- Mídia & poder\n- 100%\nsection: \"Dossiê #1\"\n---\n\n**Resumo:** Rádio \\& TV.\n\n**Palavras-chave:** Mídia & poder. 100%.\n"
+        );
+    }
+
+    #[test]
+    fn reference_list() {
+        let bib = nom_bibtex::Bibtex::parse(
+            r#"@book{EcCUNHA1902sertoes, author = {Cunha, E.}, title = {Os sertões}, year = {1902}}
+            @book{EcBAKHTIN2003, author = {Bakhtin, M.}, title = {Estética}, year = {2003}}"#,
+        )
+        .unwrap();
+        let bib = BibIndex::new(&bib);
+        let input = r#"abstract=Ver \cite{EcCUNHA1902sertoes}, \cite{EcBAKHTIN2003} e \citeyear{EcCUNHA1902sertoes}.\par keywords=Sertão.\par"#;
+        let (_, metadata) = metadata(input.as_bytes()).unwrap();
+
+        let options = WriteOptions {
+            only: Some(Only::Body),
+            references: Some(References::default()),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        metadata
+            .wtite_to(&mut output, &bib, chrono::Utc::now(), &options)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "**Resumo:** Ver (CUNHA, 1902), (BAKHTIN, 2003) e (1902).\n\n\
+             **Palavras-chave:** Sertão.\n\n\
+             **Referências**\n\n\
+             BAKHTIN, M. _Estética_. 2003.\n\n\
+             CUNHA, E. _Os sertões_. 1902.\n"
+        );
+    }
+
+    #[test]
+    fn bibliography_pointer() {
+        let (_, metadata) = metadata(br#"title=Sertao\par"#).unwrap();
+        let render = |options: &WriteOptions| {
+            let mut output = Vec::new();
+            metadata
+                .wtite_to(&mut output, &BibIndex::default(), chrono::Utc::now(), options)
+                .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        let mut options = WriteOptions {
+            only: Some(Only::FrontMatter),
+            disabled: vec!["date".to_owned()],
+            bibliography_file: Some("artigo.bib".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(render(&options), "---\ntitle: \"Sertao\"\n---\n");
+
+        options.bibliography_prefix = Some("/bib/".to_owned());
+        assert_eq!(
+            render(&options),
+            "---\ntitle: \"Sertao\"\nbibliography: \"/bib/artigo.bib\"\n---\n"
+        );
+    }
+
+    #[test]
+    fn zola_front_matter() {
+        let input = r#"title=Sertão\par keywords=Sertão. Mídia.\par number=5\par"#;
+        let (_, metadata) = metadata(input.as_bytes()).unwrap();
+
+        let date = chrono::DateTime::parse_from_rfc3339("2022-06-01T00:00:00Z").unwrap();
+        let options = WriteOptions {
+            only: Some(Only::FrontMatter),
+            front_matter: FrontMatter::Toml,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        metadata
+            .wtite_to(&mut output, &BibIndex::default(), date.into(), &options)
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "+++\ntitle = \"Sertão\"\ndate = 2022-06-01T00:00:00+00:00\n\n[taxonomies]\ntags = [\"Sertão\", \"Mídia\"]\n\n[extra]\nseries = [\"n5\"]\nnumber = 5\n+++\n"
+        );
+    }
+
+    #[test]
+    fn stable_order() {
+        let input = r#"authors=given> Aurora, family> Leão. given> Bruno, family> Sá, order> 1\par keywords=sertanejo. Sertão. Mídia. Cinema.\par"#;
+        let (_, metadata) = metadata(input.as_bytes()).unwrap();
+        let families: Vec<_> = metadata.authors.iter().flatten().map(|a| a.family).collect();
+        assert_eq!(families, ["Sá".as_bytes(), "Leão".as_bytes()]);
+
+        let options = WriteOptions {
+            only: Some(Only::FrontMatter),
+            disabled: vec!["authors".to_owned(), "description".to_owned(), "date".to_owned()],
+            sort_tags: true,
+            ..Default::default()
+        };
+        let write = || {
+            let mut output = Vec::new();
+            metadata
+                .wtite_to(&mut output, &BibIndex::default(), chrono::Utc::now(), &options)
+                .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        assert_eq!(write(), "---\ntags:\n- Cinema\n- Mídia\n- sertanejo\n- Sertão\n---\n");
+        assert_eq!(write(), write());
+    }
+
+    #[test]
+    fn title_slug() {
+        let (_, titled) = metadata(r#"title=Comunicação \& Cultura no sertão\par"#.as_bytes()).unwrap();
+        assert_eq!(titled.slug().as_deref(), Some("comunicacao-cultura-no-sertao"));
+
+        let (_, untitled) = metadata(br#"section=Artigos\par"#).unwrap();
+        assert_eq!(untitled.slug(), None);
+    }
+
+    #[test]
+    fn search_entry() {
+        const INPUT_STR: &str = r#"authors=given> Aurora, family> Leão\par title=O "sertão" da TV\par abstract=Sobre \textit {Onde nascem os fortes}.\par keywords=Sertão. Teledramaturgia.\par"#;
+
+        let (_, metadata) = metadata(INPUT_STR.as_bytes()).unwrap();
+
+        let mut output = Vec::new();
+        metadata.write_search_entry(&mut output, &BibIndex::default()).unwrap();
+        let entry: serde_json::Value = serde_json::from_slice(&output).unwrap();
+
+        assert_eq!(
+            entry,
+            serde_json::json!({
+                "title": "O \"sertão\" da TV",
+                "authors": ["Aurora Leão"],
+                "author_slugs": ["aurora-leao"],
+                "abstract": "Sobre Onde nascem os fortes.",
+                "keywords": ["Sertão", "Teledramaturgia"],
+                "slug": "o-sertao-da-tv",
+            })
+        );
+
+        // control characters are escaped, not written raw
+        let (_, control) = super::metadata(b"title=Sert\x01\x1fo\\par").unwrap();
+        let mut output = Vec::new();
+        control.write_search_entry(&mut output, &BibIndex::default()).unwrap();
+        let entry: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(entry["title"], "Sert\u{1}\u{1f}o");
+    }
 }
@@ -0,0 +1,144 @@
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+// written next to the target and renamed over it on commit, so an
+// interrupted run never leaves a truncated page behind
+pub struct AtomicFile {
+    path: PathBuf,
+    temp: PathBuf,
+    file: File,
+    committed: bool,
+}
+
+impl AtomicFile {
+    pub fn create(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let path = path.as_ref().to_owned();
+        let name = path
+            .file_name()
+            .ok_or_else(|| std::io::Error::other(format!("not a file: {}", path.display())))?;
+        let temp = path.with_file_name(format!(
+            ".{}.{}.tmp",
+            name.to_string_lossy(),
+            std::process::id()
+        ));
+        let file = File::create(&temp)?;
+
+        Ok(Self {
+            path,
+            temp,
+            file,
+            committed: false,
+        })
+    }
+
+    pub fn commit(mut self) -> std::io::Result<()> {
+        self.file.sync_all()?;
+        std::fs::rename(&self.temp, &self.path)?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = std::fs::remove_file(&self.temp);
+        }
+    }
+}
+
+pub fn write_atomic(path: impl AsRef<Path>, contents: &[u8]) -> std::io::Result<()> {
+    let mut file = AtomicFile::create(path)?;
+    file.write_all(contents)?;
+    file.commit()
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    path: &'a str,
+    contents: &'a str,
+}
+
+// one NDJSON line per would-be file, for callers that read our stdout
+// instead of the content tree
+pub fn write_record(mut write: impl Write, path: &str, contents: &[u8]) -> std::io::Result<()> {
+    let contents = String::from_utf8_lossy(contents);
+    let record = Record {
+        path,
+        contents: &contents,
+    };
+    serde_json::to_writer(&mut write, &record)?;
+    write.write_all(b"\n")
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn commit_replaces() {
+        let dir = dir("atomic-commit");
+        let path = dir.join("artigo.md");
+        std::fs::write(&path, "antigo").unwrap();
+
+        let mut file = AtomicFile::create(&path).unwrap();
+        file.write_all(b"novo").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"antigo");
+        file.commit().unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"novo");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn interrupted() {
+        let dir = dir("atomic-interrupted");
+        let path = dir.join("artigo.md");
+        std::fs::write(&path, "antigo").unwrap();
+
+        let mut file = AtomicFile::create(&path).unwrap();
+        file.write_all(b"pela metade").unwrap();
+        drop(file);
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"antigo");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn records() {
+        let mut output = Vec::new();
+        let page = "---\ntitle: \"Sertão\"\n---\n";
+        write_record(&mut output, "n5/artigo.md", page.as_bytes()).unwrap();
+        write_record(&mut output, "index.json", b"[]\n").unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"path\":\"n5/artigo.md\",\"contents\":\"---\\ntitle: \\\"Sertão\\\"\\n---\\n\"}\n\
+             {\"path\":\"index.json\",\"contents\":\"[]\\n\"}\n"
+        );
+    }
+}
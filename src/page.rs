@@ -0,0 +1,688 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use nom_bibtex::Bibtex;
+
+use crate::{
+    bib::{resolve_dois, BibIndex, DoiLookup},
+    error::{ConvertError, ParseWarning},
+    grammar::Registry,
+    input::shown,
+    metadata::{parse_all, Metadata, WriteOptions},
+    output::write_atomic,
+    slug::slug,
+    writer::{Newline, Newlines},
+};
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Existing {
+    #[default]
+    Replace,
+    Merge,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Fence {
+    Yaml,
+    Toml,
+}
+
+fn line(input: &[u8]) -> (&[u8], &[u8]) {
+    match memchr::memchr(b'\n', input) {
+        Some(pos) => (&input[..pos], &input[pos + 1..]),
+        None => (input, &input[input.len()..]),
+    }
+}
+
+// returns the fence, the lines between the fences and the remaining body
+fn front_matter(body: &[u8]) -> Option<(Fence, &[u8], &[u8])> {
+    let (first, mut rest) = line(body);
+    let fence = match first.trim_ascii_end() {
+        b"---" => Fence::Yaml,
+        b"+++" => Fence::Toml,
+        _ => return None,
+    };
+
+    let start = rest;
+    while !rest.is_empty() {
+        let (current, next) = line(rest);
+        if current.trim_ascii_end() == first.trim_ascii_end() {
+            let inner = &start[..start.len() - rest.len()];
+            return Some((fence, inner, next));
+        }
+        rest = next;
+    }
+
+    None
+}
+
+// top level YAML keys together with their nested lines
+fn yaml_entries(mut input: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut entries: Vec<(&[u8], &[u8])> = Vec::new();
+    let mut start = input;
+    let mut key: Option<&[u8]> = None;
+
+    while !input.is_empty() {
+        let (current, next) = line(input);
+        let nested = current.is_empty() || matches!(current[0], b' ' | b'\t' | b'-' | b'#');
+        if !nested {
+            if let Some(key) = key {
+                entries.push((key, &start[..start.len() - input.len()]));
+            }
+            start = input;
+            key = current.split(|c| *c == b':').next().map(<[u8]>::trim_ascii);
+        }
+        input = next;
+    }
+    if let Some(key) = key {
+        entries.push((key, start));
+    }
+
+    entries
+}
+
+// a value as written by the generator, e.g. "O \\"sertão\\""
+fn yaml_string(value: &[u8]) -> String {
+    let value = value.trim_ascii();
+    let quoted = match value
+        .strip_prefix(b"\"")
+        .and_then(|value| value.strip_suffix(b"\""))
+    {
+        Some(quoted) => String::from_utf8_lossy(quoted),
+        None => return String::from_utf8_lossy(value).into_owned(),
+    };
+
+    let mut text = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => text.push('\n'),
+            Some('t') => text.push('\t'),
+            Some('r') => text.push('\r'),
+            Some('x') => {
+                let code: String = chars.by_ref().take(2).collect();
+                text.extend(u32::from_str_radix(&code, 16).ok().and_then(char::from_u32));
+            }
+            Some(c) => text.push(c),
+            None => {}
+        }
+    }
+    text
+}
+
+fn yaml_field<'p>(page: &'p [u8], name: &[u8]) -> Option<&'p [u8]> {
+    let inner = match front_matter(page)? {
+        (Fence::Yaml, inner, _) => inner,
+        (Fence::Toml, ..) => return None,
+    };
+    let (_, lines) = yaml_entries(inner)
+        .into_iter()
+        .find(|(key, _)| *key == name)?;
+    let (first, _) = line(lines);
+    Some(&first[memchr::memchr(b':', first)? + 1..])
+}
+
+// the date of an existing page, kept on regeneration so that publication
+// dates do not move
+pub fn previous_date(page: &[u8]) -> Option<chrono::DateTime<chrono::Utc>> {
+    let date = yaml_string(yaml_field(page, b"date")?);
+    chrono::DateTime::parse_from_rfc3339(&date)
+        .ok()
+        .map(Into::into)
+}
+
+// the slugs an existing page was published under, so that a retitled
+// article keeps answering at its old addresses; `current` is left out
+pub fn previous_slugs(page: &[u8], current: &str) -> Vec<String> {
+    let inner = match front_matter(page) {
+        Some((Fence::Yaml, inner, _)) => inner,
+        _ => return Vec::new(),
+    };
+
+    let mut slugs = Vec::new();
+    let mut title = None;
+    for (key, lines) in yaml_entries(inner) {
+        let (first, mut rest) = line(lines);
+        match key {
+            b"aliases" => {
+                while !rest.is_empty() {
+                    let (current, next) = line(rest);
+                    if let Some(alias) = current.trim_ascii().strip_prefix(b"- ") {
+                        slugs.push(yaml_string(alias));
+                    }
+                    rest = next;
+                }
+            }
+            b"title" => {
+                let value = &first[memchr::memchr(b':', first).map_or(0, |pos| pos + 1)..];
+                title = Some(slug(&yaml_string(value)));
+            }
+            _ => {}
+        }
+    }
+    slugs.extend(title);
+
+    let mut seen = Vec::new();
+    slugs.retain(|slug| {
+        let keep = !slug.is_empty() && slug != current && !seen.contains(slug);
+        seen.push(slug.clone());
+        keep
+    });
+    slugs
+}
+
+// the slug each source was last written under, kept next to the pages of
+// an output directory so that a retitled article finds the page it replaces
+pub const MANIFEST: &str = ".slugs";
+
+#[derive(Debug, PartialEq, Default)]
+pub struct Manifest {
+    // (source, slug), one `slug<TAB>source` line each on disk
+    entries: Vec<(String, String)>,
+}
+
+impl Manifest {
+    pub fn read(dir: &Path) -> std::io::Result<Self> {
+        let text = match std::fs::read_to_string(dir.join(MANIFEST)) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err),
+        };
+        let entries = text
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .map(|(slug, source)| (source.to_owned(), slug.to_owned()))
+            .collect();
+        Ok(Self { entries })
+    }
+
+    pub fn slug(&self, source: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(known, _)| known == source)
+            .map(|(_, slug)| slug.as_str())
+    }
+
+    pub fn set(&mut self, source: &str, slug: &str) {
+        match self.entries.iter_mut().find(|(known, _)| known == source) {
+            Some(entry) => entry.1 = slug.to_owned(),
+            None => self.entries.push((source.to_owned(), slug.to_owned())),
+        }
+        self.entries.sort();
+    }
+
+    pub fn write(&self, dir: &Path) -> std::io::Result<()> {
+        let mut text = String::new();
+        for (source, slug) in &self.entries {
+            text.push_str(&format!("{}\t{}\n", slug, source));
+        }
+        crate::output::write_atomic(dir.join(MANIFEST), text.as_bytes())
+    }
+}
+
+pub fn write_page(
+    metadata: &Metadata,
+    body: &[u8],
+    existing: Existing,
+    mut write: impl Write,
+    bib: &BibIndex,
+    date: chrono::DateTime<chrono::Utc>,
+    options: &WriteOptions,
+) -> std::io::Result<()> {
+    let mut page = Vec::new();
+    metadata.wtite_to(&mut page, bib, date, options)?;
+
+    let (previous, body) = match front_matter(body) {
+        Some((fence, inner, rest)) => (Some((fence, inner)), rest),
+        None => (None, body),
+    };
+
+    // the generated front matter, if any, ends at the second fence
+    let generated =
+        front_matter(&page).map(|(fence, inner, _)| (fence, 4 + inner.len()));
+
+    match (previous, existing, generated) {
+        (Some((Fence::Yaml, inner)), Existing::Merge, Some((Fence::Yaml, end))) => {
+            let generated = yaml_entries(&page[4..end]);
+            write.write_all(&page[..end])?;
+            for (key, lines) in yaml_entries(inner) {
+                if !generated.iter().any(|(k, _)| *k == key) {
+                    write.write_all(lines)?;
+                }
+            }
+            write.write_all(page[end..].trim_ascii_end())?;
+        }
+        (Some(_), Existing::Merge, Some(_)) => {
+            return Err(std::io::Error::other(
+                "only YAML front matters can be merged with the generated one",
+            ));
+        }
+        // without a generated front matter the existing one is kept as is
+        (Some((fence, inner)), Existing::Merge, None) => {
+            let fence: &[u8] = match fence {
+                Fence::Yaml => b"---\n",
+                Fence::Toml => b"+++\n",
+            };
+            write.write_all(fence)?;
+            write.write_all(inner)?;
+            write.write_all(fence)?;
+            write.write_all(b"\n")?;
+            write.write_all(page.trim_ascii_end())?;
+        }
+        _ => write.write_all(page.trim_ascii_end())?,
+    }
+
+    // exactly one blank line between the generated part and the body
+    write.write_all(b"\n\n")?;
+    write.write_all(body.trim_ascii_start())
+}
+
+// how the manifest of an output directory names a metadata file: relative
+// to the working directory when below it, so that the manifest can be
+// committed with the content tree; stdin has no name to remember
+pub fn source_key(path: &str) -> Option<String> {
+    if path == "-" {
+        return None;
+    }
+    let path = std::fs::canonicalize(path).ok()?;
+    let cwd = std::env::current_dir().and_then(std::fs::canonicalize).ok();
+    let key = match cwd.as_deref().and_then(|cwd| path.strip_prefix(cwd).ok()) {
+        Some(relative) => relative.to_string_lossy(),
+        None => path.to_string_lossy(),
+    };
+    // one manifest line per source
+    (!key.contains('\n')).then(|| key.into_owned())
+}
+
+pub struct ConvertOptions<'a> {
+    pub registry: &'a Registry,
+    pub options: WriteOptions,
+    pub existing: Existing,
+    pub newline: Newline,
+    // without one the page keeps the date of the one it replaces, new
+    // pages get the current time
+    pub date: Option<chrono::DateTime<chrono::Utc>>,
+    // the search index entry instead of a page
+    pub json: bool,
+    // render the search index and the JSON-LD next to the page
+    pub search_index: bool,
+    pub jsonld: bool,
+    pub dois: Option<&'a dyn DoiLookup>,
+}
+
+// one article, read by the caller
+pub struct Sources<'a> {
+    // "-" being stdin, which has no manifest entry
+    pub path: &'a str,
+    pub metadata: &'a [u8],
+    pub bib_path: Option<&'a str>,
+    pub bib: String,
+    // an existing page the generated part is written into
+    pub body: Option<&'a [u8]>,
+    // a file, a directory to name the page after the slug in, or stdout
+    pub output: Option<&'a str>,
+}
+
+// a converted article; nothing is written until Converted::commit
+pub struct Converted {
+    pub page: Vec<u8>,
+    // where the page goes, None being stdout
+    pub output: Option<String>,
+    pub search_index: Option<Vec<u8>>,
+    pub jsonld: Option<Vec<u8>>,
+    pub warnings: Vec<ParseWarning>,
+    // bibliography keys defined twice, of which the first entry is used
+    pub duplicates: Vec<String>,
+    // the output directory, its manifest and the new slug
+    directory: Option<(PathBuf, Manifest, String)>,
+    source: Option<String>,
+    // the page of an older title, which the new page replaces
+    replaced: Option<PathBuf>,
+}
+
+pub fn convert(sources: Sources, options: ConvertOptions) -> Result<Converted, ConvertError> {
+    let ConvertOptions {
+        registry,
+        options: mut write_options,
+        existing,
+        newline,
+        ..
+    } = options;
+    let (mut metadata, errors) = parse_all(sources.metadata, registry);
+    if !errors.is_empty() {
+        let path = sources.path;
+        return Err(ConvertError::metadata(path, sources.metadata, errors));
+    }
+    let mut warnings = std::mem::take(&mut metadata.warnings);
+
+    // with a directory the page is named after the slug, and the manifest
+    // there remembers the page each source was written to last time
+    let mut directory = None;
+    let output = match sources.output {
+        Some(path) if path.ends_with('/') || Path::new(path).is_dir() => {
+            let slug = metadata
+                .slug()
+                .filter(|slug| !slug.is_empty())
+                .ok_or_else(|| {
+                    ConvertError::Usage(format!(
+                        "{} is a directory, but there is no title to name the page after",
+                        path
+                    ))
+                })?;
+            let page = Path::new(path).join(format!("{}.md", slug));
+            let manifest = Manifest::read(Path::new(path))?;
+            directory = Some((PathBuf::from(path), manifest, slug));
+            Some(page.to_string_lossy().into_owned())
+        }
+        path => path.map(str::to_owned),
+    };
+    let source = source_key(sources.path);
+    let replaced = match (&directory, &source) {
+        (Some((dir, manifest, slug)), Some(source)) => manifest
+            .slug(source)
+            .filter(|old| old != slug)
+            .map(|old| dir.join(format!("{}.md", old))),
+        _ => None,
+    };
+
+    // a retitled article keeps the addresses of the page it replaces
+    let previous = match &replaced {
+        Some(path) => std::fs::read(path).ok(),
+        None => output.as_ref().and_then(|path| std::fs::read(path).ok()),
+    };
+    if let (Some(previous), Some(slug)) = (&previous, metadata.slug()) {
+        write_options.aliases = previous_slugs(previous, &slug);
+    }
+
+    let mut bib = sources.bib;
+    if let (Some(lookup), Some(r#abstract)) = (options.dois, &metadata.r#abstract) {
+        let cited = r#abstract.citation_keys();
+        warnings.extend(resolve_dois(&mut bib, sources.metadata, cited, lookup));
+    }
+    let bib = Bibtex::parse(&bib).map_err(|err| ConvertError::Bibliography {
+        path: shown(sources.bib_path.unwrap_or_default()),
+        reason: err.to_string(),
+    })?;
+    let bib = BibIndex::new(&bib);
+    let duplicates = bib.duplicates().iter().map(|key| key.to_string()).collect();
+    warnings.extend(metadata.write_warnings(sources.metadata, &bib, &write_options)?);
+
+    // a regenerated page keeps its publication date
+    let kept = match existing {
+        Existing::Merge => sources.body.and_then(previous_date),
+        Existing::Replace => None,
+    };
+    let date = options
+        .date
+        .or_else(|| previous.as_deref().and_then(previous_date))
+        .or(kept)
+        .unwrap_or_else(chrono::Utc::now);
+
+    let mut page = Vec::new();
+    match (options.json, sources.body) {
+        (true, _) => {
+            let mut write = Newlines::new(&mut page, newline);
+            metadata.write_search_entry(&mut write, &bib)?;
+            write.write_all(b"\n")?;
+        }
+        (false, Some(body)) => write_page(
+            &metadata,
+            body,
+            existing,
+            Newlines::new(&mut page, newline),
+            &bib,
+            date,
+            &write_options,
+        )?,
+        (false, None) => metadata.wtite_to(
+            Newlines::new(&mut page, newline),
+            &bib,
+            date,
+            &write_options,
+        )?,
+    }
+
+    let search_index = match options.search_index {
+        true => {
+            let mut index = Vec::new();
+            let mut write = Newlines::new(&mut index, newline);
+            let entries = [metadata.search_entry(&bib)?];
+            serde_json::to_writer(&mut write, &entries).map_err(std::io::Error::from)?;
+            write.write_all(b"\n")?;
+            Some(index)
+        }
+        false => None,
+    };
+    let jsonld = match options.jsonld {
+        true => {
+            let mut jsonld = Vec::new();
+            let mut write = Newlines::new(&mut jsonld, newline);
+            metadata.write_jsonld(&mut write, &bib)?;
+            write.write_all(b"\n")?;
+            Some(jsonld)
+        }
+        false => None,
+    };
+
+    Ok(Converted {
+        page,
+        output,
+        search_index,
+        jsonld,
+        warnings,
+        duplicates,
+        directory,
+        source,
+        replaced,
+    })
+}
+
+impl Converted {
+    // writes the page to its output, or to `stdout` without one, then
+    // records it in the manifest of its directory
+    pub fn commit(self, mut stdout: impl Write) -> Result<(), ConvertError> {
+        match &self.output {
+            Some(path) => write_atomic(path, &self.page).map_err(|source| ConvertError::Write {
+                path: path.clone(),
+                source,
+            })?,
+            None => stdout.write_all(&self.page)?,
+        }
+        if let (Some((dir, mut manifest, slug)), Some(source)) = (self.directory, self.source) {
+            // the old page answers through the aliases of the new one now
+            if let Some(replaced) = self.replaced {
+                match std::fs::remove_file(&replaced) {
+                    Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                        return Err(err.into())
+                    }
+                    _ => {}
+                }
+            }
+            manifest.set(&source, &slug);
+            manifest.write(&dir)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::metadata::{metadata, Only};
+
+    fn page(body: &str, existing: Existing, options: &WriteOptions) -> std::io::Result<String> {
+        const INPUT_STR: &str = r#"title=Sertão\par abstract=Sobre o sertão.\par"#;
+
+        let (_, metadata) = metadata(INPUT_STR.as_bytes()).unwrap();
+
+        let date = chrono::DateTime::parse_from_rfc3339("2022-06-01T00:00:00Z").unwrap();
+        let mut output = Vec::new();
+        write_page(
+            &metadata,
+            body.as_bytes(),
+            existing,
+            &mut output,
+            &BibIndex::default(),
+            date.into(),
+            options,
+        )?;
+
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn appends_body() {
+        assert_eq!(
+            page("\n\n## Introdução\n\nTexto.\n", Existing::Replace, &WriteOptions::default()).unwrap(),
+            "---\ntitle: \"Sertão\"\ndescription: \"Sobre o sertão.\"\ndate: 2022-06-01T00:00:00+00:00\n---\n\n**Resumo:** Sobre o sertão.\n\n## Introdução\n\nTexto.\n"
+        );
+    }
+
+    #[test]
+    fn replaces_yaml_front_matter() {
+        let body = "---\ntitle: Antigo\nlayout: artigo\n---\n\nTexto.\n";
+
+        assert_eq!(
+            page(body, Existing::Replace, &WriteOptions::default()).unwrap(),
+            "---\ntitle: \"Sertão\"\ndescription: \"Sobre o sertão.\"\ndate: 2022-06-01T00:00:00+00:00\n---\n\n**Resumo:** Sobre o sertão.\n\nTexto.\n"
+        );
+    }
+
+    #[test]
+    fn merges_yaml_front_matter() {
+        let body = "---\ntitle: Antigo\nlayout: artigo\nmenu:\n  main: true\n---\n\nTexto.\n";
+
+        assert_eq!(
+            page(body, Existing::Merge, &WriteOptions::default()).unwrap(),
+            "---\ntitle: \"Sertão\"\ndescription: \"Sobre o sertão.\"\ndate: 2022-06-01T00:00:00+00:00\nlayout: artigo\nmenu:\n  main: true\n---\n\n**Resumo:** Sobre o sertão.\n\nTexto.\n"
+        );
+    }
+
+    #[test]
+    fn retitled_page() {
+        let page = "---\ntitle: \"O \\\"sertão\\\" antigo\"\naliases:\n- \"sertao\"\n- \"o-sertao-antigo\"\n---\n\nTexto.\n";
+        let page = page.as_bytes();
+
+        assert_eq!(previous_slugs(page, "sertao-novo"), ["sertao", "o-sertao-antigo"]);
+        assert_eq!(previous_slugs(page, "sertao"), ["o-sertao-antigo"]);
+        assert!(previous_slugs(b"+++\ntitle = \"Antigo\"\n+++\n", "novo").is_empty());
+        assert!(previous_slugs(b"Texto.\n", "novo").is_empty());
+        assert_eq!(
+            previous_slugs(b"---\ntitle: \"A\\\\B\\x07 \\\"C\\\"\"\n---\n", "novo"),
+            ["a-b-c"]
+        );
+    }
+
+    #[test]
+    fn kept_date() {
+        let date = chrono::DateTime::parse_from_rfc3339("2022-06-01T00:00:00Z").unwrap();
+
+        assert_eq!(
+            previous_date(b"---\ntitle: \"A\"\ndate: 2022-06-01T00:00:00+00:00\n---\n"),
+            Some(date.into())
+        );
+        assert_eq!(previous_date(b"---\ndate: ontem\n---\n"), None);
+        assert_eq!(previous_date(b"---\ntitle: \"A\"\n---\n"), None);
+        assert_eq!(previous_date(b"+++\ndate = 2022-06-01\n+++\n"), None);
+    }
+
+    #[test]
+    fn manifest() {
+        let dir = std::env::temp_dir().join(format!("manifest-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut manifest = Manifest::read(&dir).unwrap();
+        assert_eq!(manifest, Manifest::default());
+        manifest.set("meta/b.tex", "sertao");
+        manifest.set("meta/a.tex", "veredas");
+        manifest.set("meta/b.tex", "sertao-novo");
+        manifest.write(&dir).unwrap();
+
+        let text = std::fs::read_to_string(dir.join(MANIFEST)).unwrap();
+        let read = Manifest::read(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(text, "veredas\tmeta/a.tex\nsertao-novo\tmeta/b.tex\n");
+        assert_eq!(read, manifest);
+        assert_eq!(read.slug("meta/b.tex"), Some("sertao-novo"));
+        assert_eq!(read.slug("meta/c.tex"), None);
+    }
+
+    #[test]
+    fn toml_front_matter() {
+        let body = "+++\ntitle = \"Antigo\"\n+++\n\nTexto.\n";
+
+        assert_eq!(
+            page(body, Existing::Replace, &WriteOptions::default()).unwrap(),
+            "---\ntitle: \"Sertão\"\ndescription: \"Sobre o sertão.\"\ndate: 2022-06-01T00:00:00+00:00\n---\n\n**Resumo:** Sobre o sertão.\n\nTexto.\n"
+        );
+        assert!(page(body, Existing::Merge, &WriteOptions::default()).is_err());
+
+        let options = WriteOptions {
+            only: Some(Only::Body),
+            ..Default::default()
+        };
+        assert_eq!(
+            page(body, Existing::Merge, &options).unwrap(),
+            "+++\ntitle = \"Antigo\"\n+++\n\n**Resumo:** Sobre o sertão.\n\nTexto.\n"
+        );
+    }
+
+    #[test]
+    fn retitled() {
+        let dir = std::env::temp_dir().join(format!("page-convert-{}", std::process::id()));
+        let pages = dir.join("pages");
+        std::fs::create_dir_all(&pages).unwrap();
+        let source = dir.join("artigo.tex");
+        let source = source.to_str().unwrap();
+        let output = format!("{}/", pages.display());
+        let registry = Registry::default();
+
+        let convert = |title: &str| {
+            let metadata = format!(r#"title={}\par abstract=Sobre o sertão.\par"#, title);
+            let sources = Sources {
+                path: source,
+                metadata: metadata.as_bytes(),
+                bib_path: None,
+                bib: String::new(),
+                body: None,
+                output: Some(&output),
+            };
+            let options = ConvertOptions {
+                registry: &registry,
+                options: WriteOptions::default(),
+                existing: Existing::Replace,
+                newline: Newline::Lf,
+                date: None,
+                json: false,
+                search_index: false,
+                jsonld: false,
+                dois: None,
+            };
+            let converted = convert(sources, options).unwrap();
+            converted.commit(std::io::sink()).unwrap();
+        };
+        std::fs::write(source, "").unwrap();
+        convert("Sertão");
+        let date = previous_date(&std::fs::read(pages.join("sertao.md")).unwrap());
+        convert("Veredas");
+
+        let page = std::fs::read(pages.join("veredas.md")).unwrap();
+        let old = pages.join("sertao.md").exists();
+        let manifest = Manifest::read(&pages).unwrap();
+        let key = source_key(source).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(!old);
+        assert_eq!(previous_slugs(&page, "veredas"), ["sertao"]);
+        assert_eq!(previous_date(&page), date);
+        assert_eq!(manifest.slug(&key), Some("veredas"));
+    }
+}
@@ -1,18 +1,136 @@
-use nom::{IResult, bytes::complete::take_until, error::Error};
+use std::{borrow::Cow, ops::Range};
+
+use memchr::memmem;
+use nom::IResult;
+use serde::Deserialize;
 
 use crate::space::space;
 
+/// What ends a field value.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(from = "String")]
+pub enum Terminator {
+    /// A fixed string, `\par` unless configured otherwise.
+    Literal(Cow<'static, str>),
+    /// A line with nothing but whitespace on it.
+    BlankLine,
+    /// `\par` when there is one ahead, a blank line otherwise.
+    Auto,
+}
+
+impl Terminator {
+    pub const PAR: Terminator = Terminator::Literal(Cow::Borrowed("\\par"));
+
+    // where the next terminator starts and ends
+    pub(crate) fn find(&self, input: &[u8]) -> Option<Range<usize>> {
+        match self {
+            Terminator::Literal(literal) => {
+                memmem::find(input, literal.as_bytes()).map(|pos| pos..pos + literal.len())
+            }
+            Terminator::BlankLine => blank_line(input),
+            Terminator::Auto => Terminator::PAR.find(input).or_else(|| blank_line(input)),
+        }
+    }
+
+    // what ends the fields this tool writes
+    pub(crate) fn written(&self) -> &str {
+        match self {
+            Terminator::Literal(literal) => literal.as_ref(),
+            Terminator::BlankLine => "\n",
+            Terminator::Auto => "\\par",
+        }
+    }
+
+    // the ranges of the fields of `input`, found lexically without parsing
+    // them; a terminator followed by a letter is a longer command, e.g.
+    // \paragraph
+    pub(crate) fn fields(&self, input: &[u8]) -> Vec<Range<usize>> {
+        let mut fields = Vec::new();
+        let mut start = 0;
+        let mut from = 0;
+        while let Some(range) = self.find(&input[from..]) {
+            let range = from + range.start..from + range.end;
+            from = range.end;
+            if input[range.end - 1].is_ascii_alphabetic()
+                && input.get(range.end).is_some_and(u8::is_ascii_alphabetic)
+            {
+                continue;
+            }
+            fields.push(start..range.start);
+            start = range.end;
+        }
+        fields.push(start..input.len());
+        fields
+    }
+
+    /// Skips a terminator at the start of `input`, i.e. an empty field.
+    pub fn strip<'a>(&self, input: &'a [u8]) -> Option<&'a [u8]> {
+        match self {
+            Terminator::Literal(literal) => input.strip_prefix(literal.as_bytes()),
+            // blank lines are whitespace, which is skipped anyway
+            Terminator::BlankLine => None,
+            Terminator::Auto => Terminator::PAR.strip(input),
+        }
+    }
+}
+
+impl Default for Terminator {
+    fn default() -> Self {
+        Terminator::PAR
+    }
+}
+
+// "blank-line" and "auto" are modes, anything else is a literal
+impl From<String> for Terminator {
+    fn from(terminator: String) -> Self {
+        match terminator.as_str() {
+            "blank-line" => Terminator::BlankLine,
+            "auto" => Terminator::Auto,
+            _ => Terminator::Literal(Cow::Owned(terminator)),
+        }
+    }
+}
+
+fn blank_line(input: &[u8]) -> Option<Range<usize>> {
+    memchr::memchr_iter(b'\n', input).find_map(|pos| {
+        let rest = &input[pos + 1..];
+        let blank = rest
+            .iter()
+            .take_while(|c| matches!(c, b' ' | b'\t' | b'\r'))
+            .count();
+        match rest.get(blank) {
+            Some(b'\n') => Some(pos..pos + blank + 2),
+            _ => None,
+        }
+    })
+}
+
+/// A field value: everything up to the next `\par`, which is consumed, or
+/// up to the end of the input. Leading whitespace is skipped.
 pub fn paragraph(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    paragraph_with(input, &Terminator::PAR)
+}
+
+/// Like [`paragraph`], ending at `terminator`. Line breaks before the
+/// terminator or the end of the input are not part of the value.
+pub fn paragraph_with<'a>(input: &'a [u8], terminator: &Terminator) -> IResult<&'a [u8], &'a [u8]> {
     let (input, _) = space(input)?;
-    match take_until::<&str, &[u8], Error<&[u8]>>("\\par")(input){
-        Ok((input, par)) => Ok((&input[4..], par)),
-        Err(_) => Ok((&[], input)),
-    }
+    let (value, rest) = match terminator.find(input) {
+        Some(range) => (&input[..range.start], &input[range.end..]),
+        None => (input, &input[input.len()..]),
+    };
+    let end = value.len()
+        - value
+            .iter()
+            .rev()
+            .take_while(|c| matches!(c, b'\r' | b'\n'))
+            .count();
+    Ok((rest, &value[..end]))
 }
 
 #[cfg(test)]
 mod test {
-    
+
     use super::*;
 
     #[test]
@@ -54,4 +172,34 @@ mod test {
         assert!(input.is_empty());
         assert_eq!(year, b"2022");
     }
+
+    #[test]
+    fn blank_lines() {
+        let input = b"title=Sertao\r\n  \r\nyear=2022\n";
+
+        let (input, title) = paragraph_with(input, &Terminator::BlankLine).unwrap();
+        assert_eq!(title, b"title=Sertao");
+        assert_eq!(input, b"year=2022\n");
+
+        let (input, year) = paragraph_with(input, &Terminator::BlankLine).unwrap();
+        assert_eq!(year, b"year=2022");
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn terminators() {
+        let literal = Terminator::from(";;".to_owned());
+        assert_eq!(
+            paragraph_with(b"a\\par b;; c", &literal).unwrap(),
+            (&b" c"[..], &b"a\\par b"[..])
+        );
+
+        // a \par ahead wins over blank lines, which may split an abstract
+        let input = b"a\n\nb\\par c\n\nd";
+        let (input, value) = paragraph_with(input, &Terminator::Auto).unwrap();
+        assert_eq!(value, b"a\n\nb");
+        let (input, value) = paragraph_with(input, &Terminator::Auto).unwrap();
+        assert_eq!(value, b"c");
+        assert_eq!(input, b"d");
+    }
 }
@@ -0,0 +1,101 @@
+use std::{io::Write, path::Path};
+
+use memchr::memmem;
+
+pub const TOOL: &str = env!("CARGO_PKG_NAME");
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// FNV-1a, stable across builds unlike the std hasher
+pub fn hash(input: &[u8]) -> u64 {
+    input.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+pub fn header(input: &[u8], options: &[String]) -> String {
+    format!(
+        "generated by {} {}; input fnv1a:{:016x}; options: {}",
+        TOOL,
+        VERSION,
+        hash(input),
+        options.join(" ")
+    )
+}
+
+pub fn version_of(page: &[u8]) -> Option<&str> {
+    let prefix = format!("generated by {} ", TOOL);
+    let start = memmem::find(page, prefix.as_bytes())? + prefix.len();
+    let len = memchr::memchr(b';', &page[start..])?;
+    std::str::from_utf8(&page[start..start + len]).ok()
+}
+
+// reports every generated page below `dir` written by another version
+pub fn scan_stale(dir: &Path, mut write: impl Write) -> std::io::Result<bool> {
+    let mut stale = false;
+    let mut pending = vec![dir.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let mut entries = std::fs::read_dir(&dir)?.collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by_key(|entry| entry.path());
+
+        for entry in entries {
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            if path.extension().is_none_or(|ext| ext != "md") {
+                continue;
+            }
+
+            let page = std::fs::read(&path)?;
+            match version_of(&page) {
+                Some(version) if version != VERSION => {
+                    stale = true;
+                    writeln!(write, "{}: generated by {}", path.display(), version)?;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(stale)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn header_roundtrip() {
+        let header = header(b"title=Sertao\\par", &["--opengraph".to_owned()]);
+
+        assert!(header.ends_with("; options: --opengraph"));
+        assert_eq!(version_of(header.as_bytes()), Some(VERSION));
+        assert_ne!(hash(b"title=Sertao\\par"), hash("title=Sertão\\par".as_bytes()));
+    }
+
+    #[test]
+    fn scan() {
+        let dir = std::env::temp_dir().join(format!("scan-stale-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("n5")).unwrap();
+        std::fs::write(dir.join("atual.md"), format!("---\n# {}\n---\n", header(b"", &[])))
+            .unwrap();
+        std::fs::write(
+            dir.join("n5/antigo.md"),
+            format!("---\n# generated by {} 0.0.1; input fnv1a:0; options: \n---\n", TOOL),
+        )
+        .unwrap();
+        std::fs::write(dir.join("manual.md"), "---\ntitle: x\n---\n").unwrap();
+
+        let mut output = Vec::new();
+        let stale = scan_stale(&dir, &mut output).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(stale);
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().count(), 1);
+        assert!(output.ends_with("antigo.md: generated by 0.0.1\n"));
+    }
+}
@@ -0,0 +1,241 @@
+use std::io::Write;
+
+use serde_json::{json, Map, Value};
+
+use crate::{
+    bib::BibIndex,
+    grammar::Registry,
+    latex,
+    metadata::{keyword_list, Metadata},
+    r#abstract::Format,
+};
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Mode {
+    // decoded text, one line per item of a list
+    #[default]
+    Text,
+    // text as written in the metadata file
+    Raw,
+    Json,
+}
+
+fn text(value: &[u8], mode: Mode) -> Value {
+    match mode {
+        Mode::Raw => String::from_utf8_lossy(value.trim_ascii()).into(),
+        _ => String::from_utf8_lossy(&latex::decode(value.trim_ascii())).into(),
+    }
+}
+
+// the fields present in the metadata, keyed by their metadata file names
+fn document(metadata: &Metadata, mode: Mode) -> std::io::Result<Map<String, Value>> {
+    let mut document = Map::new();
+
+    if let Some(authors) = &metadata.authors {
+        let authors = authors
+            .iter()
+            .map(|author| {
+                let mut value = json!({
+                    "given": text(author.given, mode),
+                    "family": text(author.family, mode),
+                });
+                if let Some(order) = author.order {
+                    value["order"] = order.into();
+                }
+                value
+            })
+            .collect();
+        document.insert("authors".to_owned(), Value::Array(authors));
+    }
+
+    let fields = [
+        ("title", metadata.title),
+        ("first_page", metadata.first_page),
+        ("last_page", metadata.last_page),
+        ("number", metadata.number),
+        ("semester", metadata.semester),
+        ("year", metadata.year),
+        ("cover", metadata.cover),
+        ("language", metadata.language),
+    ];
+    for (name, value) in fields {
+        if let Some(value) = value {
+            document.insert(name.to_owned(), text(value, mode));
+        }
+    }
+
+    if let Some(summary) = &metadata.r#abstract {
+        let summary = match mode {
+            Mode::Raw => text(summary.source(), mode),
+            _ => {
+                let mut output = Vec::new();
+                summary.write_to(&mut output, &BibIndex::default(), Format::PlainText)?;
+                String::from_utf8_lossy(&output).into()
+            }
+        };
+        document.insert("abstract".to_owned(), summary);
+    }
+
+    if let Some(keywords) = metadata.keywords {
+        let keywords = match mode {
+            Mode::Raw => keywords
+                .split(|c| *c == b'.')
+                .map(<[u8]>::trim_ascii)
+                .filter(|kw| !kw.is_empty())
+                .map(|kw| text(kw, mode))
+                .collect(),
+            _ => keyword_list(keywords)
+                .into_iter()
+                .map(Value::from)
+                .collect(),
+        };
+        document.insert("keywords".to_owned(), Value::Array(keywords));
+    }
+
+    let sections = metadata.sections();
+    if !sections.is_empty() {
+        let sections = sections.into_iter().map(|section| text(section, mode));
+        document.insert("section".to_owned(), Value::Array(sections.collect()));
+    }
+
+    // a repeated extra key is a list
+    for (name, value) in &metadata.extra {
+        let value = text(value, mode);
+        match document.get_mut(name) {
+            Some(Value::Array(values)) => values.push(value),
+            Some(first) => *first = Value::Array(vec![first.take(), value]),
+            None => {
+                document.insert(name.clone(), value);
+            }
+        }
+    }
+
+    Ok(document)
+}
+
+// e.g. authors[0].family
+fn segments(path: &str) -> std::io::Result<Vec<(&str, Option<usize>)>> {
+    let invalid = || std::io::Error::other(format!("invalid field path: {}", path));
+    path.split('.')
+        .map(|segment| match segment.split_once('[') {
+            Some((name, index)) => {
+                let index = index.strip_suffix(']').ok_or_else(invalid)?;
+                Ok((name, Some(index.parse().map_err(|_| invalid())?)))
+            }
+            None => Ok((segment, None)),
+        })
+        .collect::<std::io::Result<Vec<_>>>()
+        .and_then(|segments| match segments.first() {
+            Some((name, _)) if !name.is_empty() => Ok(segments),
+            _ => Err(invalid()),
+        })
+}
+
+fn write_value(write: &mut impl Write, value: &Value) -> std::io::Result<()> {
+    match value {
+        Value::String(text) => writeln!(write, "{}", text),
+        Value::Array(values) => values
+            .iter()
+            .try_for_each(|value| write_value(write, value)),
+        value => writeln!(write, "{}", value),
+    }
+}
+
+// writes the value at path, such as title or authors[0].family, and returns
+// whether the metadata has it; unknown keys are an error
+pub fn get(
+    metadata: &Metadata,
+    registry: &Registry,
+    path: &str,
+    mode: Mode,
+    mut write: impl Write,
+) -> std::io::Result<bool> {
+    let segments = segments(path)?;
+    let document = Value::Object(document(metadata, mode)?);
+
+    let key = segments[0].0;
+    let known = registry.keys().any(|k| k.name == key)
+        || metadata.extra.iter().any(|(name, _)| name == key);
+    if !known {
+        return Err(std::io::Error::other(format!("unknown field: {}", key)));
+    }
+
+    let mut value = &document;
+    for (name, index) in segments {
+        let next = value.get(name).and_then(|value| match index {
+            Some(index) => value.get(index),
+            None => Some(value),
+        });
+        value = match next {
+            Some(next) => next,
+            None => return Ok(false),
+        };
+    }
+
+    match mode {
+        Mode::Json => writeln!(write, "{}", value)?,
+        _ => write_value(&mut write, value)?,
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::{grammar::DEFAULT_REGISTRY, metadata::metadata};
+
+    const INPUT: &str = r#"authors=given> Aurora, family> Leão, order> 1. given> Fulano, family> Tal\par title=Comunica\c{c}\~ao \& Cultura\par abstract=Sobre \textit{Os sert\~oes}.\par keywords=Sert\~ao. M\'idia.\par year=2022\par"#;
+
+    fn get_str(path: &str, mode: Mode) -> std::io::Result<Option<String>> {
+        let (_, metadata) = metadata(INPUT.as_bytes()).unwrap();
+        let mut output = Vec::new();
+        let found = get(&metadata, &DEFAULT_REGISTRY, path, mode, &mut output)?;
+        Ok(found.then(|| String::from_utf8(output).unwrap()))
+    }
+
+    #[test]
+    fn paths() {
+        let get = |path| get_str(path, Mode::Text).unwrap();
+
+        assert_eq!(get("title").as_deref(), Some("Comunicação & Cultura\n"));
+        assert_eq!(get("authors[0].family").as_deref(), Some("Leão\n"));
+        assert_eq!(get("keywords").as_deref(), Some("Sertão\nMídia\n"));
+        assert_eq!(get("abstract").as_deref(), Some("Sobre Os sertões.\n"));
+        assert_eq!(get("year").as_deref(), Some("2022\n"));
+        assert_eq!(get("authors[2].family"), None);
+        assert_eq!(get("cover"), None);
+    }
+
+    #[test]
+    fn modes() {
+        assert_eq!(
+            get_str("title", Mode::Raw).unwrap().as_deref(),
+            Some("Comunica\\c{c}\\~ao \\& Cultura\n")
+        );
+        assert_eq!(
+            get_str("abstract", Mode::Raw).unwrap().as_deref(),
+            Some("Sobre \\textit{Os sert\\~oes}.\n")
+        );
+        assert_eq!(
+            get_str("authors[0]", Mode::Json).unwrap().as_deref(),
+            Some("{\"family\":\"Leão\",\"given\":\"Aurora\",\"order\":1}\n")
+        );
+        assert_eq!(
+            get_str("authors[1]", Mode::Json).unwrap().as_deref(),
+            Some("{\"family\":\"Tal\",\"given\":\"Fulano\"}\n")
+        );
+        assert_eq!(
+            get_str("keywords", Mode::Json).unwrap().as_deref(),
+            Some("[\"Sertão\",\"Mídia\"]\n")
+        );
+    }
+
+    #[test]
+    fn invalid_paths() {
+        assert!(get_str("titel", Mode::Text).is_err());
+        assert!(get_str("authors[x]", Mode::Text).is_err());
+        assert!(get_str("authors[0", Mode::Text).is_err());
+        assert!(get_str("", Mode::Text).is_err());
+    }
+}
@@ -0,0 +1,145 @@
+const ABBREVIATIONS: &[&str] = &[
+    "al", "cf", "dr", "dra", "ed", "eds", "etc", "ex", "n", "org", "orgs", "p", "pp", "prof",
+    "profa", "sr", "sra", "v", "vol",
+];
+
+const CLOSING: &[char] = &['"', '\'', '”', '’', '»'];
+
+fn abbreviation(before: &str) -> bool {
+    let word = before
+        .rsplit(|c: char| c.is_whitespace() || c == '(')
+        .next()
+        .unwrap_or_default();
+
+    // initials (E.) and dotted acronyms (S.A.) never end a sentence
+    word.chars().count() == 1
+        || word.contains('.')
+        || ABBREVIATIONS
+            .iter()
+            .any(|abbreviation| abbreviation.eq_ignore_ascii_case(word))
+}
+
+fn starts_sentence(rest: &str) -> bool {
+    match rest.trim_start().chars().next() {
+        Some(c) => c.is_uppercase() || c.is_ascii_digit() || "\"“'‘«(".contains(c),
+        None => true,
+    }
+}
+
+// sentences of plain text, trimmed; periods inside parentheses, such as
+// in citations, and after abbreviations do not end a sentence
+pub fn sentences(text: &str) -> Sentences<'_> {
+    Sentences {
+        text,
+        chars: text.char_indices().peekable(),
+        start: 0,
+        depth: 0,
+    }
+}
+
+pub struct Sentences<'t> {
+    text: &'t str,
+    chars: std::iter::Peekable<std::str::CharIndices<'t>>,
+    start: usize,
+    depth: usize,
+}
+
+impl<'t> Iterator for Sentences<'t> {
+    type Item = &'t str;
+
+    fn next(&mut self) -> Option<&'t str> {
+        let text = self.text;
+        while let Some((i, c)) = self.chars.next() {
+            match c {
+                '(' => self.depth += 1,
+                ')' => self.depth = self.depth.saturating_sub(1),
+                '.' | '!' | '?' if self.depth == 0 => {
+                    let mut end = i + c.len_utf8();
+                    while let Some((j, c)) = self.chars.peek().copied() {
+                        if !CLOSING.contains(&c) && !".!?".contains(c) {
+                            break;
+                        }
+                        end = j + c.len_utf8();
+                        self.chars.next();
+                    }
+
+                    let rest = &text[end..];
+                    let boundary = (rest.is_empty() || rest.starts_with(char::is_whitespace))
+                        && starts_sentence(rest)
+                        && !(c == '.' && abbreviation(&text[self.start..i]));
+
+                    if boundary {
+                        let sentence = text[self.start..end].trim();
+                        self.start = end;
+                        return Some(sentence);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let last = text[self.start..].trim();
+        self.start = text.len();
+        Some(last).filter(|last| !last.is_empty())
+    }
+}
+
+pub fn complete(sentence: &str) -> bool {
+    sentence
+        .trim_end_matches(CLOSING)
+        .ends_with(['.', '!', '?'])
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn split(text: &str) -> Vec<&str> {
+        sentences(text).collect()
+    }
+
+    #[test]
+    fn simple() {
+        assert_eq!(
+            split("O sertão resiste. Por quê? Porque sim!"),
+            ["O sertão resiste.", "Por quê?", "Porque sim!"]
+        );
+    }
+
+    #[test]
+    fn abbreviations() {
+        assert_eq!(
+            split(
+                "A Petrobras S.A. cresceu, segundo o Prof. Silva e E. Cunha et al. em 2004. Fim."
+            ),
+            [
+                "A Petrobras S.A. cresceu, segundo o Prof. Silva e E. Cunha et al. em 2004.",
+                "Fim."
+            ]
+        );
+    }
+
+    #[test]
+    fn citations() {
+        assert_eq!(
+            split("Como visto (SANTOS, 2004, p. 23. Ver também). Depois “citou.” Outra"),
+            [
+                "Como visto (SANTOS, 2004, p. 23. Ver também).",
+                "Depois “citou.”",
+                "Outra"
+            ]
+        );
+        assert!(complete("Depois “citou.”"));
+        assert!(!complete("Outra"));
+    }
+
+    #[test]
+    fn lowercase_continuation() {
+        assert_eq!(
+            split("Foi em 3. de maio. Fim."),
+            ["Foi em 3. de maio.", "Fim."]
+        );
+        assert!(sentences("  ").next().is_none());
+    }
+}
@@ -0,0 +1,396 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, SystemTime},
+};
+
+use nom_bibtex::Bibtex;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    batch::located,
+    bib::BibIndex,
+    grammar::Registry,
+    limits::Limits,
+    metadata::{parse_all, WriteOptions},
+};
+
+const MAX_BODY: usize = 1 << 20;
+// the request line and the headers together
+const MAX_HEAD: u64 = 16 << 10;
+const MAX_CONNECTIONS: usize = 64;
+// of every read and write, so that a stalled client gives its thread back
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize)]
+struct RenderRequest {
+    metadata: String,
+    #[serde(default)]
+    bib: String,
+}
+
+/// Serves `POST /render` with the keys and options of `registry` and
+/// `options`, e.g. from a profile. The body is a JSON object with the
+/// `metadata` and optionally the `bib` source; the response is a JSON
+/// object with the `markdown` page, the `json` search entry and the
+/// located `errors` and `warnings`.
+pub fn serve(addr: &str, mut registry: Registry, options: &WriteOptions) -> std::io::Result<()> {
+    registry.set_limits(Limits {
+        max_input: Some(256 << 10),
+        max_parts: Some(10_000),
+        max_depth: None,
+        cancel: None,
+    });
+
+    let listener = TcpListener::bind(addr)?;
+    eprintln!("listening on http://{}", listener.local_addr()?);
+
+    let open = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for stream in listener.incoming() {
+            // e.g. out of file descriptors; later connections may succeed
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    eprintln!("accept failed: {}", err);
+                    std::thread::sleep(Duration::from_millis(100));
+                    continue;
+                }
+            };
+            if open.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                open.fetch_sub(1, Ordering::SeqCst);
+                let busy = message("too many connections");
+                if let Err(err) = respond(&stream, "503 Service Unavailable", &busy) {
+                    eprintln!("request failed: {}", err);
+                }
+                continue;
+            }
+
+            let (registry, open) = (&registry, &open);
+            scope.spawn(move || {
+                if let Err(err) = connection(&stream, registry, options) {
+                    eprintln!("request failed: {}", err);
+                }
+                open.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    });
+
+    Ok(())
+}
+
+fn connection(
+    stream: &TcpStream,
+    registry: &Registry,
+    options: &WriteOptions,
+) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    handle(BufReader::new(stream), stream, registry, options)
+}
+
+fn handle(
+    mut reader: impl BufRead,
+    write: impl Write,
+    registry: &Registry,
+    options: &WriteOptions,
+) -> std::io::Result<()> {
+    let mut head = reader.by_ref().take(MAX_HEAD);
+    let mut line = String::new();
+    head.read_line(&mut line)?;
+
+    let mut request_line = line.split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_owned();
+    let path = request_line.next().unwrap_or_default().to_owned();
+
+    let mut content_length = 0;
+    let mut complete = false;
+    loop {
+        line.clear();
+        if head.read_line(&mut line)? == 0 {
+            break;
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            complete = true;
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    if !complete && head.limit() == 0 {
+        let too_large = message("request headers too large");
+        return respond(write, "431 Request Header Fields Too Large", &too_large);
+    }
+
+    // never buffer more than a generous metadata file and its bibliography
+    let mut body = vec![0; content_length.min(MAX_BODY)];
+    if content_length <= MAX_BODY {
+        reader.read_exact(&mut body)?;
+    }
+
+    let (status, body) = match (method.as_str(), path.as_str()) {
+        ("POST", "/render") if content_length > MAX_BODY => {
+            ("413 Payload Too Large", message("request too large"))
+        }
+        ("POST", "/render") => render(&body, registry, options),
+        (_, "/render") => ("405 Method Not Allowed", message("only POST is allowed")),
+        _ => ("404 Not Found", message("not found")),
+    };
+
+    respond(write, status, &body)
+}
+
+// a response with a single error and no position, e.g. for a bad request
+fn message(message: &str) -> serde_json::Value {
+    json!({ "errors": [located(message.to_owned(), None)], "warnings": [] })
+}
+
+fn respond(mut write: impl Write, status: &str, body: &serde_json::Value) -> std::io::Result<()> {
+    let body = serde_json::to_vec(body)?;
+    write!(
+        write,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        body.len()
+    )?;
+    write.write_all(&body)?;
+    write.flush()
+}
+
+fn render(
+    body: &[u8],
+    registry: &Registry,
+    options: &WriteOptions,
+) -> (&'static str, serde_json::Value) {
+    let request: RenderRequest = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(err) => {
+            return (
+                "400 Bad Request",
+                message(&format!("invalid request: {}", err)),
+            )
+        }
+    };
+
+    let input = request.metadata.as_bytes();
+    let (metadata, errors) = parse_all(input, registry);
+    let mut warnings: Vec<_> = metadata
+        .warnings
+        .iter()
+        .map(|warning| located(warning.to_string(), Some(warning.position())))
+        .collect();
+    if !errors.is_empty() {
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|err| located(err.to_string(), err.position()))
+            .collect();
+        return (
+            "422 Unprocessable Entity",
+            json!({ "errors": errors, "warnings": warnings }),
+        );
+    }
+
+    let bib = match Bibtex::parse(&request.bib) {
+        Ok(bib) => bib,
+        Err(err) => {
+            let error = located(format!("invalid bibliography: {}", err), None);
+            return (
+                "422 Unprocessable Entity",
+                json!({ "errors": [error], "warnings": warnings }),
+            );
+        }
+    };
+    let bib = BibIndex::new(&bib);
+
+    let written = metadata
+        .write_warnings(input, &bib, options)
+        .and_then(|more| {
+            let mut page = Vec::new();
+            metadata.wtite_to(&mut page, &bib, SystemTime::now().into(), options)?;
+            Ok((more, page, metadata.search_entry(&bib)?))
+        });
+    match written {
+        Ok((more, page, entry)) => {
+            warnings.extend(
+                more.iter()
+                    .map(|warning| located(warning.to_string(), Some(warning.position()))),
+            );
+            (
+                "200 OK",
+                json!({
+                    "markdown": String::from_utf8_lossy(&page),
+                    "json": entry,
+                    "errors": [],
+                    "warnings": warnings,
+                }),
+            )
+        }
+        // e.g. a citation missing from the bibliography
+        Err(err) => (
+            "422 Unprocessable Entity",
+            json!({ "errors": [located(err.to_string(), None)], "warnings": warnings }),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::front_matter::FrontMatter;
+
+    fn request_with(
+        method: &str,
+        path: &str,
+        body: &str,
+        options: &WriteOptions,
+    ) -> (String, serde_json::Value) {
+        let request = format!(
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            body.len(),
+            body
+        );
+
+        let mut output = Vec::new();
+        handle(
+            Cursor::new(request),
+            &mut output,
+            &Registry::default(),
+            options,
+        )
+        .unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let (head, body) = output.split_once("\r\n\r\n").unwrap();
+        (head.to_owned(), serde_json::from_str(body).unwrap())
+    }
+
+    fn request(method: &str, path: &str, body: &str) -> (String, serde_json::Value) {
+        request_with(method, path, body, &WriteOptions::default())
+    }
+
+    #[test]
+    fn render_markdown() {
+        let (head, body) = request(
+            "POST",
+            "/render",
+            r#"{"metadata": "title=Sertão\\par abstract=Segundo \\cite{Key}.\\par", "bib": "@book{Key, author = {Cunha, E.}, title = {Os sertões}, year = {1902}}"}"#,
+        );
+
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains("Content-Type: application/json\r\n"));
+        let markdown = body["markdown"].as_str().unwrap();
+        assert!(markdown.contains("title: \"Sertão\"\n"));
+        assert!(markdown.contains("**Resumo:** Segundo (CUNHA, 1902)."));
+        assert_eq!(body["json"]["title"], "Sertão");
+        assert_eq!(body["errors"], json!([]));
+    }
+
+    #[test]
+    fn options() {
+        let options = WriteOptions {
+            front_matter: FrontMatter::Toml,
+            ..Default::default()
+        };
+        let (_, body) = request_with(
+            "POST",
+            "/render",
+            r#"{"metadata": "title=Sertão\\par"}"#,
+            &options,
+        );
+
+        assert!(body["markdown"].as_str().unwrap().starts_with("+++\n"));
+    }
+
+    #[test]
+    fn diagnostics() {
+        let (head, body) = request(
+            "POST",
+            "/render",
+            r#"{"metadata": "title=Sertão\\par\nauthors=given> Aurora\\par"}"#,
+        );
+
+        assert!(head.starts_with("HTTP/1.1 422 Unprocessable Entity\r\n"));
+        assert_eq!(
+            body["errors"],
+            json!([{
+                "message": "invalid authors field at line 2, column 22",
+                "line": 2,
+                "column": 22,
+            }])
+        );
+
+        let (head, body) = request(
+            "POST",
+            "/render",
+            r#"{"metadata": "abstract=Ver \\ref{fig}.\\par"}"#,
+        );
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert_eq!(body["warnings"][0]["line"], 1);
+        assert_eq!(body["warnings"][0]["column"], 14);
+    }
+
+    #[test]
+    fn missing_citation() {
+        let (head, body) = request(
+            "POST",
+            "/render",
+            r#"{"metadata": "abstract=Segundo \\cite{Key}.\\par"}"#,
+        );
+
+        assert!(head.starts_with("HTTP/1.1 422 Unprocessable Entity\r\n"));
+        assert_eq!(body["errors"][0]["message"], "bibliography not found: Key");
+    }
+
+    #[test]
+    fn too_large() {
+        let request = format!(
+            "POST /render HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY + 1
+        );
+
+        let mut output = Vec::new();
+        let (registry, options) = (Registry::default(), WriteOptions::default());
+        handle(Cursor::new(request), &mut output, &registry, &options).unwrap();
+
+        assert!(output.starts_with(b"HTTP/1.1 413 Payload Too Large\r\n"));
+    }
+
+    #[test]
+    fn headers_too_large() {
+        let request = format!(
+            "POST /render HTTP/1.1\r\nX-Padding: {}\r\n\r\n",
+            "a".repeat(MAX_HEAD as usize)
+        );
+
+        let mut output = Vec::new();
+        let (registry, options) = (Registry::default(), WriteOptions::default());
+        handle(Cursor::new(request), &mut output, &registry, &options).unwrap();
+
+        assert!(output.starts_with(b"HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+    }
+
+    #[test]
+    fn wrong_method() {
+        let (head, _) = request("GET", "/render", "");
+
+        assert!(head.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+    }
+
+    #[test]
+    fn not_found() {
+        let (head, _) = request("POST", "/", "");
+
+        assert!(head.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
@@ -0,0 +1,53 @@
+pub fn slug(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    let mut pending_dash = false;
+
+    for c in text.chars().flat_map(char::to_lowercase) {
+        let c = match c {
+            'á' | 'à' | 'â' | 'ã' | 'ä' => 'a',
+            'é' | 'è' | 'ê' | 'ë' => 'e',
+            'í' | 'ì' | 'î' | 'ï' => 'i',
+            'ó' | 'ò' | 'ô' | 'õ' | 'ö' => 'o',
+            'ú' | 'ù' | 'û' | 'ü' => 'u',
+            'ç' => 'c',
+            'ñ' => 'n',
+            c => c,
+        };
+
+        if c.is_ascii_alphanumeric() {
+            if pending_dash && !slug.is_empty() {
+                slug.push('-');
+            }
+            pending_dash = false;
+            slug.push(c);
+        } else {
+            pending_dash = true;
+        }
+    }
+
+    slug
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::slug;
+
+    #[test]
+    fn title() {
+        assert_eq!(
+            slug("Euclides da Cunha atualizado no sertão da teledramaturgia"),
+            "euclides-da-cunha-atualizado-no-sertao-da-teledramaturgia"
+        );
+    }
+
+    #[test]
+    fn punctuation() {
+        assert_eq!(slug("  Ação, reação: (e) emoção! "), "acao-reacao-e-emocao");
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(slug(" -- "), "");
+    }
+}
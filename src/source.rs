@@ -0,0 +1,587 @@
+use std::io::Write;
+
+use serde_json::Value;
+
+use crate::{
+    grammar::{Registry, KEYS},
+    metadata::{divisor, parse, Metadata},
+    paragraph::paragraph_with,
+    r#abstract::canonical_command,
+};
+
+pub fn field(metadata: &Metadata, key: &str) -> std::io::Result<Option<Vec<u8>>> {
+    let value = match key {
+        "authors" => match metadata.authors.as_ref() {
+            Some(authors) => {
+                let mut buf = Vec::new();
+                for (i, author) in authors.iter().enumerate() {
+                    if i > 0 {
+                        buf.extend_from_slice(b". ");
+                    }
+                    buf.extend_from_slice(b"given> ");
+                    buf.extend_from_slice(author.given.trim_ascii());
+                    buf.extend_from_slice(b", family> ");
+                    buf.extend_from_slice(author.family.trim_ascii());
+                    if let Some(order) = author.order {
+                        buf.extend_from_slice(format!(", order> {}", order).as_bytes());
+                    }
+                }
+                buf
+            }
+            None => return Ok(None),
+        },
+        "abstract" => match metadata.r#abstract.as_ref() {
+            Some(r#abstract) => {
+                let mut buf = Vec::new();
+                r#abstract.write_source(&mut buf)?;
+                buf.trim_ascii().to_vec()
+            }
+            None => return Ok(None),
+        },
+        "title" => return Ok(metadata.title.map(trimmed)),
+        "first_page" => return Ok(metadata.first_page.map(trimmed)),
+        "last_page" => return Ok(metadata.last_page.map(trimmed)),
+        "keywords" => return Ok(metadata.keywords.map(trimmed)),
+        "section" => match metadata.sections().as_slice() {
+            [] => return Ok(None),
+            sections => sections.join(&b"; "[..]),
+        },
+        "number" => return Ok(metadata.number.map(trimmed)),
+        "semester" => return Ok(metadata.semester.map(trimmed)),
+        "year" => return Ok(metadata.year.map(trimmed)),
+        "cover" => return Ok(metadata.cover.map(trimmed)),
+        "language" => return Ok(metadata.language.map(trimmed)),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(value))
+}
+
+fn trimmed(value: &[u8]) -> Vec<u8> {
+    value.trim_ascii().to_vec()
+}
+
+fn write_field(mut write: impl Write, key: &str, value: &[u8]) -> std::io::Result<()> {
+    write.write_all(key.as_bytes())?;
+    write.write_all(b"=")?;
+    write.write_all(value)?;
+    write.write_all(b"\\par\n")
+}
+
+fn keys<'m>(metadata: &[&'m Metadata]) -> Vec<&'m str> {
+    let mut keys: Vec<&str> = KEYS.iter().map(|key| key.name.as_ref()).collect();
+    for (name, _) in metadata.iter().flat_map(|metadata| &metadata.extra) {
+        if !keys.contains(&name.as_str()) {
+            keys.push(name);
+        }
+    }
+    keys
+}
+
+fn lines(metadata: &Metadata, key: &str) -> std::io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    if key == "section" {
+        // one field each, as they may have been given
+        for section in &metadata.section {
+            write_field(&mut buf, key, section.trim_ascii())?;
+        }
+    } else if let Some(value) = field(metadata, key)? {
+        write_field(&mut buf, key, &value)?;
+    }
+    for (_, value) in metadata.extra.iter().filter(|(name, _)| name == key) {
+        write_field(&mut buf, key, value.trim_ascii())?;
+    }
+    Ok(buf)
+}
+
+pub fn write_source(metadata: &Metadata, mut write: impl Write) -> std::io::Result<()> {
+    for key in keys(&[metadata]) {
+        write.write_all(&lines(metadata, key)?)?;
+    }
+
+    Ok(())
+}
+
+// the comment lines of the text between two fields, with the blank lines
+// among them; `first` when nothing comes before it
+fn comments(gap: &[u8], first: bool, mut write: impl Write) -> std::io::Result<()> {
+    if !gap.contains(&b'%') {
+        return Ok(());
+    }
+    let mut lines: Vec<_> = gap.split(|c| *c == b'\n').map(<[u8]>::trim_ascii).collect();
+    // the rest of the line of the field before and the indentation of the
+    // field after
+    if !first && lines.first().is_some_and(|line| line.is_empty()) {
+        lines.remove(0);
+    }
+    if lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    for line in lines {
+        if line.is_empty() || line.starts_with(b"%") {
+            write.write_all(line)?;
+            write.write_all(b"\n")?;
+        }
+    }
+    Ok(())
+}
+
+// like write_source, keeping the comments of `input` before the field that
+// follows them, or at the end
+fn write_formatted(
+    metadata: &Metadata,
+    input: &[u8],
+    mut write: impl Write,
+) -> std::io::Result<()> {
+    let mut spans: Vec<_> = metadata.spans.iter().collect();
+    spans.sort_by_key(|(_, span)| span.start);
+    let mut before = Vec::new();
+    let mut end = 0;
+    for (name, span) in spans {
+        before.push((name.as_str(), &input[end..span.start], end == 0));
+        end = span.end;
+    }
+
+    for key in keys(&[metadata]) {
+        for (_, gap, first) in before.iter().filter(|(name, ..)| *name == key) {
+            comments(gap, *first, &mut write)?;
+        }
+        write.write_all(&lines(metadata, key)?)?;
+    }
+    comments(&input[end..], end == 0, &mut write)
+}
+
+// the fields of `input` as formatting leaves them, found lexically: each
+// one as its canonical key and the words of its value, sorted, since
+// formatting may change the order of fields and of author parts, the
+// whitespace and punctuation between parts, braces and command aliases
+fn normalized(input: &[u8], registry: &Registry) -> Vec<(String, Vec<Vec<u8>>)> {
+    let mut fields = Vec::new();
+    for range in registry.terminator.fields(input) {
+        let mut field = input[range].trim_ascii();
+        // comments before a field, as the parser skips them
+        while let Some(comment) = field.strip_prefix(b"%") {
+            let end = memchr::memchr(b'\n', comment).map_or(comment.len(), |end| end + 1);
+            field = comment[end..].trim_ascii_start();
+        }
+        if field.is_empty() {
+            continue;
+        }
+
+        let (name, value) = match registry.key(field) {
+            Ok((rest, key)) => match divisor(rest) {
+                Ok((value, _)) => (key.name.to_string(), value),
+                Err(_) => (String::new(), field),
+            },
+            Err(_) => (String::new(), field),
+        };
+        let mut words = Vec::new();
+        let mut word = Vec::new();
+        let mut finish = |word: &mut Vec<u8>| {
+            if let Some(name) = word.strip_prefix(b"\\") {
+                let name = String::from_utf8_lossy(name);
+                *word = format!("\\{}", canonical_command(&name)).into_bytes();
+            }
+            if !word.is_empty() {
+                words.push(std::mem::take(word));
+            }
+        };
+        for &c in value {
+            match c {
+                c if c.is_ascii_whitespace() => finish(&mut word),
+                b',' | b'.' | b';' | b'{' | b'}' | b'[' | b']' | b'>' | b'=' => finish(&mut word),
+                b'\\' => {
+                    finish(&mut word);
+                    word.push(c);
+                }
+                c => word.push(c),
+            }
+        }
+        finish(&mut word);
+        words.sort();
+        fields.push((name, words));
+    }
+    fields.sort();
+    fields
+}
+
+pub fn fmt(input: &[u8], registry: &Registry) -> std::io::Result<Vec<u8>> {
+    let metadata = parse(input, registry).map_err(|err| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{}; refusing to format", err),
+        )
+    })?;
+
+    let mut output = Vec::new();
+    write_formatted(&metadata, input, &mut output)?;
+
+    // checked against the input itself, not the model it was written from
+    let (before, after) = (normalized(input, registry), normalized(&output, registry));
+    if before != after {
+        let changed = before
+            .iter()
+            .zip(&after)
+            .find(|(before, after)| before != after)
+            .or(before.last().zip(after.last()))
+            .map_or("", |(before, _)| before.0.as_str());
+        return Err(std::io::Error::other(match changed {
+            "" => "formatting would change the metadata".to_owned(),
+            key => format!("formatting would change the {} field", key),
+        }));
+    }
+
+    Ok(output)
+}
+
+// the value of the field in `field`, as a range of it, without the key and
+// the surrounding whitespace
+fn value_range(field: &[u8], registry: &Registry) -> std::io::Result<std::ops::Range<usize>> {
+    let invalid = |_| std::io::Error::other("field could not be read back");
+    let (rest, _) = registry.key(field).map_err(invalid)?;
+    let (rest, _) = divisor(rest).map_err(invalid)?;
+    let (_, value) = paragraph_with(rest, &registry.terminator).map_err(invalid)?;
+
+    let start = field.len() - rest.len() + (value.len() - value.trim_ascii_start().len());
+    Ok(start..start + value.trim_ascii().len())
+}
+
+/// Replaces the value of `key` with `value`, as LaTeX source, adding the
+/// field when it is missing; without a value the field is removed. The rest
+/// of the input is kept byte for byte.
+pub fn set(
+    input: &[u8],
+    registry: &Registry,
+    key: &str,
+    value: Option<&[u8]>,
+) -> std::io::Result<Vec<u8>> {
+    let metadata = parse(input, registry)
+        .map_err(|err| std::io::Error::other(format!("{}; refusing to edit", err)))?;
+    let known = registry.keys().any(|k| k.name == key)
+        || metadata.extra.iter().any(|(name, _)| name == key);
+    if !known {
+        return Err(std::io::Error::other(format!("unknown key: {}", key)));
+    }
+
+    let spans: Vec<_> = metadata
+        .spans
+        .iter()
+        .filter(|(name, _)| name == key)
+        .map(|(_, span)| span.clone())
+        .collect();
+    let mut output = Vec::with_capacity(input.len());
+    match (spans.as_slice(), value) {
+        ([], None) => return Ok(input.to_vec()),
+        ([], Some(value)) => {
+            let end = input.trim_ascii_end().len();
+            output.extend_from_slice(&input[..end]);
+            if end > 0 {
+                output.push(b'\n');
+            }
+            output.extend_from_slice(key.as_bytes());
+            output.push(b'=');
+            output.extend_from_slice(value);
+            output.extend_from_slice(registry.terminator.written().as_bytes());
+            output.push(b'\n');
+        }
+        ([span], Some(value)) => {
+            let range = value_range(&input[span.clone()], registry)?;
+            output.extend_from_slice(&input[..span.start + range.start]);
+            output.extend_from_slice(value);
+            output.extend_from_slice(&input[span.start + range.end..]);
+        }
+        ([span], None) => {
+            output.extend_from_slice(&input[..span.start]);
+            let rest = &input[span.end..];
+            output.extend_from_slice(rest.strip_prefix(b"\n").unwrap_or(rest));
+        }
+        (spans, _) => {
+            return Err(std::io::Error::other(format!(
+                "{} appears {} times; edit it by hand",
+                key,
+                spans.len()
+            )))
+        }
+    }
+
+    // the other fields must be left as they were
+    let updated = parse(&output, registry).map_err(|err| {
+        std::io::Error::other(format!("the new value of {} does not parse: {}", key, err))
+    })?;
+    for other in keys(&[&metadata, &updated]).into_iter().filter(|other| *other != key) {
+        if lines(&metadata, other)? != lines(&updated, other)? {
+            return Err(std::io::Error::other(format!(
+                "setting {} would change the {} field",
+                key, other
+            )));
+        }
+    }
+
+    Ok(output)
+}
+
+/// The source of a JSON value for `key`: strings as they are, authors as
+/// objects with given and family, keywords and sections as lists; null
+/// removes the field.
+pub fn json_source(key: &str, value: &Value) -> std::io::Result<Option<Vec<u8>>> {
+    let invalid = || std::io::Error::other(format!("unexpected JSON value for {}: {}", key, value));
+    let strings = |values: &[Value]| {
+        values
+            .iter()
+            .map(|value| value.as_str().map(str::to_owned).ok_or_else(invalid))
+            .collect::<std::io::Result<Vec<_>>>()
+    };
+
+    let source = match (key, value) {
+        (_, Value::Null) => return Ok(None),
+        (_, Value::String(text)) => text.clone(),
+        (_, Value::Number(number)) => number.to_string(),
+        ("authors", Value::Array(authors)) => {
+            let authors = authors
+                .iter()
+                .map(|author| {
+                    let (given, family) = (author["given"].as_str(), author["family"].as_str());
+                    match (given, family, &author["order"]) {
+                        (Some(given), Some(family), Value::Null) => {
+                            Ok(format!("given> {}, family> {}", given, family))
+                        }
+                        (Some(given), Some(family), Value::Number(order)) => {
+                            Ok(format!("given> {}, family> {}, order> {}", given, family, order))
+                        }
+                        _ => Err(invalid()),
+                    }
+                })
+                .collect::<std::io::Result<Vec<_>>>()?;
+            authors.join(". ")
+        }
+        ("keywords", Value::Array(keywords)) => format!("{}.", strings(keywords)?.join(". ")),
+        ("section", Value::Array(sections)) => strings(sections)?.join("; "),
+        _ => return Err(invalid()),
+    };
+
+    Ok(Some(source.into_bytes()))
+}
+
+pub fn merge(
+    base: &Metadata,
+    ours: &Metadata,
+    theirs: &Metadata,
+    mut write: impl Write,
+) -> std::io::Result<bool> {
+    let mut conflicted = false;
+
+    for key in keys(&[base, ours, theirs]) {
+        let base = lines(base, key)?;
+        let ours = lines(ours, key)?;
+        let theirs = lines(theirs, key)?;
+
+        if ours == theirs || theirs == base {
+            write.write_all(&ours)?;
+        } else if ours == base {
+            write.write_all(&theirs)?;
+        } else {
+            conflicted = true;
+            write.write_all(b"<<<<<<< ours\n")?;
+            write.write_all(&ours)?;
+            write.write_all(b"=======\n")?;
+            write.write_all(&theirs)?;
+            write.write_all(b">>>>>>> theirs\n")?;
+        }
+    }
+
+    Ok(conflicted)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::{
+        grammar::{Key, Multiplicity},
+        metadata::metadata,
+    };
+
+    fn source(input: &str) -> String {
+        String::from_utf8(fmt(input.as_bytes(), &Registry::default()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn canonical() {
+        let output = source(
+            r#" year = 2022 \par authors=given > Aurora ,family> Leão. given>Fulano, family>Tal, order> 1\par title=Sertão  \par abstract= Sobre \textit {Os sertões} \cite {EcCUNHA1902sertoes}. \par"#,
+        );
+
+        assert_eq!(
+            output,
+            "authors=given> Fulano, family> Tal, order> 1. given> Aurora, family> Leão\\par\ntitle=Sertão\\par\nabstract=Sobre \\textit{Os sertões} \\cite{EcCUNHA1902sertoes}.\\par\nyear=2022\\par\n"
+        );
+        assert_eq!(source(&output), output);
+    }
+
+    #[test]
+    fn fmt_refuses_unparsed_input() {
+        assert!(fmt(br#"title=Sertao\par doi=10.1234/x\par"#, &Registry::default()).is_err());
+    }
+
+    #[test]
+    fn fmt_refuses_dropped_text() {
+        let registry = Registry::default();
+
+        // the abstract ends at the unknown command
+        let err = fmt(
+            br#"title=Sertao\par abstract=Texto \foo{x} e mais texto importante.\par"#,
+            &registry,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unexpected content at line 1, column 33: \\foo{x} e mais texto importante.; \
+             refusing to format"
+        );
+
+        let output = fmt(
+            br#"section=A\par abstract=Como \citep [p. 2] {Key} e \aspas x.\par section=B\par"#,
+            &registry,
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            b"abstract=Como \\cite[p. 2]{Key} e \\enquote{x.}\\par\nsection=A\\par\nsection=B\\par\n"
+        );
+    }
+
+    #[test]
+    fn fmt_keeps_comments() {
+        let output = source("% revisado\ntitle = Sertão\\par % provisório\n\n% ano da edição\n  year=2022\\par\nauthors=given>Aurora,family>Leão\\par\n% fim\n");
+
+        assert_eq!(
+            output,
+            "authors=given> Aurora, family> Leão\\par\n% revisado\ntitle=Sertão\\par\n% provisório\n\n% ano da edição\nyear=2022\\par\n% fim\n"
+        );
+        assert_eq!(source(&output), output);
+
+        // the template handed to authors
+        let mut template = Vec::new();
+        crate::grammar::example(&mut template, &Registry::default()).unwrap();
+        let template = String::from_utf8(template).unwrap();
+        assert_eq!(source(&template), template);
+    }
+
+    #[test]
+    fn fmt_extra_keys() {
+        let mut registry = Registry::default();
+        registry.register(Key::extra("funding".to_owned(), Multiplicity::Many));
+
+        let output = fmt(
+            br#"funding = CAPES \par title=Sertao\par funding=CNPq\par"#,
+            &registry,
+        )
+        .unwrap();
+
+        assert_eq!(
+            output,
+            b"title=Sertao\\par\nfunding=CAPES\\par\nfunding=CNPq\\par\n"
+        );
+    }
+
+    #[test]
+    fn set_keeps_other_fields() {
+        let input = b"% revisado\ntitle = Sert\\~ao  \\par\nauthors=given>Aurora,family>Le\xc3\xa3o\\par\n\nyear=2021\\par\n";
+        let registry = Registry::default();
+
+        let output = set(input, &registry, "title", Some(b"O sert\\~ao")).unwrap();
+        assert_eq!(
+            output,
+            b"% revisado\ntitle = O sert\\~ao  \\par\nauthors=given>Aurora,family>Le\xc3\xa3o\\par\n\nyear=2021\\par\n"
+        );
+
+        let output = set(&output, &registry, "year", None).unwrap();
+        let output = set(&output, &registry, "number", Some(b"5")).unwrap();
+        assert_eq!(
+            output,
+            b"% revisado\ntitle = O sert\\~ao  \\par\nauthors=given>Aurora,family>Le\xc3\xa3o\\par\nnumber=5\\par\n"
+        );
+    }
+
+    #[test]
+    fn set_refuses() {
+        let registry = Registry::default();
+        let input = br#"title=Sertao\par section=A\par section=B\par"#;
+
+        assert!(set(input, &registry, "titel", Some(b"x")).is_err());
+        assert!(set(input, &registry, "section", Some(b"C")).is_err());
+        // the value would end the field early
+        assert!(set(input, &registry, "title", Some(br"A\par B")).is_err());
+    }
+
+    #[test]
+    fn json_values() {
+        let patch: Value = serde_json::json!({
+            "authors": [
+                {"given": "Aurora", "family": "Leão"},
+                {"given": "Fulano", "family": "Tal", "order": 1},
+            ],
+            "keywords": ["Sertão", "Mar"],
+            "year": 2022,
+            "cover": null,
+        });
+
+        let source = |key| json_source(key, &patch[key]).unwrap();
+        assert_eq!(
+            source("authors").unwrap(),
+            "given> Aurora, family> Leão. given> Fulano, family> Tal, order> 1".as_bytes()
+        );
+        assert_eq!(source("keywords").unwrap(), "Sertão. Mar.".as_bytes());
+        assert_eq!(source("year").unwrap(), b"2022");
+        assert_eq!(source("cover"), None);
+        assert!(json_source("title", &patch["keywords"]).is_err());
+    }
+
+    #[test]
+    fn merge_identical() {
+        let input = r#"title=Sertão\par year=2021\par"#;
+
+        assert_eq!(run(input, input, input), (false, source(input)));
+    }
+
+    fn run(base: &str, ours: &str, theirs: &str) -> (bool, String) {
+        let (_, base) = metadata(base.as_bytes()).unwrap();
+        let (_, ours) = metadata(ours.as_bytes()).unwrap();
+        let (_, theirs) = metadata(theirs.as_bytes()).unwrap();
+
+        let mut output = Vec::new();
+        let conflicted = merge(&base, &ours, &theirs, &mut output).unwrap();
+
+        (conflicted, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn merge_independent_changes() {
+        let (conflicted, output) = run(
+            r#"title=Sertão\par year=2021\par"#,
+            r#"title=O sertão\par year=2021\par"#,
+            r#"title=Sertão\par year=2022\par section=Dossiê\par"#,
+        );
+
+        assert!(!conflicted);
+        assert_eq!(
+            output,
+            "title=O sertão\\par\nsection=Dossiê\\par\nyear=2022\\par\n"
+        );
+    }
+
+    #[test]
+    fn merge_conflict() {
+        let (conflicted, output) = run(
+            r#"title=Sertão\par year=2021\par"#,
+            r#"title=O sertão\par year=2021\par"#,
+            r#"title=Os sertões\par"#,
+        );
+
+        assert!(conflicted);
+        assert_eq!(
+            output,
+            "<<<<<<< ours\ntitle=O sertão\\par\n=======\ntitle=Os sertões\\par\n>>>>>>> theirs\n"
+        );
+    }
+}
@@ -1,10 +1,8 @@
 use nom::{bytes::complete::take_while, IResult};
 
+/// Skips any run of spaces, tabs and line breaks, including none.
 pub fn space(input: &[u8]) -> IResult<&[u8], ()> {
-    let (input, _) = take_while(|c| match c {
-        b' ' | b'\t' | b'\r' | b'\n' => true,
-        _ => false,
-    })(input)?;
+    let (input, _) = take_while(|c| matches!(c, b' ' | b'\t' | b'\r' | b'\n'))(input)?;
 
     Ok((input, ()))
 }
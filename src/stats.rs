@@ -0,0 +1,124 @@
+use std::{collections::BTreeMap, io::Write, path::Path};
+
+use crate::{
+    error::location,
+    grammar::{Registry, COMMANDS},
+    latex,
+    verify::files,
+};
+
+const EXAMPLES: usize = 3;
+
+// \name or a single symbol such as \-
+fn command_name(input: &[u8]) -> &[u8] {
+    let len = input.iter().take_while(|c| c.is_ascii_alphabetic()).count();
+    &input[..len.max(1).min(input.len())]
+}
+
+// offsets of every abstract command the parser does not know, found
+// lexically so that one unknown command does not hide the next ones
+pub fn unknown_commands<'a>(input: &'a [u8], registry: &Registry) -> Vec<(&'a [u8], usize)> {
+    let known = |name: &[u8]| {
+        COMMANDS
+            .iter()
+            .any(|command| command.name.as_bytes() == name)
+            || registry
+                .commands
+                .iter()
+                .any(|command| command.name.as_bytes() == name)
+    };
+
+    let mut unknown = Vec::new();
+    for field in registry.terminator.fields(input) {
+        let offset = field.start;
+        let field = &input[field];
+
+        let value = field.trim_ascii_start();
+        let offset = offset + field.len() - value.len();
+        match registry.key(value) {
+            Ok((_, key)) if key.name == "abstract" => {}
+            _ => continue,
+        }
+
+        for pos in memchr::memchr_iter(b'\\', value) {
+            let name = command_name(&value[pos + 1..]);
+            if !name.is_empty() && !known(name) && !latex::is_symbol(&value[pos..]) {
+                unknown.push((name, offset + pos));
+            }
+        }
+    }
+
+    unknown
+}
+
+pub fn stats(dir: &Path, registry: &Registry, mut write: impl Write) -> std::io::Result<()> {
+    let mut commands: BTreeMap<String, (usize, Vec<String>)> = BTreeMap::new();
+
+    for path in files(dir)? {
+        let input = std::fs::read(&path)?;
+        for (name, offset) in unknown_commands(&input, registry) {
+            let (line, column) = location(&input, offset);
+            let (count, examples) = commands
+                .entry(String::from_utf8_lossy(name).into_owned())
+                .or_default();
+            *count += 1;
+            if examples.len() < EXAMPLES {
+                examples.push(format!("{}:{}:{}", path.display(), line, column));
+            }
+        }
+    }
+
+    let mut commands: Vec<_> = commands.into_iter().collect();
+    commands.sort_by(|(_, (a, _)), (_, (b, _))| b.cmp(a));
+
+    writeln!(write, "unknown abstract commands:")?;
+    for (name, (count, examples)) in commands {
+        writeln!(write, "\\{} {} {}", name, count, examples.join(", "))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn unknown() {
+        let input = b"title=Um \\emph{x}\\par\nabstract=Um \\footnote{a} e \\textit{b} \\-\n\\footnote{c} \\href{d}.\\par keywords=x\\par";
+
+        let found: Vec<_> = unknown_commands(input, &Registry::default())
+            .into_iter()
+            .map(|(name, offset)| (name, location(input, offset)))
+            .collect();
+
+        assert_eq!(
+            found,
+            [
+                (&b"footnote"[..], (2, 13)),
+                (&b"footnote"[..], (3, 1)),
+                (&b"href"[..], (3, 14)),
+            ]
+        );
+    }
+
+    #[test]
+    fn report() {
+        let dir = std::env::temp_dir().join(format!("stats-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.tex"), r"abstract=\href{x} e \footnote{y}\par").unwrap();
+        std::fs::write(dir.join("b.tex"), r"abstract=\footnote{z}\par").unwrap();
+
+        let mut output = Vec::new();
+        stats(&dir, &Registry::default(), &mut output).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines[0], "unknown abstract commands:");
+        assert!(lines[1].starts_with("\\footnote 2 "));
+        assert!(lines[1].ends_with("b.tex:1:10"));
+        assert!(lines[2].starts_with("\\href 1 "));
+    }
+}
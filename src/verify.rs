@@ -0,0 +1,172 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    bib::BibIndex,
+    grammar::Registry,
+    metadata::{metadata_with, WriteOptions},
+};
+
+// lines that legitimately change on every regeneration
+fn volatile(line: &[u8]) -> bool {
+    line.starts_with(b"date: ")
+        || line.starts_with(b"date = ")
+        || line.starts_with(b"# generated by ")
+}
+
+fn significant(page: &[u8]) -> Vec<&[u8]> {
+    page.split(|b| *b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .filter(|line| !volatile(line))
+        .collect()
+}
+
+pub(crate) fn files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                pending.push(entry.path());
+            } else if !entry.file_name().to_string_lossy().starts_with('.') {
+                files.push(entry.path());
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+// re-renders every metadata file below `meta` and compares it with the page
+// at the same relative path below `content`, without touching either tree
+pub fn verify(
+    content: &Path,
+    meta: &Path,
+    bib: &BibIndex,
+    registry: &Registry,
+    options: &WriteOptions,
+    mut write: impl Write,
+) -> std::io::Result<bool> {
+    let mut drift = false;
+
+    for source in files(meta)? {
+        let relative = source.strip_prefix(meta).map_err(std::io::Error::other)?;
+        let page = content.join(relative).with_extension("md");
+
+        let published = match std::fs::read(&page) {
+            Ok(published) => published,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                drift = true;
+                writeln!(
+                    write,
+                    "{}: missing, expected from {}",
+                    page.display(),
+                    source.display()
+                )?;
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+
+        let input = std::fs::read(&source)?;
+        let metadata = match metadata_with(&input, registry) {
+            Ok((&[], metadata)) => metadata,
+            _ => {
+                drift = true;
+                writeln!(write, "{}: invalid metadata", source.display())?;
+                continue;
+            }
+        };
+
+        let mut rendered = Vec::new();
+        metadata.wtite_to(&mut rendered, bib, chrono::Utc::now(), options)?;
+
+        let published = significant(&published);
+        let rendered = significant(&rendered);
+        if published == rendered {
+            continue;
+        }
+
+        drift = true;
+        writeln!(
+            write,
+            "{}: differs from {}",
+            page.display(),
+            source.display()
+        )?;
+        let (old, new) = published
+            .iter()
+            .zip(rendered.iter())
+            .find(|(old, new)| old != new)
+            .map(|(old, new)| (*old, *new))
+            .unwrap_or_else(|| {
+                let len = published.len().min(rendered.len());
+                (
+                    published.get(len).copied().unwrap_or_default(),
+                    rendered.get(len).copied().unwrap_or_default(),
+                )
+            });
+        write.write_all(b"- ")?;
+        write.write_all(old)?;
+        write.write_all(b"\n+ ")?;
+        write.write_all(new)?;
+        write.write_all(b"\n")?;
+    }
+
+    Ok(drift)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn drift() {
+        let dir = std::env::temp_dir().join(format!("verify-{}", std::process::id()));
+        let (content, meta) = (dir.join("content"), dir.join("meta"));
+        std::fs::create_dir_all(content.join("n5")).unwrap();
+        std::fs::create_dir_all(meta.join("n5")).unwrap();
+
+        std::fs::write(meta.join("n5/igual.tex"), r"title=Sertão\par").unwrap();
+        std::fs::write(
+            content.join("n5/igual.md"),
+            "---\ntitle: \"Sertão\"\ndate: 2022-06-01T00:00:00+00:00\n---\n\n",
+        )
+        .unwrap();
+        std::fs::write(meta.join("n5/editado.tex"), r"title=Sertão\par").unwrap();
+        std::fs::write(
+            content.join("n5/editado.md"),
+            "---\ntitle: \"O sertão\"\ndate: 2022-06-01T00:00:00+00:00\n---\n\n",
+        )
+        .unwrap();
+        std::fs::write(meta.join("novo.tex"), r"title=Mar\par").unwrap();
+
+        let mut output = Vec::new();
+        let drift = verify(
+            &content,
+            &meta,
+            &BibIndex::default(),
+            &Registry::default(),
+            &WriteOptions::default(),
+            &mut output,
+        )
+        .unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(drift);
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].contains("n5/editado.md: differs from"));
+        assert_eq!(lines[1], "- title: \"O sertão\"");
+        assert_eq!(lines[2], "+ title: \"Sertão\"");
+        assert!(lines[3].contains("novo.md: missing"));
+    }
+}
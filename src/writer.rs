@@ -0,0 +1,498 @@
+use std::{io::Write, ops::Range};
+
+use serde::Deserialize;
+
+use crate::sentence::{complete, sentences};
+
+// the inside of a YAML double quoted scalar, which can hold any text
+pub struct Escape<W: Write>(pub W);
+
+impl<W: Write> Escape<W> {
+    fn escape(&mut self, text: &[u8], c: u8) -> std::io::Result<()> {
+        self.0.write_all(text)?;
+        match c {
+            b'"' => self.0.write_all(b"\\\"")?,
+            b'\\' => self.0.write_all(b"\\\\")?,
+            b'\n' => self.0.write_all(b"\\n")?,
+            b'\r' => self.0.write_all(b"\\r")?,
+            b'\t' => self.0.write_all(b"\\t")?,
+            c => write!(self.0, "\\x{:02X}", c)?,
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for Escape<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // quotes, backslashes and line breaks are found with memchr, the
+        // rarer control characters in the text between them; bytes of
+        // multibyte characters are never below 0x80
+        let mut start = 0;
+        let found = memchr::memchr3_iter(b'"', b'\\', b'\n', buf);
+        for end in found.chain(std::iter::once(buf.len())) {
+            let control = |c: &u8| matches!(c, 0..=0x1f | 0x7f);
+            while let Some(pos) = buf[start..end].iter().position(control) {
+                self.escape(&buf[start..start + pos], buf[start + pos])?;
+                start += pos + 1;
+            }
+            if end < buf.len() {
+                self.escape(&buf[start..end], buf[end])?;
+                start = end + 1;
+            }
+        }
+        self.0.write_all(&buf[start..])?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+// collapses the spaces left behind by removed commands, e.g. "figura , o";
+// spaces starting a line are indentation and kept
+pub struct Tidy<W: Write> {
+    write: W,
+    space: bool,
+    line_start: bool,
+    written: usize,
+}
+
+impl<W: Write> Tidy<W> {
+    pub fn new(write: W) -> Self {
+        Self {
+            write,
+            space: false,
+            line_start: false,
+            written: 0,
+        }
+    }
+
+    // bytes emitted so far, not counting a pending space
+    pub fn position(&self) -> usize {
+        self.written
+    }
+
+    pub fn finish(mut self) -> std::io::Result<()> {
+        if self.space {
+            self.write.write_all(b" ")?;
+        }
+        Ok(())
+    }
+
+    fn word(&mut self, word: &[u8]) -> std::io::Result<()> {
+        let first = match word.first() {
+            Some(first) => *first,
+            None => return Ok(()),
+        };
+        // "![" opens a Markdown image rather than ending a sentence
+        let punctuation = matches!(first, b',' | b'.' | b';' | b':' | b'!' | b'?' | b')')
+            && !word.starts_with(b"![");
+        if self.space && !punctuation {
+            self.write.write_all(b" ")?;
+            self.written += 1;
+        }
+        self.space = false;
+        self.line_start = word.ends_with(b"\n");
+        self.written += word.len();
+        self.write.write_all(word)
+    }
+}
+
+impl<W: Write> Write for Tidy<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut text = buf;
+        while let Some(pos) = memchr::memchr(b' ', text) {
+            self.word(&text[..pos])?;
+            if self.line_start {
+                self.write.write_all(b" ")?;
+                self.written += 1;
+            } else {
+                self.space = true;
+            }
+            text = &text[pos + 1..];
+        }
+        self.word(text)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.write.flush()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Newline {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+// rewrites every `\n` as the chosen line ending, leaving existing `\r\n` alone
+pub struct Newlines<W: Write> {
+    write: W,
+    newline: Newline,
+    cr: bool,
+}
+
+impl<W: Write> Newlines<W> {
+    pub fn new(write: W, newline: Newline) -> Self {
+        Self {
+            write,
+            newline,
+            cr: false,
+        }
+    }
+}
+
+impl<W: Write> Write for Newlines<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.newline == Newline::Lf {
+            return self.write.write(buf);
+        }
+
+        let mut text = buf;
+        while let Some(pos) = memchr::memchr(b'\n', text) {
+            let cr = if pos == 0 { self.cr } else { text[pos - 1] == b'\r' };
+            self.write.write_all(&text[..pos])?;
+            self.write.write_all(if cr { b"\n" } else { b"\r\n" })?;
+            self.cr = false;
+            text = &text[pos + 1..];
+        }
+        self.write.write_all(text)?;
+        if let Some(last) = text.last() {
+            self.cr = *last == b'\r';
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.write.flush()
+    }
+}
+
+pub const DESCRIPTION_LENGTH: usize = 140;
+
+// descriptions are cut on the stack, so their length is capped
+pub const MAX_DESCRIPTION_LENGTH: usize = 1000;
+
+// the limit plus the char that tells a cut text, at four bytes a char
+const TRUNCATE_BYTES: usize = (MAX_DESCRIPTION_LENGTH + 4) * 4;
+
+// where a description longer than its length is cut
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Cut {
+    Character,
+    Word,
+    // a whole sentence that fits, else a word
+    #[default]
+    Sentence,
+}
+
+// keeps the first characters written, counted as chars and not bytes so
+// that a cut never splits an accented letter
+pub struct Truncate {
+    buf: [u8; TRUNCATE_BYTES],
+    len: usize,
+    chars: usize,
+    // the cut position, "..." comes after it
+    length: usize,
+    cut: Cut,
+}
+
+impl Default for Truncate {
+    fn default() -> Self {
+        Self::new(DESCRIPTION_LENGTH, Cut::default())
+    }
+}
+
+fn char_start(c: u8) -> bool {
+    !(0x80..0xc0).contains(&c)
+}
+
+impl Truncate {
+    pub fn new(length: usize, cut: Cut) -> Self {
+        Self {
+            buf: [0; TRUNCATE_BYTES],
+            len: 0,
+            chars: 0,
+            length: length.min(MAX_DESCRIPTION_LENGTH),
+            cut,
+        }
+    }
+
+    fn limit(&self) -> usize {
+        self.length + 3
+    }
+
+    fn written(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    fn text(&self) -> &str {
+        match std::str::from_utf8(self.written()) {
+            Ok(text) => text,
+            Err(err) => std::str::from_utf8(&self.buf[..err.valid_up_to()]).unwrap(),
+        }
+    }
+
+    fn sentence_end(&self) -> Option<usize> {
+        let text = self.text();
+        let chars = |end: usize| text[..end].chars().count();
+
+        sentences(text)
+            .filter(|sentence| complete(sentence))
+            .map(|sentence| sentence.as_ptr() as usize - text.as_ptr() as usize + sentence.len())
+            .filter(|end| (self.length / 2..=self.limit()).contains(&chars(*end)))
+            .last()
+    }
+
+    // byte offset of the char at `length`
+    fn char_cut(&self) -> usize {
+        let text = self.text();
+        text.char_indices()
+            .nth(self.length)
+            .map_or(text.len(), |(i, _)| i)
+    }
+
+    // moves `cut` back to the last space before it, unless that drops more
+    // than half of the text
+    fn word_cut(&self, cut: usize) -> usize {
+        let text = &self.text()[..cut];
+        if self.written().get(cut).is_some_and(u8::is_ascii_whitespace) {
+            return cut;
+        }
+        text.rfind(char::is_whitespace)
+            .filter(|space| text[..*space].chars().count() >= self.length / 2)
+            .unwrap_or(cut)
+    }
+
+    // returns whether the cut was moved back to keep `atomic` ranges whole
+    pub fn finish(&self, atomic: &[Range<usize>], mut write: impl Write) -> std::io::Result<bool> {
+        if self.chars <= self.limit() {
+            write.write_all(self.written())?;
+            return Ok(false);
+        }
+
+        // a whole sentence that fits reads better than a cut one
+        if self.cut == Cut::Sentence {
+            if let Some(end) = self.sentence_end() {
+                write.write_all(&self.buf[..end])?;
+                return Ok(false);
+            }
+        }
+
+        let char_cut = self.char_cut();
+        let atomic = atomic
+            .iter()
+            .find(|range| range.start < char_cut && char_cut < range.end)
+            .map(|range| range.start);
+
+        let cut = match (atomic, self.cut) {
+            (Some(start), _) => start,
+            (None, Cut::Character) => char_cut,
+            (None, Cut::Word | Cut::Sentence) => self.word_cut(char_cut),
+        };
+
+        let text = &self.buf[..cut];
+        write.write_all(text.trim_ascii_end())?;
+        write.write_all(b"...")?;
+        Ok(atomic.is_some())
+    }
+}
+
+impl Write for Truncate {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // one char past the limit tells a full text from a cut one
+        for &c in buf {
+            if char_start(c) {
+                if self.chars > self.limit() {
+                    break;
+                }
+                self.chars += 1;
+            }
+            // only a run of stray continuation bytes gets here
+            if self.len == TRUNCATE_BYTES {
+                break;
+            }
+            self.buf[self.len] = c;
+            self.len += 1;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn escape_quotes() {
+        let mut output = Vec::new();
+
+        Escape(&mut output).write_all(br#"O "sertao" e "o mar""#).unwrap();
+
+        assert_eq!(output, br#"O \"sertao\" e \"o mar\""#);
+    }
+
+    #[test]
+    fn escape_specials() {
+        let mut output = Vec::new();
+
+        Escape(&mut output).write_all(b"C:\\sertao\n\tmar\x07").unwrap();
+
+        assert_eq!(output, br#"C:\\sertao\n\tmar\x07"#);
+    }
+
+    #[test]
+    fn tidy_spaces() {
+        let mut output = Vec::new();
+
+        let mut tidy = Tidy::new(&mut output);
+        tidy.write_all(b"Na figura  , o ").unwrap();
+        tidy.write_all(b" sertao (SANTOS, 2004) .").unwrap();
+        tidy.write_all(b" Fim ").unwrap();
+        tidy.finish().unwrap();
+
+        assert_eq!(output, b"Na figura, o sertao (SANTOS, 2004). Fim ");
+
+        let mut output = Vec::new();
+
+        let mut tidy = Tidy::new(&mut output);
+        tidy.write_all(b"Diz:\n\n    o  sertao\n  ").unwrap();
+        tidy.write_all(b" vai").unwrap();
+        tidy.finish().unwrap();
+
+        assert_eq!(output, b"Diz:\n\n    o sertao\n   vai");
+    }
+
+    #[test]
+    fn crlf_newlines() {
+        let mut output = Vec::new();
+
+        let mut newlines = Newlines::new(&mut output, Newline::Crlf);
+        newlines.write_all(b"---\ntitle: x\n").unwrap();
+        newlines.write_all(b"---\r").unwrap();
+        newlines.write_all(b"\nfim\r\n").unwrap();
+
+        assert_eq!(output, b"---\r\ntitle: x\r\n---\r\nfim\r\n");
+
+        let mut output = Vec::new();
+        Newlines::new(&mut output, Newline::Lf)
+            .write_all(b"a\nb\n")
+            .unwrap();
+
+        assert_eq!(output, b"a\nb\n");
+    }
+
+    #[test]
+    fn short_description() {
+        let mut truncate = Truncate::default();
+        truncate.write_all(b"curto").unwrap();
+
+        let mut output = Vec::new();
+        assert!(!truncate.finish(&[], &mut output).unwrap());
+
+        assert_eq!(output, b"curto");
+    }
+
+    #[test]
+    fn long_description() {
+        let mut truncate = Truncate::default();
+        for _ in 0..20 {
+            truncate.write_all(b"0123456789").unwrap();
+        }
+
+        let mut output = Vec::new();
+        assert!(!truncate.finish(&[], &mut output).unwrap());
+
+        assert_eq!(output.len(), 143);
+        assert!(output.ends_with(b"9..."));
+    }
+
+    #[test]
+    fn sentence_boundary() {
+        let mut truncate = Truncate::default();
+        for _ in 0..10 {
+            truncate.write_all(b"0123456789").unwrap();
+        }
+        truncate
+            .write_all(b" Fim da frase. E outra frase bem longa que passa do limite")
+            .unwrap();
+
+        let mut output = Vec::new();
+        assert!(!truncate.finish(&[], &mut output).unwrap());
+
+        assert_eq!(output.len(), 114);
+        assert!(output.ends_with(b"9 Fim da frase."));
+    }
+
+    #[test]
+    fn cut_before_citation() {
+        let mut truncate = Truncate::default();
+        for _ in 0..13 {
+            truncate.write_all(b"0123456789").unwrap();
+        }
+        truncate.write_all(b" (SANTOS, 2004) e mais").unwrap();
+
+        let mut output = Vec::new();
+        assert!(truncate.finish(&[20..30, 131..145], &mut output).unwrap());
+
+        assert_eq!(output.len(), 133);
+        assert!(output.ends_with(b"9..."));
+    }
+
+    #[test]
+    fn multibyte_description() {
+        let mut truncate = Truncate::new(10, Cut::Character);
+        truncate.write_all("ação".repeat(5).as_bytes()).unwrap();
+
+        let mut output = Vec::new();
+        truncate.finish(&[], &mut output).unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), "açãoaçãoaç...");
+    }
+
+    #[test]
+    fn word_cut() {
+        let text = "O sertão é, antes de tudo, um forte";
+
+        let mut output = Vec::new();
+        let mut truncate = Truncate::new(20, Cut::Word);
+        truncate.write_all(text.as_bytes()).unwrap();
+        truncate.finish(&[], &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "O sertão é, antes de...");
+
+        let mut output = Vec::new();
+        let mut truncate = Truncate::new(19, Cut::Character);
+        truncate.write_all(text.as_bytes()).unwrap();
+        truncate.finish(&[], &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "O sertão é, antes d...");
+
+        let mut output = Vec::new();
+        let mut truncate = Truncate::new(19, Cut::Word);
+        truncate.write_all(text.as_bytes()).unwrap();
+        truncate.finish(&[], &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "O sertão é, antes...");
+    }
+    #[test]
+    fn capped_length() {
+        let text = "sertão ".repeat(MAX_DESCRIPTION_LENGTH);
+
+        let mut output = Vec::new();
+        let mut truncate = Truncate::new(usize::MAX, Cut::Character);
+        truncate.write_all(text.as_bytes()).unwrap();
+        truncate.finish(&[], &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.chars().count(), MAX_DESCRIPTION_LENGTH + 3);
+        assert!(output.ends_with("..."));
+    }
+}